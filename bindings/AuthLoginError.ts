@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { GeneralError } from "./GeneralError";
 
-export type AuthLoginError = { is: "General", data: GeneralError } | { is: "InvalidCredentials" };
\ No newline at end of file
+export type AuthLoginError = { is: "General", data: GeneralError } | { is: "InvalidCredentials" } | { is: "InvalidChallenge" } | { is: "InvalidCode" } | { is: "Locked", data: { retry_after: bigint, } };
\ No newline at end of file
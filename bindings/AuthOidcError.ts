@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GeneralError } from "./GeneralError";
+
+export type AuthOidcError = { is: "General", data: GeneralError } | { is: "Disabled" } | { is: "InvalidState" } | { is: "Provider" } | { is: "MissingEmail" } | { is: "EmailTaken" } | { is: "NameUnavailable" };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GeneralError } from "./GeneralError";
+
+export type AuthRefreshError = { is: "General", data: GeneralError } | { is: "InvalidToken" } | { is: "Revoked" } | { is: "Reused" };
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface AuthSession { id: string, device: string, ip: string, created: bigint, last_seen: bigint, current: boolean, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GeneralError } from "./GeneralError";
+
+export type ChangePasswordError = { is: "General", data: GeneralError } | { is: "InvalidCredentials" } | { is: "TooShortPassword" } | { is: "TooLongPassword" } | { is: "BreachedPassword" };
\ No newline at end of file
@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { ChannelType } from "./ChannelType";
 
-export interface CreateChannelBody { type: ChannelType, description: string, title: string, default_role: string, labels: Array<string>, }
\ No newline at end of file
+export interface CreateChannelBody { type: ChannelType, description: string, title: string, default_role: string, labels: Array<string>, require_verified: boolean, hide_presence: boolean, }
\ No newline at end of file
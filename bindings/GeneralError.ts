@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type GeneralError = { is: "Internal" } | { is: "Unauthorized" };
\ No newline at end of file
+export type GeneralError = { is: "Internal" } | { is: "Unauthorized" } | { is: "Unverified" } | { is: "Forbidden" } | { is: "RateLimited" } | { is: "CrossOrigin" };
\ No newline at end of file
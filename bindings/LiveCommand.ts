@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type LiveCommand = { is: "Subscribe", data: { channel_id: string, last_seq: bigint | null, epoch: string | null, } } | { is: "Unsubscribe", data: { channel_id: string, } } | { is: "Signal", data: { channel_id: string, data: string, } } | { is: "CreateBlock", data: { content: string, } } | { is: "ChangeBlock", data: { id: string, content: string, } } | { is: "PinBlock", data: { channel_id: string, block_id: string | null, } } | { is: "GetBlock", data: { id: string, } } | { is: "GetBlocks", data: { channel_id: string, limit: bigint | null, offset: bigint | null, } };
\ No newline at end of file
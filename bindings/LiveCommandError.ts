@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GeneralError } from "./GeneralError";
+import type { RoleError } from "./RoleError";
+
+export type LiveCommandError = { is: "General", data: GeneralError } | { is: "Role", data: RoleError } | { is: "InvalidMessage", data: string } | { is: "NotSubscribed" } | { is: "SignalTooLarge", data: { max: number, } } | { is: "SignalRateLimited" };
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GeneralError } from "./GeneralError";
+import type { RoleError } from "./RoleError";
+
+export type LiveEventsError = { is: "General", data: GeneralError } | { is: "Role", data: RoleError } | { is: "StreamNotFound" };
\ No newline at end of file
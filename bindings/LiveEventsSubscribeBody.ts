@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface LiveEventsSubscribeBody { channel_id: string, last_seq: bigint | null, epoch: string | null, }
\ No newline at end of file
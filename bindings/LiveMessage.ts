@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type LiveMessage = { is: "BlockConnected", data: { id: string, } } | { is: "BlockDisconnected", data: { id: string, } } | { is: "LabelsChanged" } | { is: "DescriptionChanged" } | { is: "BlockPinned", data: { id: string | null, } } | { is: "BlockChanged", data: { id: string, } };
\ No newline at end of file
+export type LiveMessage = { is: "BlockConnected", data: { id: string, } } | { is: "BlockDisconnected", data: { id: string, } } | { is: "LabelsChanged" } | { is: "DescriptionChanged" } | { is: "BlockPinned", data: { id: string | null, } } | { is: "BlockChanged", data: { id: string, } } | { is: "UserJoined", data: { name: string, } } | { is: "UserLeft", data: { name: string, } } | { is: "Signal", data: { from: string, data: string, } };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Block } from "./Block";
+
+export type LiveReplyData = { is: "BlockCreated", data: { id: string, } } | { is: "Block", data: Block } | { is: "Blocks", data: Array<Block> } | { is: "Subscribed", data: { channel_id: string, seq: bigint, epoch: string, resync_required: boolean, } } | { is: "Subscriptions", data: Array<string> };
\ No newline at end of file
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LiveCommandError } from "./LiveCommandError";
+import type { LiveMessage } from "./LiveMessage";
+import type { LiveReplyData } from "./LiveReplyData";
+import type { UnsubscribeReason } from "./UnsubscribeReason";
+
+export type LiveServerMessage = { is: "Event", data: { channel_id: string, seq: bigint, message: LiveMessage, } } | { is: "Ready", data: { epoch: string, } } | { is: "ResyncRequired", data: { channel_id: string, seq: bigint, } } | { is: "Ack", data: { id: bigint, } } | { is: "Reply", data: { id: bigint, data: LiveReplyData, } } | { is: "Unsubscribed", data: { channel_id: string, reason: UnsubscribeReason, } } | { is: "SubscribeFailed", data: { channel_id: string, error: LiveCommandError, } } | { is: "Error", data: { id: bigint | null, error: LiveCommandError, } };
\ No newline at end of file
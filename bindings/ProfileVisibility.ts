@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Visibility } from "./Visibility";
+
+export interface ProfileVisibility { display_name: Visibility, bio: Visibility, avatar: Visibility, links: Visibility, pinned_block: Visibility, }
\ No newline at end of file
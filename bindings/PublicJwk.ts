@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface PublicJwk { kty: string, kid: string, alg: string, use: string, n?: string, e?: string, crv?: string, x?: string, }
\ No newline at end of file
@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export interface RolePermissions { change_roles: Array<string>, view_blocks: Array<string>, connect_blocks: Array<string>, disconnect_blocks: Array<string>, pin_block: Array<string>, change_default_role: Array<string>, change_description: Array<string>, pin_roles: Array<string>, set_labels: boolean, live: Array<string>, }
\ No newline at end of file
+export interface RolePermissions { change_roles: Array<string>, view_blocks: Array<string>, connect_blocks: Array<string>, disconnect_blocks: Array<string>, pin_block: Array<string>, change_default_role: Array<string>, change_description: Array<string>, pin_roles: Array<string>, set_labels: boolean, live: Array<string>, signal: Array<string>, }
\ No newline at end of file
@@ -1,3 +1,6 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ProfileAvatar } from "./ProfileAvatar";
+import type { ProfileVisibility } from "./ProfileVisibility";
+import type { UserCounts } from "./UserCounts";
 
-export interface User { name: string, }
\ No newline at end of file
+export interface User { name: string, display_name: string | null, bio: string | null, avatar: ProfileAvatar | null, links: Array<string> | null, pinned_block: string | null, visibility: ProfileVisibility | null, counts: UserCounts, }
\ No newline at end of file
@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type UserActivity = { type: "ChannelCreated", data: { id: string, } } | { type: "BlockCreated", data: { id: string, } } | { type: "Joined" } | { type: "RoleCreated", data: { id: string, } } | { type: "ChannelBlockPinned", data: { block_id: string | null, id: string, } } | { type: "ChannelDescriptionChanged", data: { id: string, } } | { type: "BlockConnectedToChannel", data: { block_id: string, id: string, } } | { type: "BlockDisconnectedFromChannel", data: { block_id: string, id: string, } };
\ No newline at end of file
+export type UserActivity = { type: "ChannelCreated", data: { id: string, } } | { type: "BlockCreated", data: { id: string, } } | { type: "Joined" } | { type: "RoleCreated", data: { id: string, } } | { type: "ChannelBlockPinned", data: { block_id: string | null, id: string, } } | { type: "ChannelDescriptionChanged", data: { id: string, } } | { type: "BlockConnectedToChannel", data: { block_id: string, id: string, } } | { type: "BlockDisconnectedFromChannel", data: { block_id: string, id: string, } } | { type: "LoginLocked", data: { until: bigint, ip: string | null, } } | { type: "LoginUnlocked", data: { by: string, } } | { type: "LoginIpUnlocked", data: { ip: string, } } | { type: "Renamed", data: { from: string, } };
\ No newline at end of file
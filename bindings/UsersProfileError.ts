@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GeneralError } from "./GeneralError";
+
+export type UsersProfileError = { is: "General", data: GeneralError } | { is: "BadDisplayNameLength" } | { is: "BioTooLong" } | { is: "TooManyLinks" } | { is: "InvalidLink", data: string } | { is: "InvalidBlock", data: string } | { is: "InvalidAvatarImage" } | { is: "UnsupportedImage" } | { is: "ImageTooLarge" };
\ No newline at end of file
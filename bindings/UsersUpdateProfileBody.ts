@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ProfileAvatar } from "./ProfileAvatar";
+import type { ProfileVisibility } from "./ProfileVisibility";
+
+export interface UsersUpdateProfileBody { display_name: string | null, bio: string, avatar: ProfileAvatar | null, links: Array<string>, pinned_block: string | null, visibility: ProfileVisibility, }
\ No newline at end of file
@@ -40,6 +40,23 @@ pub enum Activity {
     BlockCreated {
         id: String,
         by: String,
+    },
+    LoginLocked {
+        name: String,
+        until: i64,
+        ip: Option<String>,
+    },
+    LoginUnlocked {
+        name: String,
+        by: String,
+    },
+    LoginIpUnlocked {
+        ip: String,
+        by: String,
+    },
+    Renamed {
+        name: String,
+        from: String,
     }
 }
 
@@ -73,6 +90,22 @@ impl Activity {
                     vec![DbActivity::Channel { activity: ChannelActivity::BlockPinned { id: block_id, by } }]
                 )
             ],
+            Self::LoginLocked { name, until, ip } => vec![(
+                ActivityTablesOf::User {name},
+                vec![DbActivity::User { activity: UserActivity::LoginLocked { until, ip } }]
+            )],
+            Self::LoginUnlocked { name, by } => vec![(
+                ActivityTablesOf::User {name},
+                vec![DbActivity::User { activity: UserActivity::LoginUnlocked { by } }]
+            )],
+            Self::LoginIpUnlocked { ip, by } => vec![( // ip isn't anyone's, so it goes to the admin
+                ActivityTablesOf::User {name: by},
+                vec![DbActivity::User { activity: UserActivity::LoginIpUnlocked { ip } }]
+            )],
+            Self::Renamed { name, from } => vec![(
+                ActivityTablesOf::User {name},
+                vec![DbActivity::User { activity: UserActivity::Renamed { from } }]
+            )],
             _ => vec![]
         }
     }
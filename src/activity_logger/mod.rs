@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use crate::{db_pool::{DbPool, self}, logger::Logger};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, Mutex, Notify};
 use activity::ActivityTablesOf;
 pub use activity::Activity;
 
@@ -23,7 +23,8 @@ pub struct ActivityLogger {
     db_pool: Arc<DbPool>,
     sender: mpsc::UnboundedSender<Message>,
     receiver: Mutex<mpsc::UnboundedReceiver<Message>>,
-    logger: Arc<Logger>
+    logger: Arc<Logger>,
+    shutdown: Notify
 }
 
 impl ActivityLogger {
@@ -33,7 +34,8 @@ impl ActivityLogger {
             db_pool,
             sender,
             receiver: Mutex::new(receiver),
-            logger
+            logger,
+            shutdown: Notify::new()
         }
     }
 
@@ -43,6 +45,11 @@ impl ActivityLogger {
         }
     }
 
+    /// makes `run` stop accepting new activities, store everything that is already queued and return
+    pub fn shutdown(&self){
+        self.shutdown.notify_one();
+    }
+
     async fn resolve_activity_tables_of(&self, activity_tables_of: &ActivityTablesOf) -> Result<Vec<String>, Error> {
         match activity_tables_of {
             ActivityTablesOf::User { name } => {
@@ -56,22 +63,37 @@ impl ActivityLogger {
         }
     }
 
+    async fn store(&self, activity: Activity){
+        for (activity_tables_of, activities) in activity.process_into() {
+            match self.resolve_activity_tables_of(&activity_tables_of).await {
+                Err(e) => self.logger.log(e.to_string()),
+                Ok(activity_tables_ids) => {
+                    for activity_table_id in activity_tables_ids {
+                        if let Err(e) = self.db_pool.push_to_activity_table(&activity_table_id, &activities).await {
+                            self.logger.log(e.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     pub async fn run(&self){
         let mut receiver = self.receiver.lock().await;
-        while let Some(activity) = receiver.recv().await {
-            for (activity_tables_of, activities) in activity.process_into() {
-                match self.resolve_activity_tables_of(&activity_tables_of).await {
-                    Err(e) => self.logger.log(e.to_string()),
-                    Ok(activity_tables_ids) => {
-                        for activity_table_id in activity_tables_ids {
-                            if let Err(e) = self.db_pool.push_to_activity_table(&activity_table_id, &activities).await {
-                                self.logger.log(e.to_string());
-                            }
-                        }
+        loop {
+            tokio::select! {
+                activity = receiver.recv() => match activity {
+                    Some(activity) => self.store(activity).await,
+                    None => break
+                },
+                _ = self.shutdown.notified() => {
+                    receiver.close();
+                    while let Some(activity) = receiver.recv().await {
+                        self.store(activity).await;
                     }
+                    break;
                 }
             }
         }
     }
 }
-
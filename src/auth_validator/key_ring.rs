@@ -0,0 +1,318 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, EncodingKey, DecodingKey, Validation, errors::ErrorKind};
+use ring::signature::{KeyPair, RsaKeyPair, Ed25519KeyPair};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use ts_rs::TS;
+use std::collections::HashSet;
+
+/// kid of the key made from a single secret, tokens signed before key rings existed have no kid at all
+const LEGACY_KID: &str = "default";
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeyRingError {
+    #[error("failed to read key ring: {0}")]
+    Io(std::io::Error),
+    #[error("failed to parse key ring: {0}")]
+    Json(serde_json::Error),
+    #[error("failed to parse pem: {0}")]
+    Pem(pem::PemError),
+    #[error("invalid key: {0}")]
+    Jwt(jsonwebtoken::errors::Error),
+    #[error("key {0} was rejected: {1}")]
+    Rejected(String, String),
+    #[error("key {0} needs `secret` for hmac or `private_key` for asymmetric algorithms")]
+    MissingKeyMaterial(String),
+    #[error("key {0} uses unsupported algorithm {1:?}")]
+    UnsupportedAlgorithm(String, Algorithm),
+    #[error("active key {0} is not in the ring")]
+    UnknownActive(String),
+    #[error("active key {0} is already retired and no successor is active yet")]
+    RetiredActive(String),
+    #[error("kid {0} is used more than once")]
+    DuplicateKid(String),
+}
+impl From<std::io::Error> for KeyRingError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<serde_json::Error> for KeyRingError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+impl From<pem::PemError> for KeyRingError {
+    fn from(value: pem::PemError) -> Self {
+        Self::Pem(value)
+    }
+}
+impl From<jsonwebtoken::errors::Error> for KeyRingError {
+    fn from(value: jsonwebtoken::errors::Error) -> Self {
+        Self::Jwt(value)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct KeyConfig {
+    pub kid: String,
+    /// HS512, RS256 or EdDSA
+    pub alg: Algorithm,
+    pub secret: Option<String>,
+    /// path to pem encoded private key (pkcs8, or pkcs1 for rsa)
+    pub private_key: Option<String>,
+    /// unix timestamp after which tokens signed with this key are no longer accepted
+    pub retires: Option<i64>,
+    /// unix timestamp from which new tokens are signed with this key, it's accepted and published right away,
+    /// so that it can be added ahead of `retires` of the active one
+    pub activates: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct RingConfig {
+    /// kid of the key new tokens are signed with until a key with later `activates` takes over
+    pub active: String,
+    pub keys: Vec<KeyConfig>,
+}
+
+/// contents of `KEY_RING_FILE`
+#[derive(Deserialize)]
+pub struct RingsConfig {
+    pub access: RingConfig,
+    pub key: RingConfig,
+}
+
+/// public part of asymmetric key, as published in jwks
+#[derive(Serialize, Clone, TS)]
+#[ts(export)]
+pub struct PublicJwk {
+    pub kty: String,
+    pub kid: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub _use: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct Jwks {
+    pub keys: Vec<PublicJwk>,
+}
+
+struct RingKey {
+    kid: String,
+    alg: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    /// `None` for hmac keys, those are never published
+    public: Option<PublicJwk>,
+    retires: Option<i64>,
+    activates: Option<i64>,
+}
+
+impl RingKey {
+    fn is_retired(&self, now: i64) -> bool {
+        match self.retires {
+            Some(retires) => retires <= now,
+            None => false
+        }
+    }
+
+    fn from_config(config: &KeyConfig) -> Result<Self, KeyRingError> {
+        match config.alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                let secret = config.secret.as_ref().ok_or(KeyRingError::MissingKeyMaterial(config.kid.clone()))?;
+                Ok(Self {
+                    kid: config.kid.clone(),
+                    alg: config.alg,
+                    encoding: EncodingKey::from_secret(secret.as_bytes()),
+                    decoding: DecodingKey::from_secret(secret.as_bytes()),
+                    public: None,
+                    retires: config.retires,
+                    activates: config.activates
+                })
+            },
+            Algorithm::RS256 | Algorithm::EdDSA => {
+                let path = config.private_key.as_ref().ok_or(KeyRingError::MissingKeyMaterial(config.kid.clone()))?;
+                let bytes = std::fs::read(path)?;
+                let pem = pem::parse(&bytes)?;
+                let rejected = |error: ring::error::KeyRejected| KeyRingError::Rejected(config.kid.clone(), error.to_string());
+                let (encoding, decoding, public) = if config.alg == Algorithm::RS256 {
+                    let pair = match pem.tag.as_str() {
+                        "RSA PRIVATE KEY" => RsaKeyPair::from_der(&pem.contents),
+                        _ => RsaKeyPair::from_pkcs8(&pem.contents)
+                    }.map_err(rejected)?;
+                    let n = URL_SAFE_NO_PAD.encode(pair.public_key().modulus().big_endian_without_leading_zero());
+                    let e = URL_SAFE_NO_PAD.encode(pair.public_key().exponent().big_endian_without_leading_zero());
+                    (EncodingKey::from_rsa_pem(&bytes)?, DecodingKey::from_rsa_components(&n, &e)?, PublicJwk {
+                        kty: "RSA".to_string(), kid: config.kid.clone(), alg: "RS256".to_string(), _use: "sig".to_string(),
+                        n: Some(n), e: Some(e), crv: None, x: None
+                    })
+                } else {
+                    let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&pem.contents).map_err(rejected)?;
+                    let x = URL_SAFE_NO_PAD.encode(pair.public_key().as_ref());
+                    (EncodingKey::from_ed_pem(&bytes)?, DecodingKey::from_ed_components(&x)?, PublicJwk {
+                        kty: "OKP".to_string(), kid: config.kid.clone(), alg: "EdDSA".to_string(), _use: "sig".to_string(),
+                        n: None, e: None, crv: Some("Ed25519".to_string()), x: Some(x)
+                    })
+                };
+                Ok(Self { kid: config.kid.clone(), alg: config.alg, encoding, decoding, public: Some(public), retires: config.retires, activates: config.activates })
+            },
+            alg => Err(KeyRingError::UnsupportedAlgorithm(config.kid.clone(), alg))
+        }
+    }
+}
+
+/// keys one kind of token is verified with, only the newest active one is used for signing
+pub struct KeyRing {
+    keys: Vec<RingKey>,
+    active: usize,
+}
+
+impl KeyRing {
+    /// single HS512 key, how keys were configured before key rings
+    pub fn from_secret(secret: &str) -> Self {
+        Self {
+            keys: vec![RingKey {
+                kid: LEGACY_KID.to_string(),
+                alg: Algorithm::HS512,
+                encoding: EncodingKey::from_secret(secret.as_bytes()),
+                decoding: DecodingKey::from_secret(secret.as_bytes()),
+                public: None,
+                retires: None,
+                activates: None
+            }],
+            active: 0
+        }
+    }
+
+    pub fn from_config(config: &RingConfig) -> Result<Self, KeyRingError> {
+        let keys = config.keys.iter().map(RingKey::from_config).collect::<Result<Vec<_>, _>>()?;
+        let active = keys.iter().position(|key| key.kid == config.active).ok_or(KeyRingError::UnknownActive(config.active.clone()))?;
+        let ring = Self { keys, active };
+        if ring.signing_key(chrono::Utc::now().timestamp()).is_none() {
+            return Err(KeyRingError::RetiredActive(config.active.clone()));
+        }
+        Ok(ring)
+    }
+
+    /// newest of the configured active key and keys whose `activates` has passed, that isn't retired yet
+    fn signing_key(&self, now: i64) -> Option<&RingKey> {
+        self.keys.iter().enumerate()
+        .filter(|(index, key)| *index == self.active || key.activates.is_some_and(|activates| activates <= now))
+        .filter(|(_, key)| !key.is_retired(now))
+        .max_by_key(|(index, key)| (key.activates.unwrap_or(i64::MIN), *index == self.active))
+        .map(|(_, key)| key)
+    }
+
+    /// fails once every active key retires, its tokens wouldn't be accepted anyway
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let key = self.signing_key(chrono::Utc::now().timestamp()).ok_or(jsonwebtoken::errors::Error::from(ErrorKind::InvalidKeyFormat))?;
+        let mut header = jsonwebtoken::Header::new(key.alg);
+        header.kid = Some(key.kid.clone());
+        jsonwebtoken::encode(&header, claims, &key.encoding)
+    }
+
+    /// tokens without kid can only come from before key rings, so they are tried against hmac keys
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let now = chrono::Utc::now().timestamp();
+        let mut error = jsonwebtoken::errors::Error::from(ErrorKind::InvalidSignature);
+        for key in self.keys.iter().filter(|key| !key.is_retired(now)) {
+            let candidate = match &header.kid {
+                Some(kid) => kid == &key.kid,
+                None => key.public.is_none()
+            };
+            if !candidate {
+                continue;
+            }
+            match jsonwebtoken::decode::<T>(token, &key.decoding, &Validation::new(key.alg)) {
+                Ok(data) => return Ok(data.claims),
+                Err(decode_error) => error = decode_error
+            }
+        }
+        Err(error)
+    }
+
+    fn kids(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|key| key.kid.as_str())
+    }
+
+    /// public keys that are still accepted
+    pub fn public_keys(&self) -> impl Iterator<Item = &PublicJwk> {
+        let now = chrono::Utc::now().timestamp();
+        self.keys.iter()
+        .filter(move |key| !key.is_retired(now))
+        .filter_map(|key| key.public.as_ref())
+    }
+}
+
+pub struct KeyRings {
+    pub access: KeyRing,
+    pub key: KeyRing,
+}
+
+impl KeyRings {
+    pub fn load(path: &str) -> Result<Self, KeyRingError> {
+        Self::from_config(&serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// kids are unique across both rings, so that a published key is never ambiguous
+    pub fn from_config(config: &RingsConfig) -> Result<Self, KeyRingError> {
+        let rings = Self {
+            access: KeyRing::from_config(&config.access)?,
+            key: KeyRing::from_config(&config.key)?
+        };
+        let mut kids = HashSet::new();
+        if let Some(kid) = rings.access.kids().chain(rings.key.kids()).find(|kid| !kids.insert(*kid)) {
+            return Err(KeyRingError::DuplicateKid(kid.to_string()));
+        }
+        Ok(rings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hmac_key(kid: &str, activates: Option<i64>, retires: Option<i64>) -> KeyConfig {
+        KeyConfig { kid: kid.to_string(), alg: Algorithm::HS512, secret: Some(kid.to_string()), private_key: None, retires, activates }
+    }
+
+    fn ring(keys: Vec<KeyConfig>) -> RingConfig {
+        RingConfig { active: "old".to_string(), keys }
+    }
+
+    #[test]
+    fn successor_signs_once_it_activates() {
+        let now = chrono::Utc::now().timestamp();
+        let pending = KeyRing::from_config(&ring(vec![hmac_key("old", None, None), hmac_key("new", Some(now + 60), None)])).unwrap();
+        assert_eq!(pending.signing_key(now).unwrap().kid, "old");
+        assert_eq!(pending.signing_key(now + 60).unwrap().kid, "new");
+    }
+
+    #[test]
+    fn retired_active_key_is_replaced_by_successor() {
+        let now = chrono::Utc::now().timestamp();
+        let rotated = KeyRing::from_config(&ring(vec![hmac_key("old", None, Some(now + 60)), hmac_key("new", Some(now - 60), None)])).unwrap();
+        assert_eq!(rotated.signing_key(now + 60).unwrap().kid, "new");
+        let alone = KeyRing::from_config(&ring(vec![hmac_key("old", None, Some(now + 60))])).unwrap();
+        assert!(alone.signing_key(now + 60).is_none());
+    }
+
+    #[test]
+    fn kids_are_unique_across_rings() {
+        let config = RingsConfig {
+            access: ring(vec![hmac_key("old", None, None)]),
+            key: ring(vec![hmac_key("old", None, None)])
+        };
+        assert!(matches!(KeyRings::from_config(&config), Err(KeyRingError::DuplicateKid(kid)) if kid == "old"));
+    }
+}
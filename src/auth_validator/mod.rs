@@ -1,6 +1,10 @@
 use jsonwebtoken::{Algorithm, EncodingKey, DecodingKey, Validation};
 use rand::Rng;
 use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+pub use key_ring::{KeyRing, KeyRings, Jwks};
+
+mod key_ring;
 
 #[derive(Clone)]
 pub enum Auth {
@@ -13,7 +17,10 @@ pub enum Auth {
 #[derive(Clone)]
 pub enum InvalidAuthData {
     Token (InvalidAuthTokenData),
-    MismatchedKeys
+    MismatchedKeys,
+    PersonalToken,
+    /// key of the tokens is revoked, expired or gone
+    RevokedKey
 }
 #[derive(Clone)]
 pub enum InvalidAuthTokenData {
@@ -33,13 +40,23 @@ impl Auth {
 
 #[derive(Clone)]
 pub struct AuthInfo {
-    pub name: String
+    pub name: String,
+    pub key: String
 }
 
-#[derive(Clone, Debug)]
 pub struct Keys {
-    pub access: String,
-    pub key: String
+    pub access: KeyRing,
+    pub key: KeyRing,
+    pub verification: String,
+    pub challenge: String
+}
+
+#[derive(Clone, Debug)]
+pub struct Lifetimes {
+    pub access: chrono::Duration,
+    pub key: chrono::Duration,
+    pub verification: chrono::Duration,
+    pub challenge: chrono::Duration
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,6 +69,33 @@ pub struct AccessClaims {
 #[derive(Serialize, Deserialize)]
 pub struct KeyClaims {
     pub key: String,
+    pub refresh: String,
+    pub exp: usize,
+}
+
+/// claims of the token that is sent (as a link) to confirm email
+#[derive(Serialize, Deserialize)]
+pub struct VerificationClaims {
+    pub name: String,
+    pub email: String,
+    pub exp: usize,
+}
+
+/// claims of the token that is given after password was accepted but second factor is still required
+#[derive(Serialize, Deserialize)]
+pub struct ChallengeClaims {
+    pub name: String,
+    /// challenge can be used only once
+    pub id: String,
+    pub exp: usize,
+}
+
+/// claims of the cookie that carries oidc login state while user is at identity provider
+#[derive(Serialize, Deserialize)]
+pub struct OidcStateClaims {
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
     pub exp: usize,
 }
 
@@ -74,37 +118,53 @@ impl From<jsonwebtoken::errors::Error> for InfoAsTokensError {
     }
 }
 
+/// random string used for keys and refresh values
+pub fn generate_secret() -> String {
+    let mut rng = rand::thread_rng();
+    (0..20).map(|_| rng.sample(rand::distributions::Alphanumeric) as char).collect()
+}
+
+/// secrets that are stored on server (e.g. reset tokens) are stored only as this hash
+pub fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+fn expiration(lifetime: chrono::Duration) -> Result<usize, InfoAsTokensError> {
+    Ok(chrono::Utc::now()
+    .checked_add_signed(lifetime).ok_or(InfoAsTokensError::Timestamp)?
+    .timestamp() as usize)
+}
+
 pub struct AuthValidator {
-    keys: Keys
+    keys: Keys,
+    lifetimes: Lifetimes
 }
 
 impl AuthValidator {
-    pub fn new(keys: &Keys) -> Self {
-        Self {keys: keys.clone()}
+    pub fn new(keys: Keys, lifetimes: &Lifetimes) -> Self {
+        Self {keys, lifetimes: lifetimes.clone()}
     }
 
-    pub fn info_as_tokens(&self, info: &AuthInfo) -> Result<Tokens, InfoAsTokensError> {
-        let exp = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::days(30)).ok_or(InfoAsTokensError::Timestamp)?
-        .timestamp() as usize;
-
-        let mut rng = rand::thread_rng();
-        let key: String = (0..20).map(|_| rng.sample(rand::distributions::Alphanumeric) as char).collect();
+    pub fn key_lifetime(&self) -> chrono::Duration {
+        self.lifetimes.key
+    }
 
+    /// access token lives for a short time, key token (which is also the refresh token) lives much longer
+    pub fn info_as_tokens(&self, info: &AuthInfo, refresh: &str) -> Result<Tokens, InfoAsTokensError> {
         let access_claims = AccessClaims {
-            exp,
-            key: key.clone(),
+            exp: expiration(self.lifetimes.access)?,
+            key: info.key.clone(),
             name: info.name.clone(),
         };
 
         let key_claims = KeyClaims {
-            exp,
-            key
+            exp: expiration(self.lifetimes.key)?,
+            key: info.key.clone(),
+            refresh: refresh.to_string()
         };
 
-        let header = jsonwebtoken::Header::new(Algorithm::HS512);
-        let access_token = jsonwebtoken::encode(&header, &access_claims, &EncodingKey::from_secret(self.keys.access.as_bytes()))?;
-        let key_token = jsonwebtoken::encode(&header, &key_claims, &EncodingKey::from_secret(self.keys.key.as_bytes()))?;
+        let access_token = self.keys.access.encode(&access_claims)?;
+        let key_token = self.keys.key.encode(&key_claims)?;
 
         Ok(Tokens {
             access: access_token,
@@ -112,33 +172,96 @@ impl AuthValidator {
         })
     }
 
-    pub fn tokens_as_auth(&self, tokens: &Tokens) -> Auth {
-        return Auth::Valid { info: AuthInfo { // to be removed!
-            name: "epicuser".to_string(),
-        }};
+    pub fn verification_token(&self, name: &str, email: &str) -> Result<String, InfoAsTokensError> {
+        let claims = VerificationClaims {
+            name: name.to_string(),
+            email: email.to_string(),
+            exp: expiration(self.lifetimes.verification)?
+        };
+        let header = jsonwebtoken::Header::new(Algorithm::HS512);
+        Ok(jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(self.keys.verification.as_bytes()))?)
+    }
 
-        let access_claims = match jsonwebtoken::decode::<AccessClaims>(
-            &tokens.access,
-            &DecodingKey::from_secret(self.keys.access.as_bytes()),
+    pub fn decode_verification_token(&self, token: &str) -> Result<VerificationClaims, jsonwebtoken::errors::Error> {
+        Ok(jsonwebtoken::decode::<VerificationClaims>(
+            token,
+            &DecodingKey::from_secret(self.keys.verification.as_bytes()),
             &Validation::new(Algorithm::HS512)
-        ) {
-            Ok(claims) => claims,
-            Err(error) => return Auth::Invalid(InvalidAuthData::Token(InvalidAuthTokenData::Access))
-        }.claims;
+        )?.claims)
+    }
+
+    pub fn challenge_token(&self, name: &str) -> Result<String, InfoAsTokensError> {
+        let claims = ChallengeClaims {
+            name: name.to_string(),
+            id: generate_secret(),
+            exp: expiration(self.lifetimes.challenge)?
+        };
+        let header = jsonwebtoken::Header::new(Algorithm::HS512);
+        Ok(jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(self.keys.challenge.as_bytes()))?)
+    }
 
-        let key_claims = match jsonwebtoken::decode::<KeyClaims>(
-            &tokens.key,
-            &DecodingKey::from_secret(self.keys.key.as_bytes()),
+    pub fn decode_challenge_token(&self, token: &str) -> Result<ChallengeClaims, jsonwebtoken::errors::Error> {
+        Ok(jsonwebtoken::decode::<ChallengeClaims>(
+            token,
+            &DecodingKey::from_secret(self.keys.challenge.as_bytes()),
             &Validation::new(Algorithm::HS512)
-        ) {
+        )?.claims)
+    }
+
+    /// signed with challenge key, it is just as short lived
+    pub fn oidc_state_token(&self, state: &str, nonce: &str, verifier: &str) -> Result<String, InfoAsTokensError> {
+        let claims = OidcStateClaims {
+            state: state.to_string(),
+            nonce: nonce.to_string(),
+            verifier: verifier.to_string(),
+            exp: expiration(self.lifetimes.challenge)?
+        };
+        let header = jsonwebtoken::Header::new(Algorithm::HS512);
+        Ok(jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(self.keys.challenge.as_bytes()))?)
+    }
+
+    /// fails also when `state` that came back from provider isn't the one the token was issued for
+    pub fn decode_oidc_state_token(&self, token: &str, state: &str) -> Result<OidcStateClaims, jsonwebtoken::errors::Error> {
+        let claims = jsonwebtoken::decode::<OidcStateClaims>(
+            token,
+            &DecodingKey::from_secret(self.keys.challenge.as_bytes()),
+            &Validation::new(Algorithm::HS512)
+        )?.claims;
+        if claims.state != state {
+            return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+        }
+        Ok(claims)
+    }
+
+    pub fn decode_access_token(&self, token: &str) -> Result<AccessClaims, jsonwebtoken::errors::Error> {
+        self.keys.access.decode::<AccessClaims>(token)
+    }
+
+    pub fn decode_key_token(&self, token: &str) -> Result<KeyClaims, jsonwebtoken::errors::Error> {
+        self.keys.key.decode::<KeyClaims>(token)
+    }
+
+    /// public keys of both rings, so that other services can verify chane tokens
+    pub fn jwks(&self) -> Jwks {
+        Jwks { keys: self.keys.access.public_keys().chain(self.keys.key.public_keys()).cloned().collect() }
+    }
+
+    /// checks only signatures and claims, the key record is checked by `SessionPool`
+    pub fn tokens_as_auth(&self, tokens: &Tokens) -> Auth {
+        let access_claims = match self.decode_access_token(&tokens.access) {
             Ok(claims) => claims,
-            Err(error) => return Auth::Invalid(InvalidAuthData::Token(InvalidAuthTokenData::Key))
-        }.claims;
+            Err(_) => return Auth::Invalid(InvalidAuthData::Token(InvalidAuthTokenData::Access))
+        };
+
+        let key_claims = match self.decode_key_token(&tokens.key) {
+            Ok(claims) => claims,
+            Err(_) => return Auth::Invalid(InvalidAuthData::Token(InvalidAuthTokenData::Key))
+        };
 
         if key_claims.key != access_claims.key {
             return Auth::Invalid(InvalidAuthData::MismatchedKeys);
         }
 
-        Auth::Valid {info: AuthInfo {name: access_claims.name}}
+        Auth::Valid {info: AuthInfo {name: access_claims.name, key: access_claims.key}}
     }
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+use serde::{Serialize, Deserialize};
+use futures::StreamExt;
+use ts_rs::TS;
+use super::{DbPool, Error, utils::as_obj_id};
+use mongodb::{bson::{doc, oid::ObjectId}, ClientSession};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, TS)]
+#[ts(export)]
+pub enum TokenScope {
+    #[serde(rename = "read_blocks")]
+    ReadBlocks,
+    #[serde(rename = "write_blocks")]
+    WriteBlocks,
+    #[serde(rename = "manage_roles")]
+    ManageRoles,
+    #[serde(rename = "live")]
+    Live,
+}
+
+/// personal access token, only hash of the token itself is stored
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    pub owner: String,
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires: Option<i64>,
+    pub created: i64,
+}
+
+impl DbPool {
+    pub async fn create_access_token(&self, name: &str, owner: &str, token_hash: &str, scopes: &[TokenScope], expires: Option<i64>) -> Result<String, Error> {
+        let document = AccessToken {
+            id: None,
+            name: name.to_string(),
+            owner: owner.to_string(),
+            token_hash: token_hash.to_string(),
+            scopes: scopes.to_owned(),
+            expires,
+            created: chrono::Utc::now().timestamp()
+        };
+        let result = self.access_tokens.insert_one(document, None).await?;
+        Ok(result.inserted_id.as_object_id().ok_or(Error::NotFound)?.to_string())
+    }
+
+    /// only tokens that haven't expired yet
+    pub async fn get_access_token_by_hash(&self, token_hash: &str) -> Result<AccessToken, Error> {
+        match self.access_tokens.find_one(doc! {
+            "token_hash": token_hash,
+            "$or": [{"expires": null}, {"expires": {"$gt": chrono::Utc::now().timestamp()}}]
+        }, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub async fn get_user_access_tokens(&self, owner: &str) -> Result<(Vec<AccessToken>, Vec<mongodb::error::Error>), Error> {
+        let mut cursor = self.access_tokens.find(doc! {"owner": owner}, None).await?;
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        while let Some(token_result) = cursor.next().await {
+            match token_result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error)
+            }
+        }
+        Ok((tokens, errors))
+    }
+
+    pub async fn delete_access_token(&self, owner: &str, id: &str) -> Result<(), Error> {
+        let result = self.access_tokens.delete_one(doc! {"_id": as_obj_id(id)?, "owner": owner}, None).await?;
+        if result.deleted_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(super) async fn rename_access_token_owner(&self, owner: &str, new_owner: &str, session: &mut ClientSession) -> Result<(), Error> {
+        self.access_tokens.update_many_with_session(doc! {"owner": owner}, doc! {"$set": {"owner": new_owner}}, None, session).await?;
+        Ok(())
+    }
+}
\ No newline at end of file
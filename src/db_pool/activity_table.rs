@@ -1,5 +1,5 @@
 use serde::{Serialize, Deserialize};
-use mongodb::{bson::{doc, oid::ObjectId}};
+use mongodb::{bson::{doc, oid::ObjectId}, options::UpdateOptions, ClientSession};
 use super::{DbPool, Error, utils::as_obj_id};
 use ts_rs::TS;
 
@@ -30,6 +30,12 @@ pub enum UserActivity {
     ChannelDescriptionChanged {id: String},
     BlockConnectedToChannel {block_id: String, id: String},
     BlockDisconnectedFromChannel {block_id: String, id: String},
+    /// `ip` is set when it was the ip that got locked, not the account
+    LoginLocked {until: i64, ip: Option<String>},
+    LoginUnlocked {by: String},
+    /// admin unlocked `ip`
+    LoginIpUnlocked {ip: String},
+    Renamed {from: String},
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, TS)]
@@ -83,6 +89,11 @@ impl DbPool {
         }
     }
 
+    pub async fn delete_activity_table(&self, id: &str) -> Result<(), Error> {
+        self.activity_tables.delete_one(doc! {"_id": as_obj_id(id)?}, None).await?;
+        Ok(())
+    }
+
     pub async fn push_to_activity_table(&self, id: &str, items: &[Activity]) -> Result<(), Error> {
         let items_bson = mongodb::bson::to_bson(items)?;
         let result = self.activity_tables.update_one(doc! {
@@ -100,4 +111,13 @@ impl DbPool {
         }
         Ok(())
     }
+
+    /// rewrites `by` of every stored activity after user was renamed
+    pub(super) async fn rename_activity_actor(&self, name: &str, new_name: &str, session: &mut ClientSession) -> Result<(), Error> {
+        let options = UpdateOptions::builder().array_filters(vec![doc! {"item.data.activity.data.by": name}]).build();
+        self.activity_tables.update_many_with_session(doc! {"items.data.activity.data.by": name}, doc! {
+            "$set": {"items.$[item].data.activity.data.by": new_name}
+        }, options, session).await?;
+        Ok(())
+    }
 }
\ No newline at end of file
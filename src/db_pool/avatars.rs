@@ -0,0 +1,38 @@
+use serde::{Serialize, Deserialize};
+use super::{DbPool, Error, utils::as_obj_id};
+use mongodb::bson::{doc, oid::ObjectId, Binary, spec::BinarySubtype};
+
+/// uploaded avatar image, kept apart from users so that user lookups stay small
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Avatar {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub mime: String,
+    pub data: Binary,
+    pub created: i64,
+}
+
+impl DbPool {
+    pub async fn create_avatar(&self, mime: &str, data: Vec<u8>) -> Result<String, Error> {
+        let document = Avatar {
+            id: None,
+            mime: mime.to_string(),
+            data: Binary { subtype: BinarySubtype::Generic, bytes: data },
+            created: chrono::Utc::now().timestamp()
+        };
+        let result = self.avatars.insert_one(document, None).await?;
+        Ok(result.inserted_id.as_object_id().ok_or(Error::NotFound)?.to_string())
+    }
+
+    pub async fn get_avatar(&self, id: &str) -> Result<Avatar, Error> {
+        match self.avatars.find_one(doc! {"_id": as_obj_id(id)?}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub async fn delete_avatar(&self, id: &str) -> Result<(), Error> {
+        self.avatars.delete_one(doc! {"_id": as_obj_id(id)?}, None).await?;
+        Ok(())
+    }
+}
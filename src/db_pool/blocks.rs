@@ -1,6 +1,6 @@
 use super::{DbPool, Error, utils::as_obj_id};
 use futures::StreamExt;
-use mongodb::{bson::{doc, oid::ObjectId}, options::FindOptions};
+use mongodb::{bson::{doc, oid::ObjectId}, options::FindOptions, ClientSession};
 use serde::{Serialize, Deserialize};
 
 const QUERY_LIMIT: i64 = 30;
@@ -90,4 +90,14 @@ impl DbPool {
             Err(error) => Err(Error::Query(error))
         }
     }
-}
\ No newline at end of file
+
+    /// rewrites owner of every block after user was renamed
+    pub(super) async fn rename_block_owner(&self, name: &str, new_name: &str, session: &mut ClientSession) -> Result<(), Error> {
+        self.blocks.update_many_with_session(doc! {"owner": name}, doc! {"$set": {"owner": new_name}}, None, session).await?;
+        Ok(())
+    }
+
+    pub async fn count_user_blocks(&self, owner: &str) -> Result<u64, Error> {
+        Ok(self.blocks.count_documents(doc! {"owner": owner}, None).await?)
+    }
+}
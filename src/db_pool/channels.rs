@@ -2,7 +2,7 @@
 use serde::{Serialize, Deserialize};
 use ts_rs::TS;
 use super::{DbPool, Error, utils::as_obj_id};
-use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::{bson::{doc, oid::ObjectId}, options::UpdateOptions, ClientSession};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Channel {
@@ -17,6 +17,11 @@ pub struct Channel {
     pub pinned_block: String,
     pub title: String,
     pub activity_table: String,
+    #[serde(default)]
+    pub require_verified: bool,
+    /// no join and leave events or presence snapshot, only ghosted channels can have it
+    #[serde(default)]
+    pub hide_presence: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
@@ -38,7 +43,7 @@ impl DbPool {
         }
     }
 
-    pub async fn create_channel(&self, _type: &ChannelType, title: &str, description: &str, roles: &[(String, String)], default_role: &str, labels: &[String], activity_table: &str) -> Result<String, Error> {
+    pub async fn create_channel(&self, _type: &ChannelType, title: &str, description: &str, roles: &[(String, String)], default_role: &str, labels: &[String], activity_table: &str, require_verified: bool, hide_presence: bool) -> Result<String, Error> {
         let model = Channel {
             id: None,
             _type: _type.clone(),
@@ -48,7 +53,9 @@ impl DbPool {
             description: description.to_owned(),
             pinned_block: "".to_owned(),
             title: title.to_string(),
-            activity_table: activity_table.to_string()
+            activity_table: activity_table.to_string(),
+            require_verified,
+            hide_presence
         };
         let result = self.channels.insert_one(model, None).await?;
         Ok(result.inserted_id.to_string())
@@ -101,4 +108,25 @@ impl DbPool {
             Ok(())
         }
     }
-}
\ No newline at end of file
+
+    /// channels that use any of the roles as default or for some user
+    pub async fn get_channel_ids_with_roles(&self, role_ids: &[String]) -> Result<Vec<String>, Error> {
+        let ids = self.channels.distinct("_id", doc! {"$or": [
+            {"default_role": {"$in": role_ids}},
+            {"roles": {"$elemMatch": {"1": {"$in": role_ids}}}}
+        ]}, None).await?;
+        Ok(ids.iter().filter_map(|id| id.as_object_id()).map(|id| id.to_hex()).collect())
+    }
+
+    /// rewrites user part of `roles` tuples of every channel after user was renamed
+    pub(super) async fn rename_channel_role_user(&self, name: &str, new_name: &str, session: &mut ClientSession) -> Result<(), Error> {
+        let options = UpdateOptions::builder().array_filters(vec![doc! {"entry.0": name}]).build();
+        self.channels.update_many_with_session(doc! {"roles": {"$elemMatch": {"0": name}}}, doc! {"$set": {"roles.$[entry].0": new_name}}, options, session).await?;
+        Ok(())
+    }
+
+    /// channels where user has own role
+    pub async fn count_user_channels(&self, name: &str) -> Result<u64, Error> {
+        Ok(self.channels.count_documents(doc! {"roles": {"$elemMatch": {"0": name}}}, None).await?)
+    }
+}
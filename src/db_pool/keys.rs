@@ -0,0 +1,121 @@
+use serde::{Serialize, Deserialize};
+use super::{DbPool, Error};
+use futures::StreamExt;
+use mongodb::{bson::{doc, oid::ObjectId}, ClientSession};
+use super::utils::as_obj_id;
+
+/// one record per login, `key` is shared by access and key tokens of that login
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Key {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub key: String,
+    pub owner: String,
+    pub refresh: String,
+    pub revoked: bool,
+    pub expires: i64,
+    pub device: String,
+    pub ip: String,
+    pub created: i64,
+    pub last_seen: i64,
+}
+
+/// who the key is being issued to
+pub struct KeyClient<'a> {
+    pub device: &'a str,
+    pub ip: &'a str,
+}
+
+impl DbPool {
+    pub async fn create_key(&self, key: &str, owner: &str, refresh: &str, expires: i64, client: &KeyClient<'_>) -> Result<(), Error> {
+        let now = chrono::Utc::now().timestamp();
+        let document = Key {
+            id: None,
+            key: key.to_string(),
+            owner: owner.to_string(),
+            refresh: refresh.to_string(),
+            revoked: false,
+            expires,
+            device: client.device.to_string(),
+            ip: client.ip.to_string(),
+            created: now,
+            last_seen: now
+        };
+        self.keys.insert_one(document, None).await?;
+        Ok(())
+    }
+
+    pub async fn get_key(&self, key: &str) -> Result<Key, Error> {
+        match self.keys.find_one(doc! {"key": key}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    /// replaces refresh value only if it is still `old_refresh`, so the same key token can't be used twice
+    pub async fn rotate_key(&self, key: &str, old_refresh: &str, refresh: &str, expires: i64, client: &KeyClient<'_>) -> Result<(), Error> {
+        let result = self.keys.update_one(doc! {
+            "key": key,
+            "refresh": old_refresh,
+            "revoked": false
+        }, doc! {
+            "$set": {
+                "refresh": refresh,
+                "expires": expires,
+                "device": client.device,
+                "ip": client.ip,
+                "last_seen": chrono::Utc::now().timestamp()
+            }
+        }, None).await?;
+        if result.modified_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn touch_key(&self, key: &str, last_seen: i64) -> Result<(), Error> {
+        self.keys.update_one(doc! {"key": key}, doc! {"$max": {"last_seen": last_seen}}, None).await?;
+        Ok(())
+    }
+
+    pub async fn revoke_key(&self, key: &str) -> Result<(), Error> {
+        self.keys.update_one(doc! {"key": key}, doc! {"$set": {"revoked": true}}, None).await?;
+        Ok(())
+    }
+
+    pub async fn revoke_user_keys(&self, owner: &str) -> Result<(), Error> {
+        self.keys.update_many(doc! {"owner": owner}, doc! {"$set": {"revoked": true}}, None).await?;
+        Ok(())
+    }
+
+    /// keys that are neither revoked nor expired
+    pub async fn get_user_keys(&self, owner: &str) -> Result<(Vec<Key>, Vec<mongodb::error::Error>), Error> {
+        let mut cursor = self.keys.find(doc! {
+            "owner": owner,
+            "revoked": false,
+            "expires": {"$gt": chrono::Utc::now().timestamp()}
+        }, None).await?;
+        let mut keys = Vec::new();
+        let mut errors = Vec::new();
+        while let Some(key_result) = cursor.next().await {
+            match key_result {
+                Ok(key) => keys.push(key),
+                Err(error) => errors.push(error)
+            }
+        }
+        Ok((keys, errors))
+    }
+
+    pub async fn get_user_key_by_id(&self, owner: &str, id: &str) -> Result<Key, Error> {
+        match self.keys.find_one(doc! {"_id": as_obj_id(id)?, "owner": owner}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub(super) async fn rename_key_owner(&self, owner: &str, new_owner: &str, session: &mut ClientSession) -> Result<(), Error> {
+        self.keys.update_many_with_session(doc! {"owner": owner}, doc! {"$set": {"owner": new_owner}}, None, session).await?;
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,75 @@
+use serde::{Serialize, Deserialize};
+use super::{DbPool, Error};
+use mongodb::{bson::doc, options::{FindOneAndUpdateOptions, ReturnDocument}};
+
+/// failed logins of one account (`user:<name>`) or one ip (`ip:<address>`)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginAttempts {
+    pub key: String,
+    pub failures: i64,
+    pub locked_until: i64,
+    pub updated: i64,
+    /// id of the last counted attempt
+    #[serde(default)]
+    pub attempt: String,
+}
+
+/// how many attempts are allowed and how long the locks after them are, in seconds
+pub struct Backoff {
+    pub allowed: i64,
+    /// first lock, every next counted attempt doubles it
+    pub base_delay: i64,
+    pub max_delay: i64,
+}
+
+impl DbPool {
+    /// counts attempt `attempt` unless `key` is locked, attempt that goes over `backoff.allowed` locks right away
+    /// (it is still checked, success clears the lock), returned `attempt` differs if this one wasn't counted
+    pub async fn count_login_attempt(&self, key: &str, attempt: &str, backoff: &Backoff, now: i64) -> Result<LoginAttempts, Error> {
+        let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+        let exponent = doc! {"$min": [{"$subtract": ["$failures", backoff.allowed + 1]}, 32]};
+        let delay = doc! {"$min": [backoff.max_delay, {"$multiply": [backoff.base_delay, {"$pow": [2_i64, exponent]}]}]};
+        match self.login_attempts.find_one_and_update(doc! {"key": key}, vec![
+            doc! {"$set": {"counted": {"$lte": [{"$ifNull": ["$locked_until", 0_i64]}, now]}}},
+            doc! {"$set": {
+                "failures": {"$cond": ["$counted", {"$add": [{"$ifNull": ["$failures", 0_i64]}, 1_i64]}, "$failures"]},
+                "attempt": {"$cond": ["$counted", attempt, "$attempt"]},
+                "updated": {"$cond": ["$counted", now, "$updated"]}
+            }},
+            doc! {"$set": {"locked_until": {"$cond": [
+                {"$and": ["$counted", {"$gt": ["$failures", backoff.allowed]}]},
+                {"$toLong": {"$add": [now, delay]}},
+                {"$ifNull": ["$locked_until", 0_i64]}
+            ]}}},
+            doc! {"$unset": "counted"}
+        ], options).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    /// takes `count` failures back, lock stays
+    pub async fn forgive_login_failures(&self, key: &str, count: i64) -> Result<(), Error> {
+        self.login_attempts.update_one(doc! {"key": key}, vec![doc! {"$set": {
+            "failures": {"$max": [0_i64, {"$subtract": ["$failures", count]}]}
+        }}], None).await?;
+        Ok(())
+    }
+
+    pub async fn clear_login_attempts(&self, key: &str) -> Result<(), Error> {
+        self.login_attempts.delete_one(doc! {"key": key}, None).await?;
+        Ok(())
+    }
+
+    /// attempts that weren't updated since `updated_before` and aren't locked anymore
+    pub async fn delete_stale_login_attempts(&self, updated_before: i64) -> Result<(), Error> {
+        self.login_attempts.delete_many(doc! {
+            "updated": {"$lt": updated_before},
+            "locked_until": {"$lt": chrono::Utc::now().timestamp()}
+        }, None).await?;
+        Ok(())
+    }
+}
@@ -1,11 +1,19 @@
 use tokio::sync::MutexGuard;
-use mongodb::{options::ClientOptions, Client, Database, Collection};
+use mongodb::{options::{ClientOptions, IndexOptions}, Client, Database, Collection, IndexModel, bson::doc, error::{ErrorKind, WriteFailure}};
 
-pub use users::User;
+pub use users::{User, Totp, Identity, Profile, ProfileAvatar, ProfileVisibility, Visibility};
 pub use channels::{Channel, ChannelType};
 pub use blocks::Block;
 pub use roles::{Role, RolePermissions};
 pub use activity_table::{ActivityTable, Activity, UserActivity, ChannelActivity, GlobalActivity};
+pub use keys::{Key, KeyClient};
+pub use password_resets::PasswordReset;
+pub use access_tokens::{AccessToken, TokenScope};
+pub use login_attempts::{LoginAttempts, Backoff};
+pub use rate_limits::RateLimit;
+pub use avatars::Avatar;
+pub use reserved_names::ReservedName;
+pub use used_challenges::UsedChallenge;
 
 mod blocks;
 mod channels;
@@ -14,6 +22,14 @@ mod utils;
 mod groups;
 mod roles;
 mod activity_table;
+mod keys;
+mod password_resets;
+mod access_tokens;
+mod login_attempts;
+mod rate_limits;
+mod avatars;
+mod reserved_names;
+mod used_challenges;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -23,6 +39,9 @@ pub enum Error {
     InvalidObjectId(mongodb::bson::oid::Error),
     #[error("not found")]
     NotFound,
+    /// unique index was violated
+    #[error("already exists")]
+    Duplicate,
     #[error("serialization error: {0}")]
     BsonSerialization(mongodb::bson::ser::Error)
 }
@@ -33,9 +52,16 @@ impl From<mongodb::bson::ser::Error> for Error {
     }
 }
 
+/// server error code of unique index violation
+const DUPLICATE_KEY: i32 = 11000;
+
 impl From<mongodb::error::Error> for Error {
     fn from(value: mongodb::error::Error) -> Self {
-        Self::Query(value)
+        match value.kind.as_ref() {
+            ErrorKind::Write(WriteFailure::WriteError(error)) if error.code == DUPLICATE_KEY => Self::Duplicate,
+            ErrorKind::Command(error) if error.code == DUPLICATE_KEY => Self::Duplicate,
+            _ => Self::Query(value)
+        }
     }
 }
 
@@ -45,27 +71,83 @@ impl From<mongodb::bson::oid::Error> for Error {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectError {
+    #[error("db connection error: {0}")]
+    Query(mongodb::error::Error),
+    /// standalone mongod can't run transactions, renames would fail only once someone tries one
+    #[error("mongodb has to run as a replica set, a single node one is enough (`mongod --replSet rs0`, then `rs.initiate()` in mongosh)")]
+    NotReplicaSet,
+}
+
+impl From<mongodb::error::Error> for ConnectError {
+    fn from(value: mongodb::error::Error) -> Self {
+        Self::Query(value)
+    }
+}
+
+/// router of a sharded cluster, which runs transactions too
+const MONGOS: &str = "isdbgrid";
+
 pub struct DbPool {
+    client: Client,
     blocks: Collection<Block>,
     users: Collection<User>,
     roles: Collection<Role>,
     channels: Collection<Channel>,
     activity_tables: Collection<ActivityTable>,
+    keys: Collection<Key>,
+    password_resets: Collection<PasswordReset>,
+    access_tokens: Collection<AccessToken>,
+    login_attempts: Collection<LoginAttempts>,
+    rate_limits: Collection<RateLimit>,
+    avatars: Collection<Avatar>,
+    reserved_names: Collection<ReservedName>,
+    used_challenges: Collection<UsedChallenge>,
 }
 
 impl DbPool {
-    pub async fn new(address: &str) -> mongodb::error::Result<Self> {
+    pub async fn new(address: &str) -> Result<Self, ConnectError> {
         let client = Client::with_options(
             ClientOptions::parse(address).await?
         )?;
         let db = client
         .database("chane");
-        Ok(Self {
+        let pool = Self {
+            client,
             blocks: db.collection("blocks"),
             users: db.collection("users"),
             roles: db.collection("roles"),
             channels: db.collection("channels"),
             activity_tables: db.collection("activity_tables"),
-        })
+            keys: db.collection("keys"),
+            password_resets: db.collection("password_resets"),
+            access_tokens: db.collection("access_tokens"),
+            login_attempts: db.collection("login_attempts"),
+            rate_limits: db.collection("rate_limits"),
+            avatars: db.collection("avatars"),
+            reserved_names: db.collection("reserved_names"),
+            used_challenges: db.collection("used_challenges"),
+        };
+        Self::require_transactions(&db).await?;
+        pool.create_indexes().await?;
+        Ok(pool)
+    }
+
+    async fn require_transactions(db: &Database) -> Result<(), ConnectError> {
+        let hello = db.run_command(doc! {"hello": 1}, None).await?;
+        if hello.contains_key("setName") || hello.get_str("msg").is_ok_and(|msg| msg == MONGOS) {
+            Ok(())
+        } else {
+            Err(ConnectError::NotReplicaSet)
+        }
+    }
+
+    /// checks that can't be done atomically in queries, creating an existing index does nothing
+    async fn create_indexes(&self) -> mongodb::error::Result<()> {
+        let unique = IndexOptions::builder().unique(true).build();
+        self.users.create_index(IndexModel::builder().keys(doc! {"name": 1}).options(unique.clone()).build(), None).await?;
+        self.login_attempts.create_index(IndexModel::builder().keys(doc! {"key": 1}).options(unique).build(), None).await?;
+        Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,46 @@
+use serde::{Serialize, Deserialize};
+use super::{DbPool, Error};
+use mongodb::{bson::{doc, oid::ObjectId}, ClientSession};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordReset {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub token_hash: String,
+    pub owner: String,
+    pub expires: i64,
+    pub used: bool,
+}
+
+impl DbPool {
+    pub async fn create_password_reset(&self, token_hash: &str, owner: &str, expires: i64) -> Result<(), Error> {
+        let document = PasswordReset {
+            id: None,
+            token_hash: token_hash.to_string(),
+            owner: owner.to_string(),
+            expires,
+            used: false
+        };
+        self.password_resets.insert_one(document, None).await?;
+        Ok(())
+    }
+
+    /// marks reset as used and returns it, fails if it is already used or expired
+    pub async fn use_password_reset(&self, token_hash: &str) -> Result<PasswordReset, Error> {
+        match self.password_resets.find_one_and_update(doc! {
+            "token_hash": token_hash,
+            "used": false,
+            "expires": {"$gt": chrono::Utc::now().timestamp()}
+        }, doc! {
+            "$set": {"used": true}
+        }, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub(super) async fn rename_password_reset_owner(&self, owner: &str, new_owner: &str, session: &mut ClientSession) -> Result<(), Error> {
+        self.password_resets.update_many_with_session(doc! {"owner": owner}, doc! {"$set": {"owner": new_owner}}, None, session).await?;
+        Ok(())
+    }
+}
\ No newline at end of file
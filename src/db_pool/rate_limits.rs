@@ -0,0 +1,48 @@
+use serde::{Serialize, Deserialize};
+use super::{DbPool, Error};
+use mongodb::{bson::doc, options::{FindOneAndUpdateOptions, ReturnDocument, UpdateModifications}};
+
+/// token bucket shared between instances
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub key: String,
+    pub tokens: f64,
+    /// unix timestamp in seconds, with fraction
+    pub updated: f64,
+    /// whether the last take succeeded
+    pub allowed: bool,
+}
+
+impl DbPool {
+    /// refills and takes a token in one atomic update, returns whether it was taken and tokens left
+    pub async fn take_rate_limit_token(&self, key: &str, capacity: u32, rate: f64) -> Result<(bool, f64), Error> {
+        let now = chrono::Utc::now().timestamp_millis() as f64 / 1000.0;
+        let capacity = capacity as f64;
+        let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+        let pipeline = vec![
+            doc! {"$set": {
+                "tokens": {"$min": [capacity, {"$add": [
+                    {"$ifNull": ["$tokens", capacity]},
+                    {"$multiply": [{"$subtract": [now, {"$ifNull": ["$updated", now]}]}, rate]}
+                ]}]},
+                "updated": now
+            }},
+            doc! {"$set": {
+                "allowed": {"$gte": ["$tokens", 1.0]},
+                "tokens": {"$cond": [{"$gte": ["$tokens", 1.0]}, {"$subtract": ["$tokens", 1.0]}, "$tokens"]}
+            }}
+        ];
+        match self.rate_limits.find_one_and_update(doc! {"key": key}, UpdateModifications::Pipeline(pipeline), options).await? {
+            Some(model) => Ok((model.allowed, model.tokens)),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub async fn delete_stale_rate_limits(&self, updated_before: i64) -> Result<(), Error> {
+        self.rate_limits.delete_many(doc! {"updated": {"$lt": updated_before as f64}}, None).await?;
+        Ok(())
+    }
+}
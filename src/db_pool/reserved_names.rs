@@ -0,0 +1,39 @@
+use serde::{Serialize, Deserialize};
+use super::{DbPool, Error};
+use mongodb::bson::{doc, oid::ObjectId};
+
+/// name of a deleted user that something still points to, nobody can take it so they don't inherit that
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReservedName {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    pub reserved: i64,
+}
+
+impl DbPool {
+    pub async fn reserve_names(&self, names: &[String]) -> Result<(), Error> {
+        if names.is_empty() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().timestamp();
+        self.reserved_names.insert_many(names.iter().map(|name| ReservedName {
+            id: None,
+            name: name.clone(),
+            reserved: now
+        }), None).await?;
+        Ok(())
+    }
+
+    /// blocks, roles or channel roles still point to the name
+    pub async fn is_name_referenced(&self, name: &str) -> Result<bool, Error> {
+        Ok(self.count_user_blocks(name).await? > 0
+        || self.count_user_roles(name).await? > 0
+        || self.count_user_edited_roles(name).await? > 0
+        || self.count_user_channels(name).await? > 0)
+    }
+
+    pub async fn is_name_reserved(&self, name: &str) -> Result<bool, Error> {
+        Ok(self.reserved_names.find_one(doc! {"name": name}, None).await?.is_some())
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Serialize, Deserialize};
 use ts_rs::TS;
 use super::{Error, DbPool, utils::as_obj_id};
-use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::{bson::{doc, oid::ObjectId}, options::UpdateOptions, ClientSession};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Role {
@@ -27,6 +27,9 @@ pub struct RolePermissions {
     pub pin_roles: Vec<String>,
     pub set_labels: bool,
     pub live: Vec<String>,
+    /// sending ephemeral signals (typing, cursors) over live socket
+    #[serde(default)]
+    pub signal: Vec<String>,
 }
 
 fn append_vec_unique<V: PartialEq + Clone>(vec1: &mut Vec<V>, vec2: &Vec<V>){
@@ -48,6 +51,7 @@ impl RolePermissions {
         append_vec_unique(&mut self.change_description, &external.change_description);
         append_vec_unique(&mut self.pin_roles, &external.pin_roles);
         append_vec_unique(&mut self.live, &external.live);
+        append_vec_unique(&mut self.signal, &external.signal);
         self.set_labels = self.set_labels || external.set_labels;
     }
 }
@@ -104,4 +108,27 @@ impl DbPool {
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// rewrites owner and editors of every role after user was renamed
+    pub(super) async fn rename_role_user(&self, name: &str, new_name: &str, session: &mut ClientSession) -> Result<(), Error> {
+        self.roles.update_many_with_session(doc! {"owner": name}, doc! {"$set": {"owner": new_name}}, None, session).await?;
+        let options = UpdateOptions::builder().array_filters(vec![doc! {"editor": name}]).build();
+        self.roles.update_many_with_session(doc! {"editors": name}, doc! {"$set": {"editors.$[editor]": new_name}}, options, session).await?;
+        Ok(())
+    }
+
+    /// roles that directly extend any of the given ones
+    pub async fn get_extending_role_ids(&self, ids: &[String]) -> Result<Vec<String>, Error> {
+        let ids = self.roles.distinct("_id", doc! {"extends": {"$in": ids}}, None).await?;
+        Ok(ids.iter().filter_map(|id| id.as_object_id()).map(|id| id.to_hex()).collect())
+    }
+
+    pub async fn count_user_roles(&self, owner: &str) -> Result<u64, Error> {
+        Ok(self.roles.count_documents(doc! {"owner": owner}, None).await?)
+    }
+
+    /// roles user may edit without owning them
+    pub async fn count_user_edited_roles(&self, name: &str) -> Result<u64, Error> {
+        Ok(self.roles.count_documents(doc! {"editors": name}, None).await?)
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Serialize, Deserialize};
+use super::{DbPool, Error};
+use mongodb::{bson::doc, options::UpdateOptions};
+
+/// login challenge token that was already used, kept until the token expires
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsedChallenge {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub expires: i64,
+}
+
+impl DbPool {
+    /// `NotFound` if challenge was already used
+    pub async fn use_challenge(&self, id: &str, expires: i64) -> Result<(), Error> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        let result = self.used_challenges.update_one(doc! {"_id": id}, doc! {
+            "$setOnInsert": {"expires": expires}
+        }, options).await?;
+        match result.upserted_id {
+            Some(_) => Ok(()),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub async fn delete_expired_challenges(&self, now: i64) -> Result<(), Error> {
+        self.used_challenges.delete_many(doc! {"expires": {"$lte": now}}, None).await?;
+        Ok(())
+    }
+}
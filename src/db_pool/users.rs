@@ -1,6 +1,8 @@
 use serde::{Serialize, Deserialize};
+use ts_rs::TS;
 use super::{DbPool, Error};
-use mongodb::bson::doc;
+use futures::StreamExt;
+use mongodb::{bson::doc, ClientSession, error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT}};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct User {
@@ -8,7 +10,88 @@ pub struct User {
     pub email: String,
     pub password_hash: String,
     pub groups: Vec<String>,
-    pub activity_table: String
+    pub activity_table: String,
+    #[serde(default = "default_verified")]
+    pub verified: bool,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub totp: Option<Totp>,
+    /// external identity provider accounts that can log in as this user
+    #[serde(default)]
+    pub identities: Vec<Identity>,
+    /// names the user had before, they stay reserved and redirect to the current one
+    #[serde(default)]
+    pub former_names: Vec<String>,
+    #[serde(default)]
+    pub profile: Profile,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, TS)]
+#[ts(export)]
+pub enum Visibility {
+    #[default]
+    #[serde(rename = "public")]
+    Public,
+    /// logged in users only
+    #[serde(rename = "users")]
+    Users,
+    /// only the user themself
+    #[serde(rename = "private")]
+    Private,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS)]
+#[ts(export)]
+pub struct ProfileVisibility {
+    pub display_name: Visibility,
+    pub bio: Visibility,
+    pub avatar: Visibility,
+    pub links: Visibility,
+    pub pinned_block: Visibility,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+#[serde(tag = "type", content = "id")]
+pub enum ProfileAvatar {
+    /// id of uploaded `Avatar`
+    Image(String),
+    Block(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    pub bio: String,
+    pub avatar: Option<ProfileAvatar>,
+    pub links: Vec<String>,
+    pub pinned_block: Option<String>,
+    pub visibility: ProfileVisibility,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Identity {
+    pub issuer: String,
+    pub subject: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Totp {
+    /// base32 encoded
+    pub secret: String,
+    /// becomes true once enrollment is confirmed with a valid code
+    pub enabled: bool,
+    /// sha256 hashes of unused recovery codes
+    pub recovery_codes: Vec<String>,
+    /// time step of the last accepted code, codes of it and earlier steps are replays
+    #[serde(default)]
+    pub last_step: u64,
+}
+
+/// users that were created before email verification existed are considered verified
+fn default_verified() -> bool {
+    true
 }
 
 pub struct CredentialUniqueness {
@@ -35,29 +118,238 @@ impl DbPool {
         }
     }
 
+    pub async fn get_user_by_former_name(&self, name: &str) -> Result<User, Error> {
+        match self.users.find_one(doc! {"former_names": name}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    /// whether name is used or reserved by anyone other than `except`
+    pub async fn is_name_taken(&self, name: &str, except: Option<&str>) -> Result<bool, Error> {
+        let mut filter = doc! {"$or": [{"name": name}, {"former_names": name}]};
+        if let Some(except) = except {
+            filter.insert("name", doc! {"$ne": except});
+        }
+        Ok(self.users.find_one(filter, None).await?.is_some() || self.is_name_reserved(name).await?)
+    }
+
+    /// old name is kept in `former_names`, `new_name` is removed from them in case user takes it back
+    pub(super) async fn rename_user(&self, name: &str, new_name: &str, session: &mut ClientSession) -> Result<(), Error> {
+        let result = self.users.update_one_with_session(doc! {"name": name}, vec![doc! {"$set": {
+            "name": new_name,
+            "former_names": {"$concatArrays": [
+                {"$filter": {"input": {"$ifNull": ["$former_names", []]}, "cond": {"$ne": ["$$this", new_name]}}},
+                [name]
+            ]}
+        }}], None, session).await?;
+        if result.matched_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// renames user and every reference to the old name in one transaction, `Duplicate` when the new name got taken meanwhile
+    pub async fn rename_user_everywhere(&self, name: &str, new_name: &str) -> Result<(), Error> {
+        let mut session = self.client.start_session(None).await?;
+        loop {
+            session.start_transaction(None).await?;
+            if let Err(error) = self.rename_user_references(name, new_name, &mut session).await {
+                let _ = session.abort_transaction().await;
+                match error {
+                    Error::Query(error) if error.contains_label(TRANSIENT_TRANSACTION_ERROR) => continue,
+                    error => return Err(error)
+                }
+            }
+            loop {
+                match session.commit_transaction().await {
+                    Ok(()) => return Ok(()),
+                    Err(error) if error.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) => continue,
+                    Err(error) if error.contains_label(TRANSIENT_TRANSACTION_ERROR) => break,
+                    Err(error) => return Err(error.into())
+                }
+            }
+        }
+    }
+
+    async fn rename_user_references(&self, name: &str, new_name: &str, session: &mut ClientSession) -> Result<(), Error> {
+        self.rename_user(name, new_name, session).await?;
+        self.rename_block_owner(name, new_name, session).await?;
+        self.rename_role_user(name, new_name, session).await?;
+        self.rename_channel_role_user(name, new_name, session).await?;
+        self.rename_activity_actor(name, new_name, session).await?;
+        self.rename_key_owner(name, new_name, session).await?;
+        self.rename_access_token_owner(name, new_name, session).await?;
+        self.rename_password_reset_owner(name, new_name, session).await
+    }
+
+    /// user whose avatar is the uploaded image `id`
+    pub async fn get_user_by_avatar_image(&self, id: &str) -> Result<User, Error> {
+        match self.users.find_one(doc! {"profile.avatar.type": "Image", "profile.avatar.id": id}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub async fn set_user_profile(&self, name: &str, profile: &Profile) -> Result<(), Error> {
+        let result = self.users.update_one(doc! {"name": name}, doc! {"$set": {"profile": mongodb::bson::to_bson(profile)?}}, None).await?;
+        if result.matched_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn get_user_by_email(&self, email: &str) -> Result<User, Error> {
+        match self.users.find_one(doc! {"email": email}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub async fn change_user_password(&self, name: &str, password_hash: &str) -> Result<(), Error> {
+        let result = self.users.update_one(doc! {"name": name}, doc! {"$set": {"password_hash": password_hash}}, None).await?;
+        if result.matched_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
     pub async fn create_user(&self, name: &str, email: &str, password_hash: &str, activity_table_id: &str) -> Result<(), Error> {
         let document = User {
             email: email.to_string(),
             name: name.to_string(),
             password_hash: password_hash.to_string(),
             groups: Vec::new(),
-            activity_table: activity_table_id.to_string()
+            activity_table: activity_table_id.to_string(),
+            verified: false,
+            created: chrono::Utc::now().timestamp(),
+            totp: None,
+            identities: Vec::new(),
+            former_names: Vec::new(),
+            profile: Profile::default()
+        };
+        self.users.insert_one(document, None).await?;
+        Ok(())
+    }
+
+    /// user without password, that can log in only through `identity`
+    pub async fn create_linked_user(&self, name: &str, email: &str, verified: bool, identity: &Identity, activity_table_id: &str) -> Result<(), Error> {
+        let document = User {
+            email: email.to_string(),
+            name: name.to_string(),
+            password_hash: String::new(),
+            groups: Vec::new(),
+            activity_table: activity_table_id.to_string(),
+            verified,
+            created: chrono::Utc::now().timestamp(),
+            totp: None,
+            identities: vec![identity.clone()],
+            former_names: Vec::new(),
+            profile: Profile::default()
         };
         self.users.insert_one(document, None).await?;
         Ok(())
     }
 
+    pub async fn get_user_by_identity(&self, identity: &Identity) -> Result<User, Error> {
+        match self.users.find_one(doc! {"identities": {"$elemMatch": {"issuer": &identity.issuer, "subject": &identity.subject}}}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
+    pub async fn link_user_identity(&self, name: &str, identity: &Identity) -> Result<(), Error> {
+        let result = self.users.update_one(doc! {"name": name}, doc! {
+            "$addToSet": {"identities": mongodb::bson::to_bson(identity)?}
+        }, None).await?;
+        if result.matched_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn set_user_totp(&self, name: &str, totp: &Option<Totp>) -> Result<(), Error> {
+        let result = self.users.update_one(doc! {"name": name}, doc! {"$set": {"totp": mongodb::bson::to_bson(totp)?}}, None).await?;
+        if result.matched_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// moves `last_step` forward, `NotFound` if it already is at `step` or past it
+    pub async fn use_user_totp_step(&self, name: &str, step: u64) -> Result<(), Error> {
+        let step = step as i64;
+        let result = self.users.update_one(doc! {"name": name, "totp.last_step": {"$not": {"$gte": step}}}, doc! {
+            "$set": {"totp.last_step": step}
+        }, None).await?;
+        if result.modified_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// removes recovery code so it can't be used again, `NotFound` if user has no such code
+    pub async fn use_user_recovery_code(&self, name: &str, code_hash: &str) -> Result<(), Error> {
+        let result = self.users.update_one(doc! {"name": name, "totp.recovery_codes": code_hash}, doc! {
+            "$pull": {"totp.recovery_codes": code_hash}
+        }, None).await?;
+        if result.modified_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn verify_user_email(&self, name: &str, email: &str) -> Result<(), Error> {
+        let result = self.users.update_one(doc! {"name": name, "email": email}, doc! {"$set": {"verified": true}}, None).await?;
+        if result.matched_count == 0 {
+            Err(Error::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// unverified users created before `created_before` timestamp
+    pub async fn get_stale_unverified_users(&self, created_before: i64) -> Result<(Vec<User>, Vec<mongodb::error::Error>), Error> {
+        let mut cursor = self.users.find(doc! {"verified": false, "created": {"$lt": created_before}}, None).await?;
+        let mut users = Vec::new();
+        let mut errors = Vec::new();
+        while let Some(user_result) = cursor.next().await {
+            match user_result {
+                Ok(user) => users.push(user),
+                Err(error) => errors.push(error)
+            }
+        }
+        Ok((users, errors))
+    }
+
+    /// fails with `NotFound` if user got verified meanwhile
+    pub async fn delete_unverified_user(&self, name: &str) -> Result<User, Error> {
+        match self.users.find_one_and_delete(doc! {"name": name, "verified": false}, None).await? {
+            Some(model) => Ok(model),
+            None => Err(Error::NotFound)
+        }
+    }
+
     pub async fn check_if_unique_credentials(&self, name: &str, email: &str) -> Result<CredentialUniqueness, Error> {
-        let filter = doc! {"$or": [{"name": name}, {"email": email}]};
+        let filter = doc! {"$or": [{"name": name}, {"former_names": name}, {"email": email}]};
         let result = self.users.find_one(filter, None).await?;
-        Ok(match result {
+        let mut uniqueness = match result {
             None => CredentialUniqueness::default(),
             Some(model) => {
                 CredentialUniqueness {
                     email: model.email.as_str() != email,
-                    name: model.name.as_str() != name,
+                    name: model.name.as_str() != name && !model.former_names.iter().any(|former| former == name),
                 }
             }
-        })
+        };
+        uniqueness.name = uniqueness.name && !self.is_name_reserved(name).await?;
+        Ok(uniqueness)
     }
 }
\ No newline at end of file
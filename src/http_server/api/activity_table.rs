@@ -10,7 +10,7 @@ pub fn service() -> Scope {
 type GetOneResponse = ResultResponse<ActivityTable, GeneralError>;
 #[get("/{id}")]
 pub async fn get_one(app_state: AppStateData, id: Path<String>, req: HttpRequest) -> Response<GetOneResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.get_activity_table(&id).await {
         Ok(table) => Response::ok_ok(table),
         Err(error) => Response::err_err(error.into())
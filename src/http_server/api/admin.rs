@@ -0,0 +1,27 @@
+use actix_web::{Scope, web::{self, Json}, post, HttpRequest};
+use serde::Deserialize;
+use ts_rs::TS;
+use crate::http_server::{AppStateData, errors::{ResultResponse, general::GeneralError}};
+use super::Response;
+
+pub fn service() -> Scope {
+    web::scope("/admin")
+    .service(unlock_login)
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "AdminUnlockLoginBody")]
+pub struct UnlockLoginBody {
+    pub name: Option<String>,
+    pub ip: Option<String>,
+}
+
+type UnlockLoginResponse = ResultResponse<(), GeneralError>;
+#[post("/unlock-login")]
+pub async fn unlock_login(app_state: AppStateData, body: Json<UnlockLoginBody>, req: HttpRequest) -> Response<UnlockLoginResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.unlock_login(body.name.as_deref(), body.ip.as_deref()).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
@@ -1,19 +1,35 @@
 use actix_web::{Scope, web::{self, Json}, post, get, HttpResponse, cookie::{CookieBuilder, Cookie}, HttpRequest};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use ts_rs::TS;
-use crate::{http_server::{AppStateData, errors::ResultResponse}, session_pool::AuthMe};
+use crate::{http_server::{AppStateData, errors::{ResultResponse, general::GeneralError}, extract_cookie_as_string}, session_pool::{AuthMe, AuthSession, LoginOutcome, TwoFactorEnrollment}};
 use super::{Response, errors};
 
 pub fn service() -> Scope {
     web::scope("/auth")
     .service(join)
     .service(login)
+    .service(login_second_factor)
     .service(me)
+    .service(refresh)
+    .service(logout)
+    .service(get_sessions)
+    .service(revoke_session)
+    .service(revoke_other_sessions)
+    .service(change_password)
+    .service(request_password_reset)
+    .service(reset_password)
+    .service(verify_email)
+    .service(resend_verification)
+    .service(enroll_two_factor)
+    .service(confirm_two_factor)
+    .service(disable_two_factor)
+    .service(start_oidc_login)
+    .service(finish_oidc_login)
 }
 
 #[get("/me")]
 pub async fn me(app_state: AppStateData, req: HttpRequest) -> Response<AuthMe> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     Response::ok(session.me().await)
 }
 
@@ -27,7 +43,7 @@ pub struct JoinBody {
 
 #[post("/join")]
 pub async fn join(app_state: AppStateData, body: Json<JoinBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::JoinError>> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
 
     match session.register(&body.name, &body.email, &body.password).await {
         Ok(tokens) => Response::new(
@@ -57,10 +73,49 @@ pub struct LoginBody {
     pub password: String,
 }
 
+#[derive(Serialize, TS)]
+#[ts(export, rename = "AuthLoginResponse")]
+#[serde(tag = "is", content = "data")]
+pub enum LoginResponse {
+    LoggedIn,
+    SecondFactorRequired {
+        challenge: String
+    }
+}
+
 #[post("/login")]
-pub async fn login(app_state: AppStateData, body: Json<LoginBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::LoginError>> {
-    let session = app_state.session_from_request(&req);
+pub async fn login(app_state: AppStateData, body: Json<LoginBody>, req: HttpRequest) -> Response<ResultResponse<LoginResponse, errors::auth::LoginError>> {
+    let session = app_state.session_from_request(&req).await;
     match session.login(&body.name, &body.password).await {
+        Ok(LoginOutcome::Tokens(tokens)) => Response::new(
+            HttpResponse::Ok()
+            .cookie(
+                CookieBuilder::new("access-token", tokens.access)
+                .http_only(true)
+                .finish()
+            )
+            .cookie(
+                CookieBuilder::new("key-token", tokens.key)
+                .finish()
+            ).take(),
+            ResultResponse::Ok(LoginResponse::LoggedIn)
+        ),
+        Ok(LoginOutcome::Challenge(challenge)) => Response::ok_ok(LoginResponse::SecondFactorRequired { challenge }),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "AuthLoginSecondFactorBody")]
+pub struct LoginSecondFactorBody {
+    pub challenge: String,
+    pub code: String,
+}
+
+#[post("/login/second-factor")]
+pub async fn login_second_factor(app_state: AppStateData, body: Json<LoginSecondFactorBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::LoginError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.login_second_factor(&body.challenge, &body.code).await {
         Ok(tokens) => Response::new(
             HttpResponse::Ok()
             .cookie(
@@ -76,4 +131,252 @@ pub async fn login(app_state: AppStateData, body: Json<LoginBody>, req: HttpRequ
         ),
         Err(error) => Response::err_err(error.into())
     }
-}
\ No newline at end of file
+}
+
+#[post("/refresh")]
+pub async fn refresh(app_state: AppStateData, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::RefreshError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.refresh(&extract_cookie_as_string(&req, "key-token")).await {
+        Ok(tokens) => Response::new(
+            HttpResponse::Ok()
+            .cookie(
+                Cookie::build("access-token", tokens.access)
+                .http_only(true)
+                .same_site(actix_web::cookie::SameSite::None)
+                .finish()
+            )
+            .cookie(
+                Cookie::build("key-token", tokens.key)
+                .same_site(actix_web::cookie::SameSite::None)
+                .finish()
+            ).take(),
+            ResultResponse::Ok(())
+        ),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[post("/logout")]
+pub async fn logout(app_state: AppStateData, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::RefreshError>> {
+    let session = app_state.session_from_request(&req).await;
+    session.logout(&extract_cookie_as_string(&req, "key-token")).await;
+    let mut access_cookie = Cookie::named("access-token");
+    access_cookie.make_removal();
+    let mut key_cookie = Cookie::named("key-token");
+    key_cookie.make_removal();
+    Response::new(
+        HttpResponse::Ok()
+        .cookie(access_cookie)
+        .cookie(key_cookie).take(),
+        ResultResponse::Ok(())
+    )
+}
+
+type GetSessionsResponse = ResultResponse<Vec<AuthSession>, GeneralError>;
+#[get("/sessions")]
+pub async fn get_sessions(app_state: AppStateData, req: HttpRequest) -> Response<GetSessionsResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.get_sessions().await {
+        Ok(sessions) => Response::ok_ok(sessions),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "AuthRevokeSessionBody")]
+pub struct RevokeSessionBody {
+    pub id: String,
+}
+
+type RevokeSessionResponse = ResultResponse<(), GeneralError>;
+#[post("/sessions/revoke")]
+pub async fn revoke_session(app_state: AppStateData, body: Json<RevokeSessionBody>, req: HttpRequest) -> Response<RevokeSessionResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.revoke_session(&body.id).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[post("/sessions/revoke-others")]
+pub async fn revoke_other_sessions(app_state: AppStateData, req: HttpRequest) -> Response<RevokeSessionResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.revoke_other_sessions().await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct ChangePasswordBody {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[post("/password/change")]
+pub async fn change_password(app_state: AppStateData, body: Json<ChangePasswordBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::ChangePasswordError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.change_password(&body.old_password, &body.new_password).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct RequestPasswordResetBody {
+    pub email: String,
+}
+
+#[post("/password/request-reset")]
+pub async fn request_password_reset(app_state: AppStateData, body: Json<RequestPasswordResetBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::ResetPasswordError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.request_password_reset(&body.email).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct ResetPasswordBody {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[post("/password/reset")]
+pub async fn reset_password(app_state: AppStateData, body: Json<ResetPasswordBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::ResetPasswordError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.reset_password(&body.token, &body.new_password).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct VerifyEmailBody {
+    pub token: String,
+}
+
+#[post("/verify-email")]
+pub async fn verify_email(app_state: AppStateData, body: Json<VerifyEmailBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::VerifyEmailError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.verify_email(&body.token).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[post("/verify-email/resend")]
+pub async fn resend_verification(app_state: AppStateData, req: HttpRequest) -> Response<ResultResponse<(), errors::auth::VerifyEmailError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.resend_verification().await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+type EnrollTwoFactorResponse = ResultResponse<TwoFactorEnrollment, errors::auth::TwoFactorError>;
+#[post("/2fa/enroll")]
+pub async fn enroll_two_factor(app_state: AppStateData, req: HttpRequest) -> Response<EnrollTwoFactorResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.enroll_two_factor().await {
+        Ok(enrollment) => Response::ok_ok(enrollment),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct ConfirmTwoFactorBody {
+    pub code: String,
+}
+
+type ConfirmTwoFactorResponse = ResultResponse<Vec<String>, errors::auth::TwoFactorError>;
+#[post("/2fa/confirm")]
+pub async fn confirm_two_factor(app_state: AppStateData, body: Json<ConfirmTwoFactorBody>, req: HttpRequest) -> Response<ConfirmTwoFactorResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.confirm_two_factor(&body.code).await {
+        Ok(recovery_codes) => Response::ok_ok(recovery_codes),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct DisableTwoFactorBody {
+    pub password: String,
+    pub code: String,
+}
+
+type DisableTwoFactorResponse = ResultResponse<(), errors::auth::TwoFactorError>;
+#[post("/2fa/disable")]
+pub async fn disable_two_factor(app_state: AppStateData, body: Json<DisableTwoFactorBody>, req: HttpRequest) -> Response<DisableTwoFactorResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.disable_two_factor(&body.password, &body.code).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+#[derive(Serialize, TS)]
+#[ts(export, rename = "AuthOidcStartResponse")]
+pub struct OidcStartResponse {
+    pub url: String,
+}
+
+/// frontend redirects user to returned url, state cookie has to be present when callback is posted
+#[post("/oidc/start")]
+pub async fn start_oidc_login(app_state: AppStateData, req: HttpRequest) -> Response<ResultResponse<OidcStartResponse, errors::auth::OidcError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.start_oidc_login().await {
+        Ok(start) => Response::new(
+            HttpResponse::Ok()
+            .cookie(
+                CookieBuilder::new("oidc-state", start.state_token)
+                .http_only(true)
+                .same_site(actix_web::cookie::SameSite::Lax)
+                .finish()
+            ).take(),
+            ResultResponse::Ok(OidcStartResponse { url: start.url })
+        ),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "AuthOidcCallbackBody")]
+pub struct OidcCallbackBody {
+    pub code: String,
+    pub state: String,
+}
+
+/// query parameters that identity provider redirected to frontend with
+#[post("/oidc/callback")]
+pub async fn finish_oidc_login(app_state: AppStateData, body: Json<OidcCallbackBody>, req: HttpRequest) -> Response<ResultResponse<LoginResponse, errors::auth::OidcError>> {
+    let session = app_state.session_from_request(&req).await;
+    let mut state_cookie = Cookie::named("oidc-state");
+    state_cookie.make_removal();
+    match session.finish_oidc_login(&body.code, &body.state, &extract_cookie_as_string(&req, "oidc-state")).await {
+        Ok(LoginOutcome::Tokens(tokens)) => Response::new(
+            HttpResponse::Ok()
+            .cookie(state_cookie)
+            .cookie(
+                CookieBuilder::new("access-token", tokens.access)
+                .http_only(true)
+                .finish()
+            )
+            .cookie(
+                CookieBuilder::new("key-token", tokens.key)
+                .finish()
+            ).take(),
+            ResultResponse::Ok(LoginResponse::LoggedIn)
+        ),
+        Ok(LoginOutcome::Challenge(challenge)) => Response::new(
+            HttpResponse::Ok().cookie(state_cookie).take(),
+            ResultResponse::Ok(LoginResponse::SecondFactorRequired { challenge })
+        ),
+        Err(error) => Response::err_err(error.into())
+    }
+}
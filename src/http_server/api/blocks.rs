@@ -14,7 +14,7 @@ pub fn service() -> Scope {
 type GetOneResponse = ResultResponse<session_pool::Block, GeneralError>;
 #[get("/{id}")]
 async fn get_one(app_state: AppStateData, id: Path<String>, req: HttpRequest) -> Response<GetOneResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.get_block(id.as_str()).await {
         Ok(block) => Response::ok_ok(block),
         Err(error) => {
@@ -33,7 +33,7 @@ pub struct CreateBody {
 type CreateResponse = ResultResponse<String, GeneralError>;
 #[post("/create")]
 async fn create(app_state: AppStateData, body: Json<CreateBody>, req: HttpRequest) -> Response<CreateResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.create_block(body.content.as_str()).await {
         Ok(id) => Response::ok_ok(id),
         Err(error) => Response::err_err(error.into())
@@ -50,7 +50,7 @@ pub struct ChangeBody {
 type ChangeResponse = ResultResponse<(), GeneralError>;
 #[post("/change")]
 async fn change(app_state: AppStateData, body: Json<ChangeBody>, req: HttpRequest) -> Response<ChangeResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.change_block(body.id.as_str(), body.content.as_str()).await {
         Ok(()) => Response::ok_ok(()),
         Err(error) => Response::err_err(error.into())
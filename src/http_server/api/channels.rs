@@ -14,12 +14,13 @@ pub fn service() -> Scope {
     .service(change_description)
     .service(change_labels)
     .service(get_channel_blocks)
+    .service(get_presence)
 }
 
 pub type GetOneResponse = ResultResponse<session_pool::Channel, GeneralError>;
 #[get("/{id}")]
 pub async fn get_one(app_state: AppStateData, id: Path<String>, req: HttpRequest) -> Response<GetOneResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.get_channel(id.as_str()).await {
         Ok(channel) => Response::ok_ok(channel),
         Err(error) => Response::err_err(error.into())
@@ -35,13 +36,18 @@ pub struct CreateBoby {
     pub title: String,
     pub default_role: String,
     pub labels: Vec<String>,
+    #[serde(default)]
+    pub require_verified: bool,
+    /// ignored unless channel is ghosted
+    #[serde(default)]
+    pub hide_presence: bool,
 }
 
 pub type CreateResponse = ResultResponse<String, GeneralError>;
 #[post("/create")]
 pub async fn create(app_state: AppStateData, body: Json<CreateBoby>, req: HttpRequest) -> Response<CreateResponse> {
-    let session = app_state.session_from_request(&req);
-    match session.create_channel(&body._type, &body.title, &body.description, &body.default_role, &body.labels).await {
+    let session = app_state.session_from_request(&req).await;
+    match session.create_channel(&body._type, &body.title, &body.description, &body.default_role, &body.labels, body.require_verified, body.hide_presence).await {
         Ok(id) => Response::ok_ok(id),
         Err(error) => Response::err_err(error.into())
     }
@@ -57,7 +63,7 @@ pub struct ConnectBlockBody {
 pub type ConnectBlockResponse = ResultResponse<(), RoleWrappedError>;
 #[put("/connect-block")]
 pub async fn connect_block(app_state: AppStateData, body: Json<ConnectBlockBody>, req: HttpRequest) -> Response<ConnectBlockResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.connect_block_to_channel(&body.id, &body.block_id).await {
         Ok(()) => Response::ok_ok(()),
         Err(error) => Response::err_err(error.into())
@@ -74,7 +80,7 @@ pub struct DisconnectBlockBody {
 pub type DisconnectBlockResponse = ResultResponse<(), RoleWrappedError>;
 #[put("/disconnect-block")]
 pub async fn disconnect_block(app_state: AppStateData, body: Json<DisconnectBlockBody>, req: HttpRequest) -> Response<DisconnectBlockResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.disconnect_block_from_channel(&body.id, &body.block_id).await {
         Ok(()) => Response::ok_ok(()),
         Err(error) => Response::err_err(error.into())
@@ -91,7 +97,7 @@ pub struct PinBlockBody {
 pub type PinBlockResponse = ResultResponse<(), RoleWrappedError>;
 #[put("/pin")]
 pub async fn pin_block(app_state: AppStateData, body: Json<PinBlockBody>, req: HttpRequest) -> Response<PinBlockResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.pin_channel_block(&body.id, &body.block_id).await {
         Ok(()) => Response::ok_ok(()),
         Err(error) => Response::err_err(error.into())
@@ -109,7 +115,7 @@ pub struct ChangeDescriptionBody {
 pub type ChangeDescriptionResponse = ResultResponse<(), RoleWrappedError>;
 #[put("/description")]
 pub async fn change_description(app_state: AppStateData, body: Json<ChangeDescriptionBody>, req: HttpRequest) -> Response<ChangeDescriptionResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.change_channel_description(&body.id, body.content.as_str()).await {
         Ok(()) => Response::ok_ok(()),
         Err(error) => Response::err_err(error.into())
@@ -126,13 +132,23 @@ pub struct ChangeLabelsBody {
 pub type ChangeLabelsResponse = ResultResponse<(), RoleWrappedError>;
 #[put("/labels")]
 pub async fn change_labels(app_state: AppStateData, body: Json<ChangeLabelsBody>, req: HttpRequest) -> Response<ChangeLabelsResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.change_channel_labels(&body.id, &body.labels).await {
         Ok(()) => Response::ok_ok(()),
         Err(error) => Response::err_err(error.into())
     }
 } // hmm... a lot of copy-paste-s???
 
+type GetPresenceResponse = ResultResponse<Vec<String>, RoleWrappedError>;
+#[get("/{id}/presence")]
+pub async fn get_presence(app_state: AppStateData, id: Path<String>, req: HttpRequest) -> Response<GetPresenceResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.get_channel_presence(&id).await {
+        Ok(names) => Response::ok_ok(names),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
 #[derive(Deserialize, TS)]
 #[ts(rename = "GetChannelBlocksQuery", export)]
 pub struct GetBlocksQuery {
@@ -143,7 +159,7 @@ pub struct GetBlocksQuery {
 type GetBlocksResponse = ResultResponse<Vec<Block>, RoleWrappedError>;
 #[get("/{id}/blocks")]
 pub async fn get_channel_blocks(app_state: AppStateData, id: Path<String>, query: Query<GetBlocksQuery>, req: HttpRequest) -> Response<GetBlocksResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.get_channel_blocks(&id, &query.limit, &query.offset).await {
         Ok((blocks, errors)) => {
             println!("ERRORS: {errors:?}");
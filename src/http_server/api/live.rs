@@ -1,9 +1,11 @@
-use std::sync::Arc;
-use actix_web::{HttpRequest, web::{self, Path}, HttpResponse, get, Scope};
+use std::{sync::Arc, time::{Duration, Instant}};
+use actix_web::{HttpRequest, web::{self, Path}, HttpResponse, Responder, get, Scope};
+use serde::{Serialize, Deserialize};
 use serde_json::json;
 use tokio::sync::Mutex;
-use crate::{live_channel::{self, LiveMessage}, logger::Logger};
-use super::AppStateData;
+use ts_rs::TS;
+use crate::{live_channel::{self, LiveMessage, PeerError}, logger::Logger, session_pool::{Session, Subscriptions, Block}};
+use super::{AppStateData, Response, errors::{ResultResponse, live::LiveCommandError, roles::RoleWrappedError}};
 use async_trait::async_trait;
 use futures::StreamExt;
 
@@ -29,6 +31,176 @@ use futures::StreamExt;
 pub fn service() -> Scope {
     web::scope("/live")
     .service(connect)
+    .service(connect_to_channel)
+}
+
+/// command sent by client
+#[derive(Deserialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum LiveCommand {
+    /// starts delivering `LiveMessage`s of the channel, if peer is allowed to watch it,
+    /// with `last_seq` (and `epoch` it came from) events missed since it are delivered first
+    Subscribe {
+        channel_id: String,
+        last_seq: Option<u64>,
+        epoch: Option<String>
+    },
+    Unsubscribe {
+        channel_id: String
+    },
+    /// ephemeral, delivered only to peers subscribed right now
+    Signal {
+        channel_id: String,
+        data: String
+    },
+    CreateBlock {
+        content: String
+    },
+    ChangeBlock {
+        id: String,
+        content: String
+    },
+    PinBlock {
+        channel_id: String,
+        block_id: Option<String>
+    },
+    GetBlock {
+        id: String
+    },
+    GetBlocks {
+        channel_id: String,
+        limit: Option<i64>,
+        offset: Option<u64>
+    },
+}
+
+/// `id` is chosen by client and comes back in the response to this request
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct LiveRequest {
+    pub id: u64,
+    pub command: LiveCommand,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum LiveReplyData {
+    BlockCreated {
+        id: String
+    },
+    Block(Block),
+    Blocks(Vec<Block>),
+    /// `seq` of the last event of the channel, when `resync_required` missed events can't be replayed
+    Subscribed {
+        channel_id: String,
+        seq: u64,
+        epoch: String,
+        resync_required: bool
+    },
+    /// channels the peer is subscribed to after unsubscribing
+    Subscriptions(Vec<String>),
+}
+
+/// everything server sends over live socket, every request gets exactly one `Ack`, `Reply` or `Error`
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum LiveServerMessage {
+    Event {
+        channel_id: String,
+        seq: u64,
+        message: LiveMessage
+    },
+    /// first message of every socket, `last_seq` of its events is resumable only together with this `epoch`
+    Ready {
+        epoch: String
+    },
+    /// events after `last_seq` given on connect are gone, client has to reload the channel
+    ResyncRequired {
+        channel_id: String,
+        seq: u64
+    },
+    /// command succeeded and has nothing to return
+    Ack {
+        id: u64
+    },
+    Reply {
+        id: u64,
+        data: LiveReplyData
+    },
+    /// server unsubscribed the peer from the channel, its other subscriptions go on
+    Unsubscribed {
+        channel_id: String,
+        reason: live_channel::UnsubscribeReason
+    },
+    /// channel an event stream resumed with `Last-Event-ID` can't be watched anymore, the rest of them were resumed
+    SubscribeFailed {
+        channel_id: String,
+        error: LiveCommandError
+    },
+    /// `id` is missing when request couldn't be parsed
+    Error {
+        id: Option<u64>,
+        error: LiveCommandError
+    },
+}
+
+/// `last_seq` without `epoch` is from before epochs existed, so it's never resumed
+pub fn cursor(last_seq: Option<u64>, epoch: Option<String>) -> Option<live_channel::Cursor> {
+    last_seq.map(|seq| live_channel::Cursor { epoch: epoch.unwrap_or_default(), seq })
+}
+
+async fn run_command(session: &Session, subscriptions: &Subscriptions, logger: &Logger, command: LiveCommand) -> Result<Option<LiveReplyData>, LiveCommandError> {
+    Ok(match command {
+        LiveCommand::Subscribe { channel_id, last_seq, epoch } => {
+            let subscribed = session.subscribe(subscriptions, &channel_id, cursor(last_seq, epoch).as_ref()).await?;
+            Some(LiveReplyData::Subscribed {
+                channel_id,
+                seq: subscribed.seq,
+                epoch: subscribed.epoch,
+                resync_required: matches!(subscribed.replay, live_channel::Replay::ResyncRequired)
+            })
+        },
+        LiveCommand::Unsubscribe { channel_id } => {
+            subscriptions.unsubscribe(&channel_id).await;
+            Some(LiveReplyData::Subscriptions(subscriptions.channel_ids().await))
+        },
+        LiveCommand::Signal { channel_id, data } => {
+            session.signal(subscriptions, &channel_id, data).await?;
+            None
+        },
+        LiveCommand::CreateBlock { content } => Some(LiveReplyData::BlockCreated { id: session.create_block(&content).await? }),
+        LiveCommand::ChangeBlock { id, content } => {
+            session.change_block(&id, &content).await?;
+            None
+        },
+        LiveCommand::PinBlock { channel_id, block_id } => {
+            session.pin_channel_block(&channel_id, &block_id).await?;
+            None
+        },
+        LiveCommand::GetBlock { id } => Some(LiveReplyData::Block(session.get_block(&id).await?)),
+        LiveCommand::GetBlocks { channel_id, limit, offset } => {
+            let (blocks, errors) = session.get_channel_blocks(&channel_id, &limit, &offset).await?;
+            for error in errors { // blocks that failed to load are left out
+                logger.log(error.to_string());
+            }
+            Some(LiveReplyData::Blocks(blocks))
+        }
+    })
+}
+
+async fn handle_request(session: &Session, subscriptions: &Subscriptions, logger: &Logger, text: &str) -> LiveServerMessage {
+    let request = match serde_json::from_str::<LiveRequest>(text) {
+        Ok(request) => request,
+        Err(error) => return LiveServerMessage::Error { id: None, error: LiveCommandError::InvalidMessage(error.to_string()) }
+    };
+    match run_command(session, subscriptions, logger, request.command).await {
+        Ok(None) => LiveServerMessage::Ack { id: request.id },
+        Ok(Some(data)) => LiveServerMessage::Reply { id: request.id, data },
+        Err(error) => LiveServerMessage::Error { id: Some(request.id), error }
+    }
 }
 
 struct WebsocketPeer {
@@ -36,35 +208,75 @@ struct WebsocketPeer {
     logger: Arc<Logger>
 }
 
-#[async_trait]
-impl live_channel::Peer for WebsocketPeer {
-    async fn receive_message(&self, message: &LiveMessage) {
-        if let Err(error) = self.session.lock().await.text(
-            match serde_json::to_string(&message).map_err(|error| {
+impl WebsocketPeer {
+    async fn send(&self, message: &LiveServerMessage) -> Result<(), PeerError> {
+        let data = match serde_json::to_string(message) {
+            Ok(data) => data,
+            Err(error) => {
                 self.logger.log(error.to_string());
-            }) {
-                Ok(data) => data,
-                Err(()) => return
+                return Ok(());
             }
-        ).await {
-            self.logger.log(error.to_string());
-        }
+        };
+        self.session.lock().await.text(data).await.map_err(|_| PeerError::Closed)
     }
 }
 
-#[get("/{id}")]
-pub async fn connect(app_state: AppStateData, request: HttpRequest, body: web::Payload, id: Path<String>) -> HttpResponse {
-    let session = app_state.session_from_request(&request);
-    let handle = match session.live(&id).await.map_err(|e| {
-        println!("{e:?}");
-        HttpResponse::InternalServerError().json(json!({"message": "this is wip"}))
-    }) {
-        Ok(handle) => handle,
-        Err(error) => return error
+pub fn close_frame(reason: live_channel::CloseReason) -> actix_ws::CloseReason {
+    let (code, description) = match reason {
+        live_channel::CloseReason::Shutdown => (actix_ws::CloseCode::Away, "server is shutting down"),
+        live_channel::CloseReason::Revoked => (actix_ws::CloseCode::Policy, "session was revoked"),
+        live_channel::CloseReason::Renamed => (actix_ws::CloseCode::Restart, "user was renamed"),
+        live_channel::CloseReason::IdleTimeout => (actix_ws::CloseCode::Away, "connection was idle for too long"),
+        live_channel::CloseReason::SlowConsumer => (actix_ws::CloseCode::Again, "too many undelivered messages"),
+        live_channel::CloseReason::Resync => (actix_ws::CloseCode::Restart, "events may have been missed, channels have to be resynced")
     };
+    actix_ws::CloseReason {
+        code,
+        description: Some(description.to_owned())
+    }
+}
+
+#[async_trait]
+impl live_channel::Peer for WebsocketPeer {
+    async fn receive_message(&self, channel_id: &str, seq: u64, message: &LiveMessage) -> Result<(), PeerError> {
+        self.send(&LiveServerMessage::Event { channel_id: channel_id.to_owned(), seq, message: message.clone() }).await
+    }
+
+    async fn unsubscribed(&self, channel_id: &str, reason: live_channel::UnsubscribeReason) -> Result<(), PeerError> {
+        self.send(&LiveServerMessage::Unsubscribed { channel_id: channel_id.to_owned(), reason }).await
+    }
 
-    let (response, session, mut message_stream) = match actix_ws::handle(&request, body).map_err(|e| {
-        println!("{e:?}");
+    async fn close(&self, reason: live_channel::CloseReason) {
+        let session = self.session.lock().await.clone();
+        // peer that is already closed has nothing to be told
+        let _ = session.close(Some(close_frame(reason))).await;
+    }
+}
+
+/// socket without subscriptions, channels are added with `Subscribe` command
+#[get("")]
+pub async fn connect(app_state: AppStateData, request: HttpRequest, body: web::Payload) -> HttpResponse {
+    open_socket(app_state, request, body, None).await
+}
+
+#[derive(Deserialize)]
+pub struct ConnectQuery {
+    pub last_seq: Option<u64>,
+    pub epoch: Option<String>,
+}
+
+/// socket that is subscribed to one channel from the start, `last_seq` and `epoch` resume after a dropped connection
+#[get("/{id}")]
+pub async fn connect_to_channel(app_state: AppStateData, request: HttpRequest, body: web::Payload, id: Path<String>, query: web::Query<ConnectQuery>) -> HttpResponse {
+    let query = query.into_inner();
+    open_socket(app_state, request, body, Some((id.into_inner(), cursor(query.last_seq, query.epoch)))).await
+}
+
+async fn open_socket(app_state: AppStateData, request: HttpRequest, body: web::Payload, channel: Option<(String, Option<live_channel::Cursor>)>) -> HttpResponse {
+    let session = app_state.session_from_request(&request).await;
+
+    let (response, ws_session, mut message_stream) = match actix_ws::handle(&request, body).map_err(|e| {
+        app_state.logger.log(e.to_string());
         HttpResponse::InternalServerError().json(json!({"message": e.to_string()}))
     }) {
         Ok(result) => result,
@@ -72,20 +284,71 @@ pub async fn connect(app_state: AppStateData, request: HttpRequest, body: web::P
     };
 
     let peer = Arc::new(WebsocketPeer {
-        session: Mutex::new(session.clone()),
+        session: Mutex::new(ws_session.clone()),
         logger: app_state.logger.clone()
     });
+    let subscriptions = session.live(peer.clone());
+    let _ = peer.send(&LiveServerMessage::Ready { epoch: subscriptions.epoch() }).await;
 
-    handle.connect(peer).await;
+    if let Some((channel_id, cursor)) = channel {
+        match session.subscribe(&subscriptions, &channel_id, cursor.as_ref()).await {
+            Ok(live_channel::Subscribed { seq, replay: live_channel::Replay::ResyncRequired, .. }) => {
+                let _ = peer.send(&LiveServerMessage::ResyncRequired { channel_id, seq }).await;
+            },
+            Ok(_) => {},
+            Err(error) => {
+                subscriptions.close().await;
+                return Response::<ResultResponse<(), RoleWrappedError>>::err_err(error.into()).respond_to(&request);
+            }
+        }
+    }
 
+    let live_config = app_state.live_config;
     actix_rt::spawn(async move {
-        while let Some(Ok(msg)) = message_stream.next().await {
-            println!("{msg:?}");
-        }
+        let mut heartbeat = tokio::time::interval(Duration::from_secs(live_config.ping_interval.max(1)));
+        let idle_timeout = Duration::from_secs(live_config.idle_timeout);
+        let mut last_seen = Instant::now();
 
-        handle.disconnect().await;
-        let _ = session.close(None).await;
+        let close_reason = loop {
+            tokio::select! {
+                message = message_stream.next() => {
+                    last_seen = Instant::now();
+                    match message {
+                        Some(Ok(actix_ws::Message::Text(text))) => {
+                            let _ = peer.send(&handle_request(&session, &subscriptions, &peer.logger, &text).await).await;
+                        },
+                        Some(Ok(actix_ws::Message::Binary(_))) => {
+                            let _ = peer.send(&LiveServerMessage::Error {
+                                id: None,
+                                error: LiveCommandError::InvalidMessage("binary frames are not supported".to_string())
+                            }).await;
+                        },
+                        Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                            let _ = peer.session.lock().await.pong(&bytes).await;
+                        },
+                        Some(Ok(actix_ws::Message::Close(reason))) => break reason,
+                        Some(Ok(_)) => {}, // pongs only keep connection alive
+                        Some(Err(error)) => break Some(actix_ws::CloseReason {
+                            code: actix_ws::CloseCode::Protocol,
+                            description: Some(error.to_string())
+                        }),
+                        None => break None
+                    }
+                },
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > idle_timeout {
+                        break Some(close_frame(live_channel::CloseReason::IdleTimeout));
+                    }
+                    if peer.session.lock().await.ping(b"").await.is_err() {
+                        break None;
+                    }
+                }
+            }
+        };
+
+        subscriptions.close().await;
+        let _ = ws_session.close(close_reason).await;
     });
 
     response
-}
\ No newline at end of file
+}
@@ -0,0 +1,283 @@
+use std::{sync::{Arc, Mutex as SyncMutex, OnceLock}, collections::{HashMap, BTreeMap}, time::Duration};
+use actix_web::{HttpRequest, web::{self, Path, Json, Query, Bytes}, HttpResponse, Responder, get, post, Scope};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use tokio::sync::{Mutex, mpsc};
+use ts_rs::TS;
+use crate::{live_channel::{self, LiveMessage, PeerError}, logger::Logger, session_pool::Subscriptions, auth_validator::generate_secret};
+use super::{AppStateData, Response, errors::{ResultResponse, live::LiveEventsError}, live::{LiveServerMessage, close_frame, cursor}};
+use async_trait::async_trait;
+use futures::StreamExt;
+
+/// events buffered between peer's writer and the http response
+const STREAM_BUFFER: usize = 16;
+
+pub fn service() -> Scope {
+    web::scope("/live-events")
+    .service(open)
+    .service(subscribe)
+    .service(unsubscribe)
+}
+
+/// open event streams by id, subscriptions are changed through them
+#[derive(Default)]
+pub struct EventStreams(SyncMutex<HashMap<String, Arc<EventStream>>>);
+
+impl EventStreams {
+    fn get(&self, id: &str) -> Option<Arc<EventStream>> {
+        self.0.lock().unwrap().get(id).cloned()
+    }
+}
+
+pub struct EventStream {
+    peer: Arc<SsePeer>,
+    subscriptions: Subscriptions,
+}
+
+/// `id` of every event is epoch followed by `/` and `channel:seq` pairs of every subscribed channel (comma separated),
+/// so `Last-Event-ID` of a reconnecting client resumes all of them
+struct SsePeer {
+    sender: Mutex<Option<mpsc::Sender<Bytes>>>,
+    /// set once peer is registered
+    epoch: OnceLock<String>,
+    cursor: SyncMutex<BTreeMap<String, u64>>,
+    logger: Arc<Logger>,
+}
+
+fn format_event(id: Option<&str>, event: Option<&str>, data: &str) -> Bytes {
+    let mut frame = String::new();
+    if let Some(id) = id {
+        frame.push_str(&format!("id: {id}\n"));
+    }
+    if let Some(event) = event {
+        frame.push_str(&format!("event: {event}\n"));
+    }
+    frame.push_str(&format!("data: {data}\n\n"));
+    Bytes::from(frame)
+}
+
+/// ids without epoch are from before epochs existed, they are never resumed
+fn parse_cursor(value: &str) -> Vec<(String, live_channel::Cursor)> {
+    let (epoch, pairs) = value.split_once('/').unwrap_or(("", value));
+    pairs.split(',')
+    .filter_map(|pair| {
+        let (channel_id, seq) = pair.trim().split_once(':')?;
+        Some((channel_id.to_owned(), live_channel::Cursor { epoch: epoch.to_owned(), seq: seq.parse().ok()? }))
+    })
+    .collect()
+}
+
+impl SsePeer {
+    async fn send_frame(&self, frame: Bytes) -> Result<(), PeerError> {
+        match self.sender.lock().await.as_ref() {
+            Some(sender) => sender.send(frame).await.map_err(|_| PeerError::Closed),
+            None => Err(PeerError::Closed)
+        }
+    }
+
+    fn message_frame(&self, message: &LiveServerMessage) -> Option<Bytes> {
+        match serde_json::to_string(message) {
+            Ok(data) => Some(format_event(None, None, &data)),
+            Err(error) => {
+                self.logger.log(error.to_string());
+                None
+            }
+        }
+    }
+
+    fn track(&self, channel_id: &str, seq: u64) {
+        self.cursor.lock().unwrap().insert(channel_id.to_owned(), seq);
+    }
+
+    fn untrack(&self, channel_id: &str) {
+        self.cursor.lock().unwrap().remove(channel_id);
+    }
+}
+
+#[async_trait]
+impl live_channel::Peer for SsePeer {
+    async fn receive_message(&self, channel_id: &str, seq: u64, message: &LiveMessage) -> Result<(), PeerError> {
+        self.track(channel_id, seq);
+        let pairs = self.cursor.lock().unwrap().iter()
+        .map(|(channel_id, seq)| format!("{channel_id}:{seq}"))
+        .collect::<Vec<String>>()
+        .join(",");
+        let id = format!("{}/{pairs}", self.epoch.get().map_or("", String::as_str));
+        let data = match serde_json::to_string(&LiveServerMessage::Event { channel_id: channel_id.to_owned(), seq, message: message.clone() }) {
+            Ok(data) => data,
+            Err(error) => {
+                self.logger.log(error.to_string());
+                return Ok(());
+            }
+        };
+        self.send_frame(format_event(Some(&id), None, &data)).await
+    }
+
+    /// channel is left out of `Last-Event-ID` from now on, so a reconnecting client doesn't resume it
+    async fn unsubscribed(&self, channel_id: &str, reason: live_channel::UnsubscribeReason) -> Result<(), PeerError> {
+        self.untrack(channel_id);
+        match self.message_frame(&LiveServerMessage::Unsubscribed { channel_id: channel_id.to_owned(), reason }) {
+            Some(frame) => self.send_frame(frame).await,
+            None => Ok(())
+        }
+    }
+
+    /// sends `close` event and ends the response
+    async fn close(&self, reason: live_channel::CloseReason) {
+        let mut sender = self.sender.lock().await;
+        if let Some(sender) = sender.take() {
+            let description = close_frame(reason).description.unwrap_or_default();
+            let _ = sender.send(format_event(None, Some("close"), &json!({"reason": description}).to_string())).await;
+        }
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "LiveEventsQuery")]
+pub struct OpenQuery {
+    /// channels to subscribe to right away, comma separated
+    pub channels: Option<String>,
+}
+
+/// `text/event-stream` alternative to live socket, first event is `ready` with `stream_id`,
+/// subscriptions are then changed with `/{stream_id}/subscribe` and `/{stream_id}/unsubscribe`
+#[get("")]
+pub async fn open(app_state: AppStateData, request: HttpRequest, query: Query<OpenQuery>) -> HttpResponse {
+    let session = app_state.session_from_request(&request).await;
+
+    let (sender, mut receiver) = mpsc::channel(STREAM_BUFFER);
+    let peer = Arc::new(SsePeer {
+        sender: Mutex::new(Some(sender)),
+        epoch: OnceLock::new(),
+        cursor: SyncMutex::new(BTreeMap::new()),
+        logger: app_state.logger.clone()
+    });
+    let subscriptions = session.live(peer.clone());
+    let epoch = subscriptions.epoch();
+    let _ = peer.epoch.set(epoch.clone());
+
+    // channels from `Last-Event-ID` are resumed (or reported when they can't be), the ones from query start fresh
+    let mut channels: Vec<(String, Option<live_channel::Cursor>)> = request.headers().get("Last-Event-ID")
+    .and_then(|value| value.to_str().ok())
+    .map(parse_cursor)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(channel_id, cursor)| (channel_id, Some(cursor)))
+    .collect();
+    for channel_id in query.channels.as_deref().unwrap_or_default().split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        if !channels.iter().any(|(id, _)| id == channel_id) {
+            channels.push((channel_id.to_owned(), None));
+        }
+    }
+
+    // nobody reads the buffer until the response is returned, so these go ahead of it in the body
+    let stream_id = generate_secret();
+    let mut initial = vec![format_event(None, Some("ready"), &json!({"stream_id": stream_id, "epoch": epoch}).to_string())];
+    for (channel_id, cursor) in channels {
+        match session.subscribe(&subscriptions, &channel_id, cursor.as_ref()).await {
+            Ok(subscribed) => {
+                peer.track(&channel_id, subscribed.seq);
+                if let live_channel::Replay::ResyncRequired = subscribed.replay {
+                    initial.extend(peer.message_frame(&LiveServerMessage::ResyncRequired { channel_id, seq: subscribed.seq }));
+                }
+            },
+            Err(error) if cursor.is_some() => {
+                initial.extend(peer.message_frame(&LiveServerMessage::SubscribeFailed { channel_id, error: error.into() }));
+            },
+            Err(error) => {
+                subscriptions.close().await;
+                return Response::<ResultResponse<(), LiveEventsError>>::err_err(error.into()).respond_to(&request);
+            }
+        }
+    }
+
+    let stream = Arc::new(EventStream { peer: peer.clone(), subscriptions });
+    app_state.event_streams.0.lock().unwrap().insert(stream_id.clone(), stream.clone());
+
+    // comments keep proxies from closing the response and tell when client is gone
+    let ping_interval = Duration::from_secs(app_state.live_config.ping_interval.max(1));
+    let app_state = app_state.clone();
+    actix_rt::spawn(async move {
+        let mut heartbeat = tokio::time::interval(ping_interval);
+        loop {
+            heartbeat.tick().await;
+            if stream.peer.send_frame(Bytes::from_static(b": ping\n\n")).await.is_err() {
+                break;
+            }
+        }
+        app_state.event_streams.0.lock().unwrap().remove(&stream_id);
+        stream.subscriptions.close().await;
+    });
+
+    let body = futures::stream::iter(initial)
+    .chain(futures::stream::poll_fn(move |context| receiver.poll_recv(context)))
+    .map(Ok::<_, actix_web::Error>);
+    HttpResponse::Ok()
+    .content_type("text/event-stream")
+    .insert_header(("Cache-Control", "no-cache"))
+    .insert_header(("X-Accel-Buffering", "no"))
+    .streaming(body)
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "LiveEventsSubscribeBody")]
+pub struct SubscribeBody {
+    pub channel_id: String,
+    /// last `seq` client got from the channel, missed events are sent first
+    pub last_seq: Option<u64>,
+    /// epoch `last_seq` is from
+    pub epoch: Option<String>,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export, rename = "LiveEventsSubscribed")]
+pub struct Subscribed {
+    pub seq: u64,
+    pub epoch: String,
+    pub resync_required: bool,
+}
+
+type SubscribeResponse = ResultResponse<Subscribed, LiveEventsError>;
+#[post("/{stream_id}/subscribe")]
+pub async fn subscribe(app_state: AppStateData, stream_id: Path<String>, body: Json<SubscribeBody>, req: HttpRequest) -> Response<SubscribeResponse> {
+    let session = app_state.session_from_request(&req).await;
+    let stream = match app_state.event_streams.get(&stream_id) {
+        Some(stream) => stream,
+        None => return Response::err_err(LiveEventsError::StreamNotFound)
+    };
+    let body = body.into_inner();
+    match session.subscribe(&stream.subscriptions, &body.channel_id, cursor(body.last_seq, body.epoch).as_ref()).await {
+        Ok(subscribed) => {
+            stream.peer.track(&body.channel_id, subscribed.seq);
+            Response::ok_ok(Subscribed {
+                seq: subscribed.seq,
+                epoch: subscribed.epoch,
+                resync_required: matches!(subscribed.replay, live_channel::Replay::ResyncRequired)
+            })
+        },
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "LiveEventsUnsubscribeBody")]
+pub struct UnsubscribeBody {
+    pub channel_id: String,
+}
+
+type UnsubscribeResponse = ResultResponse<bool, LiveEventsError>;
+#[post("/{stream_id}/unsubscribe")]
+pub async fn unsubscribe(app_state: AppStateData, stream_id: Path<String>, body: Json<UnsubscribeBody>, req: HttpRequest) -> Response<UnsubscribeResponse> {
+    let session = app_state.session_from_request(&req).await;
+    let stream = match app_state.event_streams.get(&stream_id) {
+        Some(stream) => stream,
+        None => return Response::err_err(LiveEventsError::StreamNotFound)
+    };
+    match session.unsubscribe(&stream.subscriptions, &body.channel_id).await {
+        Ok(unsubscribed) => {
+            stream.peer.untrack(&body.channel_id);
+            Response::ok_ok(unsubscribed)
+        },
+        Err(error) => Response::err_err(error.into())
+    }
+}
@@ -7,8 +7,15 @@ mod channels;
 mod users;
 mod roles;
 mod live;
+mod live_events;
 mod auth;
 mod activity_table;
+mod tokens;
+mod admin;
+mod well_known;
+
+pub use well_known::service as well_known_service;
+pub use live_events::EventStreams;
 
 pub fn service() -> Scope {
     web::scope("/api")
@@ -18,5 +25,8 @@ pub fn service() -> Scope {
     .service(roles::service())
     .service(auth::service())
     .service(activity_table::service())
+    .service(tokens::service())
+    .service(admin::service())
     .service(live::service())
+    .service(live_events::service())
 }
\ No newline at end of file
@@ -15,7 +15,7 @@ pub fn service() -> Scope {
 type GetOneResponse = ResultResponse<Role, GeneralError>;
 #[get("/{id}")]
 pub async fn get_one(app_state: AppStateData, id: Path<String>, req: HttpRequest) -> Response<GetOneResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.get_role(&id).await {
         Ok(role) => Response::ok_ok(role),
         Err(error) => Response::err_err(error.into())
@@ -34,7 +34,7 @@ pub struct CreateBoby {
 type CreateResponse = ResultResponse<String, CreateRoleError>;
 #[post("/create")]
 pub async fn create(app_state: AppStateData, body: Json<CreateBoby>, req: HttpRequest) -> Response<CreateResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.create_role(&body.name, &body.extends, &body.editors, &body.permissions).await {
         Ok(id) => Response::ok_ok(id),
         Err(error) => Response::err_err(error.into())
@@ -53,7 +53,7 @@ pub struct ChangeBody {
 type ChangeRoleResponse = ResultResponse<(), GeneralError>;
 #[put("/change/{id}")]
 pub async fn change(app_state: AppStateData, id: Path<String>, body: Json<ChangeBody>, req: HttpRequest) -> Response<ChangeRoleResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.change_role(id.as_str(), &body.name, &body.extends, &body.editors, body.permissions.clone()).await {
         Ok(()) => Response::ok_ok(()),
         Err(error) => Response::err_err(error.into())
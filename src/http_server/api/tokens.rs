@@ -0,0 +1,57 @@
+use actix_web::{Scope, web::{self, Json}, post, get, HttpRequest};
+use serde::Deserialize;
+use ts_rs::TS;
+use crate::{http_server::{AppStateData, errors::{ResultResponse, general::GeneralError, tokens::CreateTokenError}}, session_pool::{AccessToken, CreatedAccessToken}, db_pool::TokenScope};
+use super::Response;
+
+pub fn service() -> Scope {
+    web::scope("/tokens")
+    .service(get_tokens)
+    .service(create_token)
+    .service(revoke_token)
+}
+
+type GetTokensResponse = ResultResponse<Vec<AccessToken>, GeneralError>;
+#[get("")]
+pub async fn get_tokens(app_state: AppStateData, req: HttpRequest) -> Response<GetTokensResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.get_access_tokens().await {
+        Ok(tokens) => Response::ok_ok(tokens),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "CreateAccessTokenBody")]
+pub struct CreateTokenBody {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    /// in seconds, positive, token never expires without it
+    pub lifetime: Option<i64>,
+}
+
+type CreateTokenResponse = ResultResponse<CreatedAccessToken, CreateTokenError>;
+#[post("/create")]
+pub async fn create_token(app_state: AppStateData, body: Json<CreateTokenBody>, req: HttpRequest) -> Response<CreateTokenResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.create_access_token(&body.name, &body.scopes, body.lifetime).await {
+        Ok(token) => Response::ok_ok(token),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "RevokeAccessTokenBody")]
+pub struct RevokeTokenBody {
+    pub id: String,
+}
+
+type RevokeTokenResponse = ResultResponse<(), GeneralError>;
+#[post("/revoke")]
+pub async fn revoke_token(app_state: AppStateData, body: Json<RevokeTokenBody>, req: HttpRequest) -> Response<RevokeTokenResponse> {
+    let session = app_state.session_from_request(&req).await;
+    match session.revoke_access_token(&body.id).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
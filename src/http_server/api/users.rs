@@ -1,18 +1,113 @@
-use actix_web::{Scope, web::{self, Path}, get, HttpRequest};
-use super::{AppStateData, Response, errors::{general::GeneralError, ResultResponse}};
-use crate::session_pool::User;
+use actix_web::{Scope, web::{self, Path, Json, Bytes}, get, post, HttpRequest, HttpResponse, Responder, cookie::Cookie, http::header};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use super::{AppStateData, Response, errors::{self, ResultResponse, general::GeneralError}};
+use crate::{session_pool::{User, MAX_AVATAR_SIZE}, db_pool::{Profile, ProfileAvatar, ProfileVisibility}};
 
 pub fn service() -> Scope {
     web::scope("/users")
+    .app_data(web::PayloadConfig::new(MAX_AVATAR_SIZE))
+    .service(change_name)
+    .service(update_profile)
+    .service(upload_avatar)
+    .service(get_avatar)
     .service(get_one)
 }
 
-type GetOneResponse = ResultResponse<User, GeneralError>;
+type GetOneResponse = ResultResponse<User, errors::users::GetUserError>;
 #[get("/{name}")]
 pub async fn get_one(app_state: AppStateData, name: Path<String>, req: HttpRequest) -> Response<GetOneResponse> {
-    let session = app_state.session_from_request(&req);
+    let session = app_state.session_from_request(&req).await;
     match session.get_user(&name).await {
         Ok(user) => Response::ok_ok(user),
         Err(error) => Response::err_err(error.into())
     }
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "UsersChangeNameBody")]
+pub struct ChangeNameBody {
+    pub name: String,
+}
+
+/// responds with tokens that carry the new name
+#[post("/change-name")]
+pub async fn change_name(app_state: AppStateData, body: Json<ChangeNameBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::users::ChangeNameError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.change_name(&body.name).await {
+        Ok(tokens) => Response::new(
+            HttpResponse::Ok()
+            .cookie(
+                Cookie::build("access-token", tokens.access)
+                .http_only(true)
+                .same_site(actix_web::cookie::SameSite::None)
+                .finish()
+            )
+            .cookie(
+                Cookie::build("key-token", tokens.key)
+                .same_site(actix_web::cookie::SameSite::None)
+                .finish()
+            ).take(),
+            ResultResponse::Ok(())
+        ),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export, rename = "UsersUpdateProfileBody")]
+pub struct UpdateProfileBody {
+    pub display_name: Option<String>,
+    pub bio: String,
+    /// image avatar has to be uploaded with `/users/avatar/upload` first
+    pub avatar: Option<ProfileAvatar>,
+    pub links: Vec<String>,
+    pub pinned_block: Option<String>,
+    pub visibility: ProfileVisibility,
+}
+
+#[post("/profile/update")]
+pub async fn update_profile(app_state: AppStateData, body: Json<UpdateProfileBody>, req: HttpRequest) -> Response<ResultResponse<(), errors::users::ProfileError>> {
+    let session = app_state.session_from_request(&req).await;
+    let body = body.into_inner();
+    let profile = Profile {
+        display_name: body.display_name,
+        bio: body.bio,
+        avatar: body.avatar,
+        links: body.links,
+        pinned_block: body.pinned_block,
+        visibility: body.visibility
+    };
+    match session.update_profile(profile).await {
+        Ok(()) => Response::ok_ok(()),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export, rename = "UsersUploadAvatarResponse")]
+pub struct UploadAvatarResponse {
+    pub id: String,
+}
+
+/// raw png, jpeg, gif or webp as request body
+#[post("/avatar/upload")]
+pub async fn upload_avatar(app_state: AppStateData, body: Bytes, req: HttpRequest) -> Response<ResultResponse<UploadAvatarResponse, errors::users::ProfileError>> {
+    let session = app_state.session_from_request(&req).await;
+    match session.upload_avatar(body.to_vec()).await {
+        Ok(id) => Response::ok_ok(UploadAvatarResponse { id }),
+        Err(error) => Response::err_err(error.into())
+    }
+}
+
+#[get("/avatars/{id}")]
+pub async fn get_avatar(app_state: AppStateData, id: Path<String>, req: HttpRequest) -> HttpResponse {
+    let session = app_state.session_from_request(&req).await;
+    match session.get_avatar(&id).await {
+        Ok((mime, data)) => HttpResponse::Ok()
+        .content_type(mime)
+        .insert_header((header::CACHE_CONTROL, "private, max-age=3600"))
+        .body(data),
+        Err(error) => Response::<ResultResponse<(), GeneralError>>::err_err(error.into()).respond_to(&req)
+    }
+}
@@ -0,0 +1,14 @@
+use actix_web::{Scope, web::{self, Json}, get};
+use crate::{http_server::AppStateData, auth_validator::Jwks};
+
+/// served outside of `/api`, where other services expect it
+pub fn service() -> Scope {
+    web::scope("/.well-known")
+    .service(jwks)
+}
+
+/// plain jwks document instead of `ResultResponse`, so that standard jwt libraries can consume it
+#[get("/jwks.json")]
+pub async fn jwks(app_state: AppStateData) -> Json<Jwks> {
+    Json(app_state.auth_validator.jwks())
+}
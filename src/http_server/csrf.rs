@@ -0,0 +1,106 @@
+use std::{future::{ready, Ready}, rc::Rc, sync::Arc};
+use actix_web::{body::EitherBody, dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, http::{Method, header}};
+use futures_util::future::LocalBoxFuture;
+use super::errors::{ResultResponse, AsBuilder, general::GeneralError};
+
+/// scheme, host and port of url, as browsers send it in `Origin`
+pub fn origin_of(url: &str) -> Option<String> {
+    let origin = reqwest::Url::parse(url).ok()?.origin();
+    match origin.is_tuple() {
+        true => Some(origin.ascii_serialization()),
+        false => None
+    }
+}
+
+/// origin of request from `Origin`, or from `Referer` when browser left it out
+fn request_origin(request: &ServiceRequest) -> Option<String> {
+    let headers = request.headers();
+    if let Some(origin) = headers.get(header::ORIGIN).and_then(|value| value.to_str().ok()) {
+        return Some(origin.to_string());
+    }
+    headers.get(header::REFERER)
+    .and_then(|value| value.to_str().ok())
+    .and_then(origin_of)
+}
+
+/// only cookies are sent by browsers on their own, bearer tokens have to be attached by the caller
+fn has_cookie_credentials(request: &ServiceRequest) -> bool {
+    !request.headers().contains_key(header::AUTHORIZATION)
+    && (request.cookie("access-token").is_some() || request.cookie("key-token").is_some())
+}
+
+/// browsers don't apply same origin policy to websockets, so their upgrades are checked like state changing requests
+fn is_websocket_upgrade(request: &ServiceRequest) -> bool {
+    request.headers().get(header::UPGRADE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// rejects state changing requests and websocket upgrades from origins that aren't allowed,
+/// requests without origin and referer are let through only when they don't carry auth cookies
+pub struct Csrf {
+    allowed_origins: Arc<Vec<String>>,
+}
+
+impl Csrf {
+    pub fn new(allowed_origins: Arc<Vec<String>>) -> Self {
+        Self { allowed_origins }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Csrf
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware { service: Rc::new(service), allowed_origins: self.allowed_origins.clone() }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+    allowed_origins: Arc<Vec<String>>,
+}
+
+impl<S> CsrfMiddleware<S> {
+    fn is_allowed(&self, request: &ServiceRequest) -> bool {
+        if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) && !is_websocket_upgrade(request) {
+            return true;
+        }
+        match request_origin(request) {
+            Some(origin) => self.allowed_origins.iter().any(|allowed| allowed == &origin),
+            None => !has_cookie_credentials(request)
+        }
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        if !self.is_allowed(&request) {
+            let error = GeneralError::CrossOrigin;
+            let response = error.builder().json(ResultResponse::<(), GeneralError>::Err(error));
+            return Box::pin(async move { Ok(request.into_response(response).map_into_right_body()) });
+        }
+        let service = self.service.clone();
+        Box::pin(async move {
+            Ok(service.call(request).await?.map_into_left_body())
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use actix_web::{HttpResponse, HttpResponseBuilder};
+use actix_web::{HttpResponse, HttpResponseBuilder, http::header};
 use serde::Serialize;
 use ts_rs::TS;
 use crate::session_pool;
@@ -12,6 +12,7 @@ pub enum JoinError {
     BadNameLength,
     TooShortPassword,
     TooLongPassword,
+    BreachedPassword,
     NameTaken,
     EmailTaken,
     General (GeneralError),
@@ -35,7 +36,8 @@ impl From<session_pool::RegisterError> for JoinError {
             session_pool::RegisterError::InvaildNameChars => Self::InvaildNameChars,
             session_pool::RegisterError::NameTaken => Self::NameTaken,
             session_pool::RegisterError::TooLongPassword => Self::TooLongPassword,
-            session_pool::RegisterError::TooShortPassword => Self::TooShortPassword
+            session_pool::RegisterError::TooShortPassword => Self::TooShortPassword,
+            session_pool::RegisterError::BreachedPassword => Self::BreachedPassword
         }
     }
 }
@@ -45,13 +47,22 @@ impl From<session_pool::RegisterError> for JoinError {
 #[serde(tag = "is", content = "data")]
 pub enum LoginError {
     General(GeneralError),
-    InvalidCredentials
+    InvalidCredentials,
+    InvalidChallenge,
+    InvalidCode,
+    /// in seconds, also sent as `Retry-After`
+    Locked { retry_after: i64 }
 }
 impl AsBuilder for LoginError {
     fn builder(&self) -> HttpResponseBuilder {
         match self {
             Self::General(error) => error.builder(),
-            Self::InvalidCredentials => HttpResponse::Forbidden()
+            Self::Locked { retry_after } => {
+                let mut builder = HttpResponse::TooManyRequests();
+                builder.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+                builder
+            },
+            _ => HttpResponse::Forbidden()
         }
     }
 }
@@ -60,7 +71,202 @@ impl From<session_pool::LoginError> for LoginError {
         match value {
             session_pool::LoginError::General(error) => Self::General(error.into()),
             session_pool::LoginError::InfoAsTokens(_) => Self::General(GeneralError::Internal),
-            session_pool::LoginError::InvalidCredentials => Self::InvalidCredentials
+            session_pool::LoginError::InvalidCredentials => Self::InvalidCredentials,
+            session_pool::LoginError::InvalidChallenge => Self::InvalidChallenge,
+            session_pool::LoginError::InvalidCode => Self::InvalidCode,
+            session_pool::LoginError::Locked { retry_after } => Self::Locked { retry_after },
+            session_pool::LoginError::SecondFactor(_) => Self::General(GeneralError::Internal) // logged by session
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Serialize, TS)]
+#[ts(export, rename = "AuthRefreshError")]
+#[serde(tag = "is", content = "data")]
+pub enum RefreshError {
+    General(GeneralError),
+    InvalidToken,
+    Revoked,
+    Reused
+}
+impl AsBuilder for RefreshError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            _ => HttpResponse::Unauthorized()
+        }
+    }
+}
+impl From<session_pool::RefreshError> for RefreshError {
+    fn from(value: session_pool::RefreshError) -> Self {
+        match value {
+            session_pool::RefreshError::General(error) => Self::General(error.into()),
+            session_pool::RefreshError::InfoAsTokens(_) => Self::General(GeneralError::Internal),
+            session_pool::RefreshError::InvalidToken => Self::InvalidToken,
+            session_pool::RefreshError::Revoked => Self::Revoked,
+            session_pool::RefreshError::Reused => Self::Reused
+        }
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum ChangePasswordError {
+    General(GeneralError),
+    InvalidCredentials,
+    TooShortPassword,
+    TooLongPassword,
+    BreachedPassword,
+}
+impl AsBuilder for ChangePasswordError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::InvalidCredentials => HttpResponse::Forbidden(),
+            _ => HttpResponse::BadRequest()
+        }
+    }
+}
+impl From<session_pool::ChangePasswordError> for ChangePasswordError {
+    fn from(value: session_pool::ChangePasswordError) -> Self {
+        match value {
+            session_pool::ChangePasswordError::General(error) => Self::General(error.into()),
+            session_pool::ChangePasswordError::Hashing(_) => Self::General(GeneralError::Internal),
+            session_pool::ChangePasswordError::InvalidCredentials => Self::InvalidCredentials,
+            session_pool::ChangePasswordError::TooShortPassword => Self::TooShortPassword,
+            session_pool::ChangePasswordError::TooLongPassword => Self::TooLongPassword,
+            session_pool::ChangePasswordError::BreachedPassword => Self::BreachedPassword
+        }
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum ResetPasswordError {
+    General(GeneralError),
+    InvalidToken,
+    TooShortPassword,
+    TooLongPassword,
+    BreachedPassword,
+}
+impl AsBuilder for ResetPasswordError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::InvalidToken => HttpResponse::Forbidden(),
+            _ => HttpResponse::BadRequest()
+        }
+    }
+}
+impl From<session_pool::ResetPasswordError> for ResetPasswordError {
+    fn from(value: session_pool::ResetPasswordError) -> Self {
+        match value {
+            session_pool::ResetPasswordError::General(error) => Self::General(error.into()),
+            session_pool::ResetPasswordError::Hashing(_) => Self::General(GeneralError::Internal),
+            session_pool::ResetPasswordError::InvalidToken => Self::InvalidToken,
+            session_pool::ResetPasswordError::TooShortPassword => Self::TooShortPassword,
+            session_pool::ResetPasswordError::TooLongPassword => Self::TooLongPassword,
+            session_pool::ResetPasswordError::BreachedPassword => Self::BreachedPassword
+        }
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum VerifyEmailError {
+    General(GeneralError),
+    InvalidToken,
+    AlreadyVerified,
+}
+impl AsBuilder for VerifyEmailError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::InvalidToken => HttpResponse::Forbidden(),
+            Self::AlreadyVerified => HttpResponse::Conflict()
+        }
+    }
+}
+impl From<session_pool::VerifyEmailError> for VerifyEmailError {
+    fn from(value: session_pool::VerifyEmailError) -> Self {
+        match value {
+            session_pool::VerifyEmailError::General(error) => Self::General(error.into()),
+            session_pool::VerifyEmailError::InfoAsTokens(_) => Self::General(GeneralError::Internal),
+            session_pool::VerifyEmailError::Mail(_) => Self::General(GeneralError::Internal), // logged by session
+            session_pool::VerifyEmailError::InvalidToken => Self::InvalidToken,
+            session_pool::VerifyEmailError::AlreadyVerified => Self::AlreadyVerified
+        }
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum TwoFactorError {
+    General(GeneralError),
+    AlreadyEnabled,
+    NotEnrolled,
+    InvalidCode,
+    InvalidCredentials,
+}
+impl AsBuilder for TwoFactorError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::AlreadyEnabled => HttpResponse::Conflict(),
+            Self::NotEnrolled => HttpResponse::BadRequest(),
+            Self::InvalidCode | Self::InvalidCredentials => HttpResponse::Forbidden()
+        }
+    }
+}
+impl From<session_pool::TwoFactorError> for TwoFactorError {
+    fn from(value: session_pool::TwoFactorError) -> Self {
+        match value {
+            session_pool::TwoFactorError::General(error) => Self::General(error.into()),
+            session_pool::TwoFactorError::Totp(_) => Self::General(GeneralError::Internal), // logged by session
+            session_pool::TwoFactorError::AlreadyEnabled => Self::AlreadyEnabled,
+            session_pool::TwoFactorError::NotEnrolled => Self::NotEnrolled,
+            session_pool::TwoFactorError::InvalidCode => Self::InvalidCode,
+            session_pool::TwoFactorError::InvalidCredentials => Self::InvalidCredentials
+        }
+    }
+}
+#[derive(Serialize, TS)]
+#[ts(export, rename = "AuthOidcError")]
+#[serde(tag = "is", content = "data")]
+pub enum OidcError {
+    General(GeneralError),
+    Disabled,
+    InvalidState,
+    Provider,
+    MissingEmail,
+    EmailTaken,
+    NameUnavailable,
+}
+impl AsBuilder for OidcError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::Disabled => HttpResponse::NotFound(),
+            Self::Provider => HttpResponse::BadGateway(),
+            Self::EmailTaken | Self::NameUnavailable => HttpResponse::Conflict(),
+            _ => HttpResponse::BadRequest()
+        }
+    }
+}
+impl From<session_pool::OidcError> for OidcError {
+    fn from(value: session_pool::OidcError) -> Self {
+        match value {
+            session_pool::OidcError::General(error) => Self::General(error.into()),
+            session_pool::OidcError::InfoAsTokens(_) => Self::General(GeneralError::Internal),
+            session_pool::OidcError::Disabled => Self::Disabled,
+            session_pool::OidcError::InvalidState => Self::InvalidState,
+            session_pool::OidcError::Provider(_) => Self::Provider, // logged by session
+            session_pool::OidcError::MissingEmail => Self::MissingEmail,
+            session_pool::OidcError::EmailTaken => Self::EmailTaken,
+            session_pool::OidcError::NameUnavailable => Self::NameUnavailable
+        }
+    }
+}
@@ -10,12 +10,20 @@ use super::AsBuilder;
 pub enum GeneralError {
     Internal,
     Unauthorized,
+    Unverified,
+    Forbidden,
+    RateLimited,
+    CrossOrigin,
 }
 impl AsBuilder for GeneralError {
     fn builder(&self) -> HttpResponseBuilder {
         match self {
             Self::Internal => HttpResponse::InternalServerError(),
-            Self::Unauthorized => HttpResponse::Forbidden()
+            Self::Unauthorized => HttpResponse::Forbidden(),
+            Self::Unverified => HttpResponse::Forbidden(),
+            Self::Forbidden => HttpResponse::Forbidden(),
+            Self::RateLimited => HttpResponse::TooManyRequests(),
+            Self::CrossOrigin => HttpResponse::Forbidden()
         }
     }
 }
@@ -26,7 +34,9 @@ impl From<session_pool::Error> for GeneralError {
                 println!("{error}");
                 Self::Internal
             },
-            session_pool::Error::Unauthorized => Self::Unauthorized
+            session_pool::Error::Unauthorized => Self::Unauthorized,
+            session_pool::Error::Unverified => Self::Unverified,
+            session_pool::Error::Forbidden => Self::Forbidden
         }
     }
 }
@@ -0,0 +1,78 @@
+use actix_web::{HttpResponse, HttpResponseBuilder};
+use serde::Serialize;
+use ts_rs::TS;
+use crate::session_pool;
+use super::{AsBuilder, general::GeneralError, roles::RoleError};
+
+/// error reply to a command sent over live socket
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum LiveCommandError {
+    General(GeneralError),
+    Role(RoleError),
+    /// frame isn't a valid `LiveRequest`
+    InvalidMessage(String),
+    /// signals can be sent only to subscribed channels
+    NotSubscribed,
+    SignalTooLarge {
+        max: usize
+    },
+    SignalRateLimited,
+}
+impl From<session_pool::Error> for LiveCommandError {
+    fn from(value: session_pool::Error) -> Self {
+        Self::General(value.into())
+    }
+}
+impl From<session_pool::SignalError> for LiveCommandError {
+    fn from(value: session_pool::SignalError) -> Self {
+        match value {
+            session_pool::SignalError::General(error) => Self::General(error.into()),
+            session_pool::SignalError::NotSubscribed => Self::NotSubscribed,
+            session_pool::SignalError::TooLarge(max) => Self::SignalTooLarge { max },
+            session_pool::SignalError::RateLimited => Self::SignalRateLimited
+        }
+    }
+}
+impl From<session_pool::RoleWrappedError> for LiveCommandError {
+    fn from(value: session_pool::RoleWrappedError) -> Self {
+        match value {
+            session_pool::RoleWrappedError::General(error) => Self::General(error.into()),
+            session_pool::RoleWrappedError::Role(error) => Self::Role(error.into())
+        }
+    }
+}
+
+/// error of event stream (sse) requests
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "is", content = "data")]
+pub enum LiveEventsError {
+    General(GeneralError),
+    Role(RoleError),
+    /// stream was closed or never existed
+    StreamNotFound,
+}
+impl AsBuilder for LiveEventsError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::Role(error) => error.builder(),
+            Self::StreamNotFound => HttpResponse::NotFound()
+        }
+    }
+}
+impl From<session_pool::Error> for LiveEventsError {
+    fn from(value: session_pool::Error) -> Self {
+        Self::General(value.into())
+    }
+}
+impl From<session_pool::RoleWrappedError> for LiveEventsError {
+    fn from(value: session_pool::RoleWrappedError) -> Self {
+        match value {
+            session_pool::RoleWrappedError::General(error) => Self::General(error.into()),
+            session_pool::RoleWrappedError::Role(error) => Self::Role(error.into())
+        }
+    }
+}
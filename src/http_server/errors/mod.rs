@@ -4,7 +4,10 @@ use ts_rs::TS;
 
 pub mod auth;
 pub mod general;
+pub mod live;
 pub mod roles;
+pub mod tokens;
+pub mod users;
 
 pub trait AsBuilder {
     fn builder(&self) -> HttpResponseBuilder;
@@ -0,0 +1,29 @@
+use actix_web::{HttpResponse, HttpResponseBuilder};
+use serde::Serialize;
+use ts_rs::TS;
+use crate::session_pool;
+use super::{general::GeneralError, AsBuilder};
+
+#[derive(Serialize, TS)]
+#[ts(export, rename = "TokensCreateError")]
+#[serde(tag = "is", content = "data")]
+pub enum CreateTokenError {
+    General(GeneralError),
+    InvalidLifetime,
+}
+impl AsBuilder for CreateTokenError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::InvalidLifetime => HttpResponse::BadRequest()
+        }
+    }
+}
+impl From<session_pool::CreateAccessTokenError> for CreateTokenError {
+    fn from(value: session_pool::CreateAccessTokenError) -> Self {
+        match value {
+            session_pool::CreateAccessTokenError::General(error) => Self::General(error.into()),
+            session_pool::CreateAccessTokenError::InvalidLifetime => Self::InvalidLifetime
+        }
+    }
+}
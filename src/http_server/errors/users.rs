@@ -0,0 +1,102 @@
+use actix_web::{HttpResponse, HttpResponseBuilder, http::header};
+use serde::Serialize;
+use ts_rs::TS;
+use crate::session_pool;
+use super::{general::GeneralError, AsBuilder};
+
+#[derive(Serialize, TS)]
+#[ts(export, rename = "UsersGetError")]
+#[serde(tag = "is", content = "data")]
+pub enum GetUserError {
+    General(GeneralError),
+    /// also sent as permanent redirect to the current name
+    Renamed { name: String },
+}
+impl AsBuilder for GetUserError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::Renamed { name } => {
+                let mut builder = HttpResponse::PermanentRedirect();
+                builder.insert_header((header::LOCATION, format!("/api/users/{name}")));
+                builder
+            }
+        }
+    }
+}
+impl From<session_pool::GetUserError> for GetUserError {
+    fn from(value: session_pool::GetUserError) -> Self {
+        match value {
+            session_pool::GetUserError::General(error) => Self::General(error.into()),
+            session_pool::GetUserError::Renamed(name) => Self::Renamed { name }
+        }
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export, rename = "UsersChangeNameError")]
+#[serde(tag = "is", content = "data")]
+pub enum ChangeNameError {
+    General(GeneralError),
+    InvaildNameChars,
+    BadNameLength,
+    NameTaken,
+}
+impl AsBuilder for ChangeNameError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            _ => HttpResponse::BadRequest()
+        }
+    }
+}
+impl From<session_pool::ChangeNameError> for ChangeNameError {
+    fn from(value: session_pool::ChangeNameError) -> Self {
+        match value {
+            session_pool::ChangeNameError::General(error) => Self::General(error.into()),
+            session_pool::ChangeNameError::InfoAsTokens(_) => Self::General(GeneralError::Internal),
+            session_pool::ChangeNameError::InvaildNameChars => Self::InvaildNameChars,
+            session_pool::ChangeNameError::BadNameLength => Self::BadNameLength,
+            session_pool::ChangeNameError::NameTaken => Self::NameTaken
+        }
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export, rename = "UsersProfileError")]
+#[serde(tag = "is", content = "data")]
+pub enum ProfileError {
+    General(GeneralError),
+    BadDisplayNameLength,
+    BioTooLong,
+    TooManyLinks,
+    InvalidLink(String),
+    InvalidBlock(String),
+    InvalidAvatarImage,
+    UnsupportedImage,
+    ImageTooLarge,
+}
+impl AsBuilder for ProfileError {
+    fn builder(&self) -> HttpResponseBuilder {
+        match self {
+            Self::General(error) => error.builder(),
+            Self::ImageTooLarge => HttpResponse::PayloadTooLarge(),
+            _ => HttpResponse::BadRequest()
+        }
+    }
+}
+impl From<session_pool::ProfileError> for ProfileError {
+    fn from(value: session_pool::ProfileError) -> Self {
+        match value {
+            session_pool::ProfileError::General(error) => Self::General(error.into()),
+            session_pool::ProfileError::BadDisplayNameLength => Self::BadDisplayNameLength,
+            session_pool::ProfileError::BioTooLong => Self::BioTooLong,
+            session_pool::ProfileError::TooManyLinks => Self::TooManyLinks,
+            session_pool::ProfileError::InvalidLink(link) => Self::InvalidLink(link),
+            session_pool::ProfileError::InvalidBlock(id) => Self::InvalidBlock(id),
+            session_pool::ProfileError::InvalidAvatarImage => Self::InvalidAvatarImage,
+            session_pool::ProfileError::UnsupportedImage => Self::UnsupportedImage,
+            session_pool::ProfileError::ImageTooLarge => Self::ImageTooLarge
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use std::sync::Arc;
-use actix_web::{HttpServer as ActixHttpServer, App, web::Data, HttpRequest};
+use std::sync::{Arc, Mutex};
+use actix_web::{HttpServer as ActixHttpServer, App, web::Data, HttpRequest, dev::ServerHandle, http::{header, Method}};
 use actix_cors::Cors;
-use crate::{session_pool::{SessionPool, Session}, logger::Logger, auth_validator::Tokens};
+use crate::{session_pool::{SessionPool, Session, Client, Credentials}, logger::Logger, auth_validator::{AuthValidator, Tokens}, rate_limiter::RateLimiter};
+use rate_limit::RateLimit;
+use csrf::Csrf;
+pub use csrf::origin_of;
 
 mod api;
 mod errors;
+mod csrf;
+mod rate_limit;
 
 fn extract_cookie_as_string(request: &HttpRequest, name: &str) -> String {
     match request.cookie(name) {
@@ -15,18 +20,57 @@ fn extract_cookie_as_string(request: &HttpRequest, name: &str) -> String {
 
 pub struct AppState {
     session_pool: Arc<SessionPool>,
+    /// for requests that need no session, like jwks
+    auth_validator: Arc<AuthValidator>,
     logger: Arc<Logger>,
+    live_config: LiveConfig,
+    event_streams: api::EventStreams,
+    trusted_proxy: bool,
 }
 
-impl AppState {
-    pub fn session_from_request(&self, request: &HttpRequest) -> Session {
-        self.session_pool.spawn_session(&Tokens {
+/// `Authorization: Bearer` personal access token takes precedence over cookies
+fn extract_credentials(request: &HttpRequest) -> Credentials {
+    let bearer = request.headers().get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "));
+    match bearer {
+        Some(token) => Credentials::Bearer(token.trim().to_owned()),
+        None => Credentials::Tokens(Tokens {
             access: extract_cookie_as_string(request, "access-token"),
             key: extract_cookie_as_string(request, "key-token")
         })
     }
 }
 
+impl AppState {
+    pub async fn session_from_request(&self, request: &HttpRequest) -> Session {
+        let credentials = extract_credentials(request);
+        let client = Client {
+            device: request.headers().get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default().to_owned(),
+            ip: self.client_ip(request)
+        };
+        self.session_pool.spawn_session(&credentials, client).await
+    }
+
+    /// forwarding headers are anyone's to set, so they are used only behind a trusted proxy
+    fn client_ip(&self, request: &HttpRequest) -> String {
+        if self.trusted_proxy {
+            return request.connection_info().realip_remote_addr().unwrap_or_default().to_owned();
+        }
+        request.peer_addr().map(|address| address.ip().to_string()).unwrap_or_default()
+    }
+
+    /// user or token if request is authenticated, ip otherwise
+    pub async fn rate_limit_identity(&self, request: &HttpRequest) -> String {
+        match self.session_pool.identify(&extract_credentials(request)).await {
+            Some(identity) => identity,
+            None => format!("ip:{}", self.client_ip(request))
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("faild to bind server: {0}")]
@@ -43,14 +87,65 @@ impl From<std::io::Error> for Error {
     }
 }
 
+/// heartbeat of live sockets, in seconds
+#[derive(Clone, Copy)]
+pub struct LiveConfig {
+    pub ping_interval: u64,
+    /// socket is closed when client sends nothing (pongs included) for this long
+    pub idle_timeout: u64,
+}
+
+pub struct Config {
+    /// seconds
+    pub shutdown_timeout: u64,
+    pub live: LiveConfig,
+    /// origins (`scheme://host[:port]`) allowed to make credentialed cross origin requests
+    pub allowed_origins: Vec<String>,
+    /// server is behind a proxy that sets `Forwarded` or `X-Forwarded-For`
+    pub trusted_proxy: bool,
+}
+
 pub struct HttpServer {
     session_pool: Arc<SessionPool>,
+    auth_validator: Arc<AuthValidator>,
+    rate_limiter: Arc<RateLimiter>,
     logger: Arc<Logger>,
+    shutdown_timeout: u64,
+    allowed_origins: Arc<Vec<String>>,
+    live_config: LiveConfig,
+    trusted_proxy: bool,
+    handle: Mutex<Option<ServerHandle>>,
 }
 
 impl HttpServer {
-    pub fn new(session_pool: Arc<SessionPool>, logger: Arc<Logger>) -> Self {
-        Self {session_pool, logger}
+    pub fn new(session_pool: Arc<SessionPool>, auth_validator: Arc<AuthValidator>, rate_limiter: Arc<RateLimiter>, config: Config, logger: Arc<Logger>) -> Self {
+        Self {
+            session_pool,
+            auth_validator,
+            rate_limiter,
+            logger,
+            shutdown_timeout: config.shutdown_timeout,
+            allowed_origins: Arc::new(config.allowed_origins),
+            live_config: config.live,
+            trusted_proxy: config.trusted_proxy,
+            handle: Mutex::new(None)
+        }
+    }
+
+    /// stops accepting connections, requests already being served go on
+    pub async fn pause(&self) {
+        let handle = self.handle.lock().unwrap().clone();
+        if let Some(handle) = handle {
+            handle.pause().await;
+        }
+    }
+
+    /// stops accepting connections and waits for in-flight requests (up to `shutdown_timeout` seconds)
+    pub async fn stop(&self) {
+        let handle = self.handle.lock().unwrap().clone();
+        if let Some(handle) = handle {
+            handle.stop(true).await;
+        }
     }
 
     pub async fn run(self: Arc<Self>) {
@@ -58,22 +153,40 @@ impl HttpServer {
         let app_state = Data::new(AppState {
             logger: this.logger.clone(),
             session_pool: this.session_pool.clone(),
+            auth_validator: this.auth_validator.clone(),
+            live_config: this.live_config,
+            event_streams: Default::default(),
+            trusted_proxy: this.trusted_proxy,
         });
 
-        ActixHttpServer::new(move || {
+        let rate_limiter = this.rate_limiter.clone();
+        let allowed_origins = this.allowed_origins.clone();
+
+        let server = ActixHttpServer::new(move || {
             App::new()
-            .wrap(
-                Cors::permissive()
+            .wrap(RateLimit::new(rate_limiter.clone()))
+            .wrap(Csrf::new(allowed_origins.clone()))
+            .wrap({
+                let allowed_origins = allowed_origins.clone();
+                Cors::default()
+                .allowed_origin_fn(move |origin, _| allowed_origins.iter().any(|allowed| allowed.as_bytes() == origin.as_bytes()))
+                .allowed_methods([Method::GET, Method::POST, Method::PUT])
                 .allow_any_header()
-                .allow_any_origin()
-                .allow_any_method()
-            )
+                .expose_headers(["ratelimit-limit", "ratelimit-remaining", "ratelimit-reset", "retry-after"])
+                .supports_credentials()
+                .max_age(60 * 60)
+            })
             .app_data(app_state.clone())
             .service(api::service())
+            .service(api::well_known_service())
         })
+        .disable_signals() // signals are handled in main, so that everything else can shut down too
+        .shutdown_timeout(self.shutdown_timeout)
         .bind(("127.0.0.1", 5000)).map_err(Error::FailedToBind).unwrap()
-        .run()
-        .await.unwrap();
+        .run();
+
+        *self.handle.lock().unwrap() = Some(server.handle());
+        server.await.unwrap();
     }
 }
 
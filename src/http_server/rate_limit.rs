@@ -0,0 +1,91 @@
+use std::{future::{ready, Ready}, rc::Rc, sync::Arc};
+use actix_web::{HttpResponse, HttpResponseBuilder, body::EitherBody, dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, http::header::{HeaderName, HeaderValue, RETRY_AFTER}};
+use futures_util::future::LocalBoxFuture;
+use crate::rate_limiter::{RateLimiter, Decision};
+use super::{AppStateData, errors::{ResultResponse, general::GeneralError}};
+
+const LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+fn rate_limit_headers(decision: &Decision) -> [(HeaderName, HeaderValue); 3] {
+    [
+        (LIMIT, HeaderValue::from(decision.limit)),
+        (REMAINING, HeaderValue::from(decision.remaining)),
+        (RESET, HeaderValue::from(decision.reset))
+    ]
+}
+
+/// token bucket per route and user (ip for anonymous requests), see `RateLimiter`
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), limiter: self.limiter.clone() }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let identity = match request.app_data::<AppStateData>() {
+                Some(app_state) => app_state.rate_limit_identity(request.request()).await,
+                None => return Ok(service.call(request).await?.map_into_left_body())
+            };
+            let decision = limiter.check(request.path(), &identity).await;
+
+            if let Some(decision) = decision.as_ref().filter(|decision| !decision.allowed) {
+                let mut builder: HttpResponseBuilder = HttpResponse::TooManyRequests();
+                for header in rate_limit_headers(decision) {
+                    builder.insert_header(header);
+                }
+                builder.insert_header((RETRY_AFTER, decision.retry_after));
+                let response = builder.json(ResultResponse::<(), GeneralError>::Err(GeneralError::RateLimited));
+                return Ok(request.into_response(response).map_into_right_body());
+            }
+
+            let mut response = service.call(request).await?;
+            if let Some(decision) = decision {
+                for (name, value) in rate_limit_headers(&decision) {
+                    response.headers_mut().insert(name, value);
+                }
+            }
+            Ok(response.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,104 @@
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Notify;
+use crate::{db_pool::{self, DbPool}, logger::Logger};
+
+const INTERVAL: Duration = Duration::from_secs(60 * 10);
+/// rate limit buckets are full again long before this
+const RATE_LIMIT_TTL: i64 = 60 * 60;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// how long an account may stay unverified before it gets deleted
+    pub unverified_user_ttl: chrono::Duration,
+    /// failed logins older than this are forgotten
+    pub login_attempts_ttl: chrono::Duration,
+}
+
+/// periodically cleans up stale data
+pub struct Janitor {
+    db_pool: Arc<DbPool>,
+    logger: Arc<Logger>,
+    config: Config,
+    shutdown: Notify
+}
+
+impl Janitor {
+    pub fn new(db_pool: Arc<DbPool>, config: Config, logger: Arc<Logger>) -> Self {
+        Self {
+            db_pool,
+            logger,
+            config,
+            shutdown: Notify::new()
+        }
+    }
+
+    pub fn shutdown(&self){
+        self.shutdown.notify_one();
+    }
+
+    async fn delete_unverified_users(&self) -> Result<(), db_pool::Error> {
+        let created_before = (chrono::Utc::now() - self.config.unverified_user_ttl).timestamp();
+        let (users, errors) = self.db_pool.get_stale_unverified_users(created_before).await?;
+        for error in errors {
+            self.logger.log(error.to_string());
+        }
+        for user in users {
+            let user = match self.db_pool.delete_unverified_user(&user.name).await {
+                Ok(user) => user,
+                Err(db_pool::Error::NotFound) => continue, // verified meanwhile
+                Err(error) => return Err(error)
+            };
+            self.db_pool.revoke_user_keys(&user.name).await?;
+            // names nothing points to are free to take again
+            let mut names = user.former_names;
+            names.push(user.name);
+            let mut referenced = Vec::new();
+            for name in names {
+                if self.db_pool.is_name_referenced(&name).await? {
+                    referenced.push(name);
+                }
+            }
+            self.db_pool.reserve_names(&referenced).await?;
+            if let Err(error) = self.db_pool.delete_activity_table(&user.activity_table).await {
+                self.logger.log(error.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_stale_login_attempts(&self) -> Result<(), db_pool::Error> {
+        let updated_before = (chrono::Utc::now() - self.config.login_attempts_ttl).timestamp();
+        self.db_pool.delete_stale_login_attempts(updated_before).await
+    }
+
+    async fn delete_expired_challenges(&self) -> Result<(), db_pool::Error> {
+        self.db_pool.delete_expired_challenges(chrono::Utc::now().timestamp()).await
+    }
+
+    async fn delete_stale_rate_limits(&self) -> Result<(), db_pool::Error> {
+        self.db_pool.delete_stale_rate_limits(chrono::Utc::now().timestamp() - RATE_LIMIT_TTL).await
+    }
+
+    pub async fn run(&self){
+        let mut interval = tokio::time::interval(INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(error) = self.delete_unverified_users().await {
+                        self.logger.log(error.to_string());
+                    }
+                    if let Err(error) = self.delete_stale_login_attempts().await {
+                        self.logger.log(error.to_string());
+                    }
+                    if let Err(error) = self.delete_stale_rate_limits().await {
+                        self.logger.log(error.to_string());
+                    }
+                    if let Err(error) = self.delete_expired_challenges().await {
+                        self.logger.log(error.to_string());
+                    }
+                },
+                _ = self.shutdown.notified() => break
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use async_trait::async_trait;
+use super::{Broker, Inbox, Event};
+
+/// single instance, events never leave the process
+#[derive(Default)]
+pub struct MemoryBroker {
+    inbox: Inbox,
+}
+
+#[async_trait]
+impl Broker for MemoryBroker {
+    fn node_id(&self) -> &str {
+        "local"
+    }
+
+    fn publish(&self, event: &Event) {
+        self.inbox.push(event.clone());
+    }
+
+    async fn next(&self) -> Option<Event> {
+        self.inbox.next().await
+    }
+
+    async fn run(&self) {}
+
+    fn shutdown(&self) {
+        self.inbox.close();
+    }
+}
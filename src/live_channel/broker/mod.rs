@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::{Mutex, Notify, mpsc::{self, UnboundedReceiver, UnboundedSender}};
+use super::Event;
+pub use memory::MemoryBroker;
+pub use relay::{RelayBroker, RelayHub};
+
+mod memory;
+mod relay;
+
+/// carries events between instances, every instance (publisher included) gets each event exactly once from `next`
+#[async_trait]
+pub trait Broker {
+    /// tells this instance apart from the others in the cluster
+    fn node_id(&self) -> &str;
+    fn publish(&self, event: &Event);
+    /// `None` once broker is shut down and everything already received was returned
+    async fn next(&self) -> Option<Event>;
+    /// keeps connections (if any) alive until `shutdown`
+    async fn run(&self);
+    fn shutdown(&self);
+}
+
+pub type BrokerShared = Arc<dyn Broker + Send + Sync>;
+
+/// events waiting for `next`, closing it lets queued ones out before `None`
+struct Inbox {
+    sender: UnboundedSender<Event>,
+    receiver: Mutex<UnboundedReceiver<Event>>,
+    closed: Notify,
+}
+
+impl Default for Inbox {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            closed: Notify::new()
+        }
+    }
+}
+
+impl Inbox {
+    /// events pushed after close are dropped
+    fn push(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    async fn next(&self) -> Option<Event> {
+        let mut receiver = self.receiver.lock().await;
+        tokio::select! {
+            biased;
+            event = receiver.recv() => event,
+            _ = self.closed.notified() => {
+                receiver.close();
+                receiver.recv().await
+            }
+        }
+    }
+
+    fn close(&self) {
+        self.closed.notify_one();
+    }
+}
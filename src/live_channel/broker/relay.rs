@@ -0,0 +1,326 @@
+use std::{sync::Arc, time::Duration};
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use tokio::{io::{AsyncRead, AsyncWrite, AsyncBufReadExt, AsyncWriteExt, BufReader}, net::{TcpListener, TcpStream}, sync::{Mutex, Notify, broadcast, mpsc::{self, Receiver, Sender, error::TrySendError}}};
+use crate::{logger::Logger, auth_validator::generate_secret};
+use super::{Broker, Inbox, Event};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// frames a slow instance can fall behind before it's disconnected (and starts over once it reconnects)
+const HUB_BUFFER: usize = 1024;
+/// frames published while the hub is unreachable that wait for it, the rest are dropped
+const PENDING_LIMIT: usize = 1024;
+
+/// `unix:/path` or `host:port`
+fn unix_path(address: &str) -> Option<&str> {
+    address.strip_prefix("unix:")
+}
+
+/// first line an instance sends to the hub
+#[derive(Serialize, Deserialize)]
+struct Handshake {
+    secret: String,
+    node: String,
+}
+
+/// instance connected to a `RelayHub`, events come back through the hub even to their publisher,
+/// so every instance gets each event once and in the same order, events published while disconnected wait for the hub,
+/// hub tells every instance (this one included) when it connects, so that it can start over
+pub struct RelayBroker {
+    address: String,
+    secret: String,
+    node: String,
+    inbox: Inbox,
+    outgoing: Sender<String>,
+    pending: Mutex<Receiver<String>>,
+    logger: Arc<Logger>,
+    shutdown: Notify,
+}
+
+impl RelayBroker {
+    pub fn new(address: &str, secret: &str, logger: Arc<Logger>) -> Self {
+        let (outgoing, pending) = mpsc::channel(PENDING_LIMIT);
+        Self {
+            address: address.to_owned(),
+            secret: secret.to_owned(),
+            node: generate_secret(),
+            inbox: Inbox::default(),
+            outgoing,
+            pending: Mutex::new(pending),
+            logger,
+            shutdown: Notify::new()
+        }
+    }
+
+    fn receive(&self, line: &str) {
+        match serde_json::from_str::<Event>(line) { // one event per line
+            Ok(event) => self.inbox.push(event),
+            Err(error) => self.logger.log(format!("invalid live relay frame: {error}"))
+        }
+    }
+
+    async fn connect(&self, pending: &mut Receiver<String>) -> Result<(), std::io::Error> {
+        match unix_path(&self.address) {
+            #[cfg(unix)]
+            Some(path) => self.serve(tokio::net::UnixStream::connect(path).await?, pending).await,
+            #[cfg(not(unix))]
+            Some(_) => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "unix sockets are not supported")),
+            None => self.serve(TcpStream::connect(&self.address).await?, pending).await
+        }
+    }
+
+    async fn serve<S: AsyncRead + AsyncWrite>(&self, stream: S, pending: &mut Receiver<String>) -> Result<(), std::io::Error> {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        let handshake = serde_json::to_string(&Handshake { secret: self.secret.clone(), node: self.node.clone() })?;
+        writer.write_all(format!("{handshake}\n").as_bytes()).await?;
+        self.logger.log(format!("connected to live relay {}", self.address));
+        loop {
+            tokio::select! {
+                line = lines.next_line() => match line? {
+                    Some(line) => self.receive(&line),
+                    None => return Err(std::io::ErrorKind::UnexpectedEof.into())
+                },
+                frame = pending.recv() => match frame {
+                    Some(frame) => writer.write_all(format!("{frame}\n").as_bytes()).await?,
+                    None => return Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Broker for RelayBroker {
+    fn node_id(&self) -> &str {
+        &self.node
+    }
+
+    fn publish(&self, event: &Event) {
+        match serde_json::to_string(event) {
+            Ok(frame) => {
+                if let Err(TrySendError::Full(_)) = self.outgoing.try_send(frame) {
+                    self.logger.log(format!("live relay {} is unreachable, event dropped", self.address));
+                }
+            },
+            Err(error) => self.logger.log(error.to_string())
+        }
+    }
+
+    async fn next(&self) -> Option<Event> {
+        self.inbox.next().await
+    }
+
+    /// reconnects until shutdown
+    async fn run(&self) {
+        let mut pending = self.pending.lock().await;
+        loop {
+            let result = tokio::select! {
+                result = self.connect(&mut pending) => result,
+                _ = self.shutdown.notified() => break
+            };
+            if let Err(error) = result {
+                self.logger.log(format!("live relay {}: {error}", self.address));
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {},
+                _ = self.shutdown.notified() => break
+            }
+        }
+        self.inbox.close();
+    }
+
+    fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+}
+
+/// forwards every line it gets to every connected instance (sender included), never looks inside,
+/// instances have to know `secret`, without it the hub listens only on loopback or unix socket
+pub struct RelayHub {
+    address: String,
+    secret: Arc<String>,
+    logger: Arc<Logger>,
+    shutdown: Notify,
+}
+
+impl RelayHub {
+    pub fn new(address: &str, secret: &str, logger: Arc<Logger>) -> Self {
+        Self {
+            address: address.to_owned(),
+            secret: Arc::new(secret.to_owned()),
+            logger,
+            shutdown: Notify::new()
+        }
+    }
+
+    pub async fn run(&self) {
+        let (sender, _) = broadcast::channel(HUB_BUFFER);
+        let result = match unix_path(&self.address) {
+            #[cfg(unix)]
+            Some(path) => {
+                let _ = std::fs::remove_file(path); // left behind by previous run
+                match tokio::net::UnixListener::bind(path) {
+                    Ok(listener) => {
+                        loop {
+                            tokio::select! {
+                                accepted = listener.accept() => match accepted {
+                                    Ok((stream, _)) => {
+                                        tokio::spawn(relay(stream, sender.clone(), self.secret.clone(), self.logger.clone()));
+                                    },
+                                    Err(error) => self.logger.log(format!("live relay hub: {error}"))
+                                },
+                                _ = self.shutdown.notified() => break
+                            }
+                        }
+                        Ok(())
+                    },
+                    Err(error) => Err(error)
+                }
+            },
+            #[cfg(not(unix))]
+            Some(_) => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "unix sockets are not supported")),
+            None => match TcpListener::bind(&self.address).await {
+                Ok(listener) if self.secret.is_empty() && !listener.local_addr().is_ok_and(|address| address.ip().is_loopback()) => {
+                    Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "relay secret is required outside of loopback"))
+                },
+                Ok(listener) => {
+                    loop {
+                        tokio::select! {
+                            accepted = listener.accept() => match accepted {
+                                Ok((stream, _)) => {
+                                    tokio::spawn(relay(stream, sender.clone(), self.secret.clone(), self.logger.clone()));
+                                },
+                                Err(error) => self.logger.log(format!("live relay hub: {error}"))
+                            },
+                            _ = self.shutdown.notified() => break
+                        }
+                    }
+                    Ok(())
+                },
+                Err(error) => Err(error)
+            }
+        };
+        if let Err(error) = result {
+            self.logger.log(format!("live relay hub can't listen on {}: {error}", self.address));
+        }
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+}
+
+fn announce(sender: &broadcast::Sender<String>, event: &Event) {
+    if let Ok(frame) = serde_json::to_string(event) {
+        let _ = sender.send(frame);
+    }
+}
+
+/// instance is let in only after a handshake with the right secret, lagging instance is disconnected,
+/// everyone gets told when an instance joins or leaves
+async fn relay<S: AsyncRead + AsyncWrite>(stream: S, sender: broadcast::Sender<String>, secret: Arc<String>, logger: Arc<Logger>) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let handshake = match lines.next_line().await {
+        Ok(Some(line)) => serde_json::from_str::<Handshake>(&line).ok(),
+        _ => None
+    };
+    let node = match handshake {
+        Some(handshake) if ring::constant_time::verify_slices_are_equal(handshake.secret.as_bytes(), secret.as_bytes()).is_ok() => handshake.node,
+        _ => {
+            logger.log("live relay instance rejected, wrong secret".to_owned());
+            return;
+        }
+    };
+    let mut receiver = sender.subscribe();
+    announce(&sender, &Event::NodeJoined { node: node.clone() });
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    let _ = sender.send(line);
+                },
+                _ => break
+            },
+            frame = receiver.recv() => match frame {
+                Ok(frame) => {
+                    if writer.write_all(format!("{frame}\n").as_bytes()).await.is_err() {
+                        break;
+                    }
+                },
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    logger.log(format!("live relay instance fell behind by {count} events, disconnected"));
+                    break;
+                },
+                Err(broadcast::error::RecvError::Closed) => break
+            }
+        }
+    }
+    announce(&sender, &Event::NodeLeft { node });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::live_channel::LiveMessage;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// how long to wait for events that shouldn't come
+    const QUIET: Duration = Duration::from_millis(300);
+
+    async fn next(broker: &RelayBroker) -> Event {
+        tokio::time::timeout(TIMEOUT, broker.next()).await.expect("event").expect("open broker")
+    }
+
+    async fn expect_quiet(broker: &RelayBroker) {
+        assert!(tokio::time::timeout(QUIET, broker.next()).await.is_err(), "unexpected event");
+    }
+
+    async fn start(address: &str, secret: &str) -> Arc<RelayBroker> {
+        let broker = Arc::new(RelayBroker::new(address, secret, Arc::new(Logger::new())));
+        tokio::spawn({
+            let broker = broker.clone();
+            async move { broker.run().await }
+        });
+        broker
+    }
+
+    #[actix_rt::test]
+    async fn brokers_get_each_event_once() {
+        let path = std::env::temp_dir().join(format!("live-relay-test-{}.sock", std::process::id()));
+        let address = format!("unix:{}", path.display());
+        let hub = Arc::new(RelayHub::new(&address, "secret", Arc::new(Logger::new())));
+        tokio::spawn({
+            let hub = hub.clone();
+            async move { hub.run().await }
+        });
+        while !path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let first = start(&address, "secret").await;
+        assert!(matches!(next(&first).await, Event::NodeJoined { node } if node == first.node_id()));
+        let second = start(&address, "secret").await;
+        assert!(matches!(next(&second).await, Event::NodeJoined { node } if node == second.node_id()));
+        assert!(matches!(next(&first).await, Event::NodeJoined { node } if node == second.node_id()));
+
+        let intruder = start(&address, "wrong").await;
+        intruder.publish(&Event::Message { channel_id: "channel".to_owned(), message: LiveMessage::LabelsChanged });
+
+        first.publish(&Event::Message { channel_id: "channel".to_owned(), message: LiveMessage::DescriptionChanged });
+        for broker in [&first, &second] {
+            assert!(matches!(next(broker).await, Event::Message { channel_id, message: LiveMessage::DescriptionChanged } if channel_id == "channel"));
+        }
+        expect_quiet(&first).await;
+        expect_quiet(&second).await;
+
+        second.shutdown();
+        assert!(matches!(next(&first).await, Event::NodeLeft { node } if node == second.node_id()));
+
+        first.shutdown();
+        intruder.shutdown();
+        hub.shutdown();
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,15 +1,104 @@
+mod outbox;
+mod registry;
+pub mod broker;
+
 use std::{collections::HashMap, fmt::Debug, sync::Arc};
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
 use async_trait::async_trait;
-use tokio::sync::{Mutex, mpsc::{UnboundedReceiver, UnboundedSender, self}};
+use tokio::sync::{Mutex, mpsc::{Sender, UnboundedSender, UnboundedReceiver, self}};
+use futures::StreamExt;
 use ts_rs::TS;
 
-use crate::logger::Logger;
+use crate::{logger::Logger, rate_limiter::{self, Store}};
+use outbox::{Outbox, Outgoing};
+use registry::Registry;
+use broker::BrokerShared;
+
+pub use outbox::OverflowPolicy;
+
+pub type PeerShared = Arc<dyn Peer + Send + Sync>;
+
+/// channels re-checked at once, each check asks the db once per subscribed user
+const RECHECK_CONCURRENCY: usize = 4;
 
-type PeerShared = Arc<dyn Peer + Send + Sync>;
-type Channels = HashMap<String, HashMap<i64, PeerShared>>;
+pub struct Config {
+    /// messages queued per peer before `overflow` kicks in
+    pub queue_capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// events kept per channel for peers resuming with `last_seq`
+    pub replay_capacity: usize,
+    /// signals one user can send, shared by all of its peers
+    pub signal_rate: rate_limiter::Policy,
+    /// bytes of signal data
+    pub signal_max_size: usize,
+}
+
+/// outcome of subscribing with a `Cursor`
+#[derive(Clone, Copy, Debug)]
+pub enum Replay {
+    /// missed events (if any) were queued for the peer
+    Complete,
+    /// missed events are no longer buffered (or cursor is from another epoch), client has to reload channel state
+    ResyncRequired,
+}
+
+/// last event client got from a channel, sequence numbers are only comparable within one epoch
+#[derive(Clone, Debug)]
+pub struct Cursor {
+    pub epoch: String,
+    pub seq: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Subscribed {
+    /// sequence number of the last event of the channel, 0 if there was none yet
+    pub seq: u64,
+    /// numbering `seq` belongs to, it changes when instance restarts or may have missed events
+    pub epoch: String,
+    pub replay: Replay,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum CloseReason {
+    Shutdown,
+    Revoked,
+    /// user got a new name, peer has to reconnect with fresh tokens
+    Renamed,
+    /// client didn't send anything (not even pong) for too long
+    IdleTimeout,
+    /// peer's queue overflowed with `OverflowPolicy::Disconnect`
+    SlowConsumer,
+    /// instance may have missed events, peer has to reconnect and resync
+    Resync,
+}
 
-#[derive(Serialize, Clone, TS)]
+/// why the server unsubscribed a peer from a channel, the peer stays connected to the rest
+#[derive(Clone, Copy, Debug, Serialize, TS)]
+#[ts(export)]
+pub enum UnsubscribeReason {
+    /// user can no longer watch the channel
+    AccessLost,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PeerError {
+    #[error("peer connection is closed")]
+    Closed,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignalError {
+    #[error("peer is not subscribed to the channel")]
+    NotSubscribed,
+    #[error("peer can't send signals to the channel")]
+    Forbidden,
+    #[error("signal is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("too many signals")]
+    RateLimited,
+}
+
+#[derive(Serialize, Deserialize, Clone, TS)]
 #[ts(export)]
 #[serde(tag = "is", content = "data")]
 pub enum LiveMessage {
@@ -27,98 +116,340 @@ pub enum LiveMessage {
     BlockChanged {
         id: String
     },
+    /// first peer of the user subscribed to the channel
+    UserJoined {
+        name: String
+    },
+    /// last peer of the user left the channel
+    UserLeft {
+        name: String
+    },
+    /// short-lived client signal (typing, cursor), `data` is up to clients
+    Signal {
+        from: String,
+        data: String
+    },
 }
 
-#[async_trait]
-pub trait Peer {
-    async fn receive_message(&self, message: &LiveMessage);
+impl LiveMessage {
+    /// not persisted anywhere, not numbered and never replayed
+    pub fn is_ephemeral(&self) -> bool {
+        matches!(self, Self::Signal { .. })
+    }
+
+    /// tells something about blocks of the channel, only peers with `view_blocks` get it
+    pub fn reveals_blocks(&self) -> bool {
+        matches!(self, Self::BlockConnected { .. } | Self::BlockDisconnected { .. } | Self::BlockPinned { .. } | Self::BlockChanged { .. })
+    }
 }
 
-#[derive(Clone)]
-pub struct Handle {
-    pub channel_id: String,
-    pub peer_id: i64,
+/// what travels between instances through the broker
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "is", content = "data")]
+pub enum Event {
+    Message {
+        channel_id: String,
+        message: LiveMessage
+    },
+    /// permissions of subscribers of the channels may have changed
+    Recheck {
+        channel_ids: Vec<String>
+    },
+    /// closes every peer opened with the auth key
+    DisconnectKey {
+        key: String,
+        reason: CloseReason
+    },
+    /// user got their first (`present`) or lost their last peer in the channel on the instance,
+    /// `announce` is false in channels that hide presence
+    Presence {
+        node: String,
+        channel_id: String,
+        name: String,
+        present: bool,
+        announce: bool
+    },
+    /// instance (re)connected to the cluster, it starts over and the others tell it who is present
+    NodeJoined {
+        node: String
+    },
+    /// instance lost connection to the cluster, its users are no longer present
+    NodeLeft {
+        node: String
+    },
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum DisconnectError {
-    #[error("channel id: {0} not found")]
-    ChannelNotFound(String),
-    #[error("peer id: {0} not found")]
-    PeerNotFound(i64)
+/// what subscribed peer may do besides watching the channel
+#[derive(Clone, Copy, Debug)]
+pub struct Grants {
+    /// peers without it don't get block events
+    pub view_blocks: bool,
+    pub signal: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Access {
+    Denied,
+    Live(Grants),
+}
+
+/// tells what user may do in a channel right now, used to re-check subscribed peers
+#[async_trait]
+pub trait AccessChecker {
+    /// `None` if it couldn't be found out (e.g. db is down), peers are left alone then
+    async fn check(&self, channel_id: &str, name: &str) -> Option<Access>;
 }
 
-type MpscMessage = (String, LiveMessage);
+pub type AccessCheckerShared = Arc<dyn AccessChecker + Send + Sync>;
+
+#[async_trait]
+pub trait Peer {
+    /// peer gets messages of every channel it is subscribed to, `channel_id` tells them apart,
+    /// `seq` increases by one with every event of the channel,
+    /// messages are delivered one at a time by peer's own writer task,
+    /// peer that fails is considered dead and removed from every channel
+    async fn receive_message(&self, channel_id: &str, seq: u64, message: &LiveMessage) -> Result<(), PeerError>;
+    /// peer was unsubscribed from the channel by the server and gets no more of its messages
+    async fn unsubscribed(&self, channel_id: &str, reason: UnsubscribeReason) -> Result<(), PeerError>;
+    /// peer should be closed gracefully (e.g. websocket close frame)
+    async fn close(&self, reason: CloseReason);
+}
+
+/// registered peer, used to (un)subscribe it and to unregister it
+#[derive(Clone, Copy)]
+pub struct PeerHandle {
+    pub peer_id: i64,
+}
 
 pub struct LiveChannel {
-    channels: Mutex<Channels>,
+    registry: Arc<Registry>,
+    /// events go through broker even on a single instance, peers get only what comes out of it
+    broker: BrokerShared,
+    access_checker: AccessCheckerShared,
+    config: Config,
+    signal_limiter: rate_limiter::MemoryStore,
     logger: Arc<Logger>,
-    peer_id: Mutex<i64>,
-    receiver: Mutex<UnboundedReceiver<MpscMessage>>,
-    sender: UnboundedSender<MpscMessage>
+    /// every writer task holds a clone, dropping this one lets `run` wait for writers to finish
+    writers: std::sync::Mutex<Option<Sender<()>>>,
+    writers_done: Mutex<mpsc::Receiver<()>>,
+    /// channels waiting for `recheck`, taken by `run`
+    rechecks: UnboundedSender<String>,
+    queued_rechecks: Mutex<Option<UnboundedReceiver<String>>>,
 }
 
 impl LiveChannel {
-    pub fn new(logger: Arc<Logger>) -> Self {
-        let (sender, receiver) = mpsc::unbounded_channel();
+    pub fn new(config: Config, broker: BrokerShared, access_checker: AccessCheckerShared, logger: Arc<Logger>) -> Self {
+        let (writers, writers_done) = mpsc::channel(1);
+        let (rechecks, queued_rechecks) = mpsc::unbounded_channel();
         Self {
-            receiver: Mutex::new(receiver),
-            sender,
-            channels: Default::default(),
-            peer_id: Default::default(),
-            logger
+            registry: Arc::new(Registry::new(config.replay_capacity, broker.clone())),
+            broker,
+            access_checker,
+            config,
+            signal_limiter: Default::default(),
+            logger,
+            writers: std::sync::Mutex::new(Some(writers)),
+            writers_done: Mutex::new(writers_done),
+            rechecks,
+            queued_rechecks: Mutex::new(Some(queued_rechecks)),
         }
     }
 
+    /// publishes event to peers on every instance
     pub fn receive_message(&self, channel_id: &str, message: &LiveMessage) {
-        if let Err(error) = self.sender.send((channel_id.to_string(), message.clone())) {
-            self.logger.log(error.to_string());
+        self.broker.publish(&Event::Message { channel_id: channel_id.to_owned(), message: message.clone() });
+    }
+
+    /// re-checks access of peers subscribed to the channels on every instance
+    pub fn recheck(&self, channel_ids: Vec<String>) {
+        if !channel_ids.is_empty() {
+            self.broker.publish(&Event::Recheck { channel_ids });
         }
     }
 
-    async fn handle_message(&self, channel_id: &str, message: &LiveMessage) {
-        let empty_peers = HashMap::new();
-        let channels = self.channels.lock().await;
-        let peers = channels.get(channel_id).unwrap_or(&empty_peers);
-        for peer in peers.values() {
-            peer.receive_message(message).await
+    /// starts peer's writer task, `key` is the auth key of the session peer belongs to, used to kick peers when it gets revoked,
+    /// `name` is the user shown in presence of channels peer subscribes to
+    pub fn register(&self, peer: PeerShared, key: Option<String>, name: Option<String>) -> PeerHandle {
+        let outbox = Arc::new(Outbox::new(self.config.queue_capacity, self.config.overflow));
+        let peer_id = self.registry.add_peer(outbox.clone(), key, name);
+        match self.writers.lock().unwrap().clone() {
+            Some(alive) => {
+                tokio::spawn(write(self.registry.clone(), peer_id, outbox, peer, self.logger.clone(), alive));
+            },
+            None => self.registry.close_peer(peer_id, CloseReason::Shutdown) // shutting down, peer is closed right away
         }
+        PeerHandle { peer_id }
     }
 
-    pub async fn connect(&self, peer: PeerShared, channel_id: &str) -> Handle {
-        let mut peer_id = self.peer_id.lock().await;
-        let handle = Handle {
-            channel_id: channel_id.to_string(),
-            peer_id: *peer_id,
-        };
+    /// with `cursor` every buffered event after it is queued for the peer before any new one,
+    /// `hide_presence` stops join and leave events of the channel
+    pub fn subscribe(&self, handle: PeerHandle, channel_id: &str, cursor: Option<&Cursor>, hide_presence: bool, grants: Grants) -> Subscribed {
+        self.registry.subscribe(handle.peer_id, channel_id, cursor, hide_presence, grants)
+    }
 
-        let mut channels = self.channels.lock().await;
-        match channels.get_mut(channel_id) {
-            Some(peers) => {
-                peers.insert(*peer_id, peer);
+    /// broadcasts signal from the peer to everyone subscribed to the channel (including the peer)
+    pub async fn signal(&self, handle: PeerHandle, channel_id: &str, data: String) -> Result<(), SignalError> {
+        let from = self.registry.signal_sender(handle.peer_id, channel_id)?;
+        if data.len() > self.config.signal_max_size {
+            return Err(SignalError::TooLarge(self.config.signal_max_size));
+        }
+        match self.signal_limiter.take(&from, &self.config.signal_rate).await {
+            Ok(bucket) if !bucket.allowed => return Err(SignalError::RateLimited),
+            Ok(_) => {},
+            Err(error) => self.logger.log(error.to_string())
+        }
+        self.receive_message(channel_id, &LiveMessage::Signal { from, data });
+        Ok(())
+    }
+
+    /// numbering of events peers get right now, it only changes when every peer is closed
+    pub fn epoch(&self) -> String {
+        self.registry.epoch()
+    }
+
+    /// names of users subscribed to the channel on any instance
+    pub fn presence(&self, channel_id: &str) -> Vec<String> {
+        self.registry.presence(channel_id)
+    }
+
+    /// false if peer wasn't subscribed to the channel
+    pub fn unsubscribe(&self, handle: PeerHandle, channel_id: &str) -> bool {
+        self.registry.unsubscribe(handle.peer_id, channel_id)
+    }
+
+    /// channels peer is subscribed to, the server may have unsubscribed it from some
+    pub fn channel_ids(&self, handle: PeerHandle) -> Vec<String> {
+        self.registry.channel_ids(handle.peer_id)
+    }
+
+    /// removes peer from every channel and stops its writer, peer isn't closed
+    pub fn unregister(&self, handle: PeerHandle) {
+        if let Some(outbox) = self.registry.remove_peer(handle.peer_id) {
+            outbox.stop();
+        }
+    }
+
+    /// removes and closes every peer connected with given auth key
+    pub fn disconnect_key(&self, key: &str, reason: CloseReason) {
+        self.broker.publish(&Event::DisconnectKey { key: key.to_owned(), reason });
+    }
+
+    async fn handle_event(&self, event: Event) {
+        match event {
+            Event::Message { channel_id, message } => {
+                if let LiveMessage::LabelsChanged = message { // labels decide which permissions apply
+                    let _ = self.rechecks.send(channel_id.clone());
+                }
+                self.registry.fanout(&channel_id, &message);
             },
-            None => {
-                let mut peers = HashMap::new();
-                peers.insert(*peer_id, peer);
-                channels.insert(channel_id.to_string(), peers);
-            }
-        };
-        *peer_id += 1;
+            Event::Recheck { channel_ids } => {
+                for channel_id in channel_ids {
+                    let _ = self.rechecks.send(channel_id);
+                }
+            },
+            Event::DisconnectKey { key, reason } => {
+                for peer_id in self.registry.peers_with_key(&key) {
+                    self.registry.close_peer(peer_id, reason);
+                }
+            },
+            Event::Presence { node, channel_id, name, present, announce } => self.registry.apply_presence(&node, &channel_id, name, present, announce),
+            Event::NodeJoined { node } if node == self.broker.node_id() => self.registry.reset(),
+            Event::NodeJoined { node } => {
+                self.registry.drop_node(&node);
+                for (channel_id, name, announce) in self.registry.local_presence() {
+                    self.broker.publish(&Event::Presence { node: self.broker.node_id().to_owned(), channel_id, name, present: true, announce });
+                }
+            },
+            Event::NodeLeft { node } => self.registry.drop_node(&node)
+        }
+    }
 
-        handle
+    /// makes `run` deliver already received messages, close every connected peer and return
+    pub fn shutdown(&self){
+        self.broker.shutdown();
     }
 
-    pub async fn disconnect(&self, handle: Handle) -> Result<(), DisconnectError> {
-        let mut channels = self.channels.lock().await;
-        let channel = channels.get_mut(handle.channel_id.as_str()).ok_or(DisconnectError::ChannelNotFound(handle.channel_id.clone()))?;
-        channel.remove(&handle.peer_id).ok_or(DisconnectError::PeerNotFound(handle.peer_id))?;
-        Ok(())
+    /// peers get their queued messages before the close
+    async fn close_peers(&self){
+        self.writers.lock().unwrap().take();
+        for peer_id in self.registry.peer_ids() {
+            self.registry.close_peer(peer_id, CloseReason::Shutdown);
+        }
+        self.writers_done.lock().await.recv().await;
     }
 
     pub async fn run(&self){
-        let mut receiver = self.receiver.lock().await;
-        while let Some((channel_id, message)) = receiver.recv().await {
-            self.handle_message(channel_id.as_str(), &message).await;
+        let rechecker = self.queued_rechecks.lock().await.take()
+        .map(|queued| tokio::spawn(recheck(self.registry.clone(), self.access_checker.clone(), queued)));
+        tokio::join!(
+            self.broker.run(),
+            async {
+                while let Some(event) = self.broker.next().await {
+                    self.handle_event(event).await;
+                }
+                self.close_peers().await;
+            }
+        );
+        if let Some(rechecker) = rechecker {
+            rechecker.abort();
+        }
+    }
+}
+
+/// re-checks channels as they get queued, a few at a time, so that slow checks hold up neither events nor each other
+async fn recheck(registry: Arc<Registry>, access_checker: AccessCheckerShared, mut queued: UnboundedReceiver<String>) {
+    futures::stream::poll_fn(|context| queued.poll_recv(context))
+    .for_each_concurrent(RECHECK_CONCURRENCY, |channel_id| recheck_channel(&registry, &access_checker, channel_id))
+    .await;
+}
+
+/// unsubscribes peers of users that lost access from the channel, updates grants of the rest,
+/// peers are closed only when their key is revoked (see `disconnect_key`)
+async fn recheck_channel(registry: &Registry, access_checker: &AccessCheckerShared, channel_id: String) {
+    let mut users: HashMap<String, Vec<i64>> = HashMap::new();
+    for (peer_id, name) in registry.subscribers(&channel_id) {
+        users.entry(name).or_default().push(peer_id);
+    }
+    for (name, peer_ids) in users {
+        match access_checker.check(&channel_id, &name).await {
+            Some(Access::Denied) => {
+                for peer_id in peer_ids {
+                    registry.revoke_subscription(peer_id, &channel_id, UnsubscribeReason::AccessLost);
+                }
+            },
+            Some(Access::Live(grants)) => {
+                for peer_id in peer_ids {
+                    registry.set_grants(&channel_id, peer_id, grants);
+                }
+            },
+            None => {}
+        }
+    }
+}
+
+/// delivers peer's queued messages one by one, so a slow peer only holds up itself
+async fn write(registry: Arc<Registry>, peer_id: i64, outbox: Arc<Outbox>, peer: PeerShared, logger: Arc<Logger>, _alive: Sender<()>) {
+    while let Some(outgoing) = outbox.next().await {
+        match outgoing {
+            Outgoing::Message(channel_id, seq, message) => {
+                if peer.receive_message(&channel_id, seq, &message).await.is_err() {
+                    registry.remove_peer(peer_id);
+                    logger.log(format!("removed dead live peer {peer_id}"));
+                    break;
+                }
+            },
+            Outgoing::Unsubscribed(channel_id, reason) => {
+                if peer.unsubscribed(&channel_id, reason).await.is_err() {
+                    registry.remove_peer(peer_id);
+                    logger.log(format!("removed dead live peer {peer_id}"));
+                    break;
+                }
+            },
+            Outgoing::Close(reason) => {
+                registry.remove_peer(peer_id);
+                peer.close(reason).await;
+                break;
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,129 @@
+use std::{collections::VecDeque, sync::Mutex};
+use tokio::sync::Notify;
+use super::{LiveMessage, CloseReason, UnsubscribeReason};
+
+/// what happens when a peer doesn't keep up and its queue is full
+#[derive(Clone, Copy, Debug)]
+pub enum OverflowPolicy {
+    DropOldest,
+    Disconnect,
+}
+
+impl std::str::FromStr for OverflowPolicy {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "drop_oldest" => Ok(Self::DropOldest),
+            "disconnect" => Ok(Self::Disconnect),
+            _ => Err(format!("unknown overflow policy: {value}"))
+        }
+    }
+}
+
+pub enum Outgoing {
+    Message(String, u64, LiveMessage),
+    Unsubscribed(String, UnsubscribeReason),
+    Close(CloseReason),
+}
+
+#[derive(Default)]
+struct State {
+    /// messages and notices, never `Close`
+    items: VecDeque<Outgoing>,
+    close: Option<CloseReason>,
+    /// peer is gone, writer should just stop
+    stopped: bool,
+}
+
+/// bounded queue between fanout and the writer task of one peer, pushing never waits
+pub struct Outbox {
+    state: Mutex<State>,
+    notify: Notify,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            notify: Notify::new(),
+            capacity: capacity.max(1),
+            policy
+        }
+    }
+
+    pub fn push(&self, channel_id: &str, seq: u64, message: &LiveMessage) {
+        let mut state = self.state.lock().unwrap();
+        if state.close.is_some() || state.stopped {
+            return;
+        }
+        if state.items.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => { // notices are kept, the channel would look alive otherwise
+                    if let Some(index) = state.items.iter().position(|item| matches!(item, Outgoing::Message(..))) {
+                        state.items.remove(index);
+                    }
+                },
+                OverflowPolicy::Disconnect => {
+                    state.items.clear();
+                    state.close = Some(CloseReason::SlowConsumer);
+                    drop(state);
+                    self.notify.notify_one();
+                    return;
+                }
+            }
+        }
+        state.items.push_back(Outgoing::Message(channel_id.to_owned(), seq, message.clone()));
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// queued after messages of the channel that are already waiting, there is at most one per subscription
+    pub fn unsubscribed(&self, channel_id: &str, reason: UnsubscribeReason) {
+        let mut state = self.state.lock().unwrap();
+        if state.close.is_some() || state.stopped {
+            return;
+        }
+        state.items.push_back(Outgoing::Unsubscribed(channel_id.to_owned(), reason));
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// queued messages are still delivered before closing only on shutdown
+    pub fn close(&self, reason: CloseReason) {
+        let mut state = self.state.lock().unwrap();
+        if state.close.is_none() {
+            if !matches!(reason, CloseReason::Shutdown) {
+                state.items.clear();
+            }
+            state.close = Some(reason);
+        }
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    pub fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.notify.notify_one();
+    }
+
+    /// `None` once the outbox is stopped
+    pub async fn next(&self) -> Option<Outgoing> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.stopped {
+                    return None;
+                }
+                if let Some(item) = state.items.pop_front() {
+                    return Some(item);
+                }
+                if let Some(reason) = state.close {
+                    return Some(Outgoing::Close(reason));
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
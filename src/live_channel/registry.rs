@@ -0,0 +1,619 @@
+use std::{collections::{HashMap, HashSet, VecDeque, hash_map::DefaultHasher}, hash::{Hash, Hasher}, sync::{Arc, Mutex, MutexGuard}, time::{Duration, Instant}};
+use crate::auth_validator::generate_secret;
+use super::{outbox::Outbox, broker::BrokerShared, Event, LiveMessage, CloseReason, UnsubscribeReason, Replay, Cursor, Subscribed, Grants, SignalError};
+
+/// channels are spread over shards, so that unrelated channels rarely share a lock
+const SHARDS: usize = 16;
+/// how often a shard drops idle channels, so a sweep is paid for once per interval and not on every event
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// how long a channel without subscribers keeps its events for peers that reconnect
+const IDLE_TTL: Duration = Duration::from_secs(10 * 60);
+
+struct Shard {
+    channels: HashMap<String, ChannelState>,
+    /// cluster wide, kept apart from `channels` since users can be present on other instances only
+    presence: HashMap<String, Presence>,
+    /// last `seq` of channels dropped as idle, events fanned out since then weren't numbered,
+    /// so a recreated channel continues past it and cursors from before the eviction require resync
+    evicted: HashMap<String, u64>,
+    swept: Instant,
+}
+
+impl Shard {
+    fn new(now: Instant) -> Self {
+        Self { channels: HashMap::new(), presence: HashMap::new(), evicted: HashMap::new(), swept: now }
+    }
+
+    fn sweep(&mut self, now: Instant) {
+        if now.duration_since(self.swept) >= SWEEP_INTERVAL {
+            let evicted = &mut self.evicted;
+            self.channels.retain(|channel_id, state| {
+                let expired = state.is_expired(now);
+                if expired {
+                    evicted.insert(channel_id.clone(), state.seq);
+                }
+                !expired
+            });
+            self.swept = now;
+        }
+    }
+
+    fn channel(&mut self, channel_id: &str) -> &mut ChannelState {
+        let evicted = &mut self.evicted;
+        self.channels.entry(channel_id.to_owned()).or_insert_with(|| ChannelState {
+            seq: evicted.remove(channel_id).map_or(0, |seq| seq + 1),
+            ..Default::default()
+        })
+    }
+}
+
+/// users present in a channel with instances they are present on
+#[derive(Default)]
+struct Presence {
+    users: HashMap<String, HashSet<String>>,
+    announce: bool,
+}
+
+impl Presence {
+    /// true if user just became present (or absent) on the whole cluster
+    fn update(&mut self, node: &str, name: &str, present: bool) -> bool {
+        let nodes = self.users.entry(name.to_owned()).or_default();
+        let changed = match present {
+            true => nodes.insert(node.to_owned()) && nodes.len() == 1,
+            false => nodes.remove(node) && nodes.is_empty()
+        };
+        if nodes.is_empty() {
+            self.users.remove(name);
+        }
+        changed
+    }
+}
+
+struct Subscriber {
+    outbox: Arc<Outbox>,
+    grants: Grants,
+}
+
+impl Subscriber {
+    fn push(&self, channel_id: &str, seq: u64, message: &LiveMessage) {
+        if self.grants.view_blocks || !message.reveals_blocks() {
+            self.outbox.push(channel_id, seq, message);
+        }
+    }
+}
+
+/// state of a channel is kept for `IDLE_TTL` after its last peer leaves, so that resuming peers get what they missed,
+/// peers resuming after that get `ResyncRequired` since the recreated channel continues past their `last_seq` with nothing buffered
+#[derive(Default)]
+struct ChannelState {
+    seq: u64,
+    /// last events, oldest first
+    buffer: VecDeque<(u64, LiveMessage)>,
+    peers: HashMap<i64, Subscriber>,
+    /// subscribed peers of this instance per user name, one user with several tabs joins and leaves once
+    presence: HashMap<String, usize>,
+    hide_presence: bool,
+    /// when the last peer left
+    idle_since: Option<Instant>,
+}
+
+impl ChannelState {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.idle_since.is_some_and(|since| now.duration_since(since) >= IDLE_TTL)
+    }
+
+    fn replay(&self, last_seq: u64, subscriber: &Subscriber, channel_id: &str) -> Replay {
+        if last_seq > self.seq {
+            return Replay::ResyncRequired; // client saw sequence numbers from before a restart
+        }
+        if last_seq < self.seq && self.buffer.front().is_none_or(|(seq, _)| *seq > last_seq + 1) {
+            return Replay::ResyncRequired;
+        }
+        for (seq, message) in self.buffer.iter().filter(|(seq, _)| *seq > last_seq) {
+            subscriber.push(channel_id, *seq, message);
+        }
+        Replay::Complete
+    }
+}
+
+struct PeerEntry {
+    key: Option<String>,
+    /// user the peer belongs to, anonymous peers don't show up in presence
+    name: Option<String>,
+    outbox: Arc<Outbox>,
+    channels: HashSet<String>,
+}
+
+/// who is subscribed where and what was sent recently, locks are never held across an await,
+/// `peers` is only ever locked inside a shard lock, never the other way around
+pub struct Registry {
+    peers: Mutex<HashMap<i64, PeerEntry>>,
+    shards: Vec<Mutex<Shard>>,
+    next_peer_id: Mutex<i64>,
+    replay_capacity: usize,
+    /// presence changes are published like any other event
+    broker: BrokerShared,
+    /// cursors of other epochs (earlier runs, other instances) can't be resumed
+    epoch: Mutex<String>,
+}
+
+impl Registry {
+    pub fn new(replay_capacity: usize, broker: BrokerShared) -> Self {
+        Self {
+            replay_capacity,
+            broker,
+            peers: Mutex::new(HashMap::new()),
+            shards: (0..SHARDS).map(|_| Mutex::new(Shard::new(Instant::now()))).collect(),
+            next_peer_id: Mutex::new(0),
+            epoch: Mutex::new(generate_secret())
+        }
+    }
+
+    /// locks shard of the channel, dropping its idle channels when it's time to
+    fn shard(&self, channel_id: &str) -> MutexGuard<'_, Shard> {
+        let mut hasher = DefaultHasher::new();
+        channel_id.hash(&mut hasher);
+        let mut shard = self.shards[hasher.finish() as usize % SHARDS].lock().unwrap();
+        shard.sweep(Instant::now());
+        shard
+    }
+
+    pub fn add_peer(&self, outbox: Arc<Outbox>, key: Option<String>, name: Option<String>) -> i64 {
+        let peer_id = {
+            let mut next_peer_id = self.next_peer_id.lock().unwrap();
+            *next_peer_id += 1;
+            *next_peer_id
+        };
+        self.peers.lock().unwrap().insert(peer_id, PeerEntry { key, name, outbox, channels: HashSet::new() });
+        peer_id
+    }
+
+    /// replay and subscription happen under the shard lock, so no event is missed or delivered twice,
+    /// peer is looked up under it too, so a peer removed meanwhile is either cleaned up after or never subscribed
+    pub fn subscribe(&self, peer_id: i64, channel_id: &str, cursor: Option<&Cursor>, hide_presence: bool, grants: Grants) -> Subscribed {
+        let epoch = self.epoch();
+        let mut shard = self.shard(channel_id);
+        let peer = self.peers.lock().unwrap().get_mut(&peer_id).map(|entry| {
+            entry.channels.insert(channel_id.to_owned());
+            (Subscriber { outbox: entry.outbox.clone(), grants }, entry.name.clone())
+        });
+        let (subscriber, name) = match peer {
+            Some(peer) => peer,
+            None => return Subscribed {
+                seq: shard.channels.get(channel_id).map_or(0, |state| state.seq),
+                epoch,
+                replay: Replay::Complete
+            }
+        };
+        let state = shard.channel(channel_id);
+        state.hide_presence = hide_presence;
+        state.idle_since = None;
+        let replay = match cursor {
+            Some(cursor) if cursor.epoch == epoch => state.replay(cursor.seq, &subscriber, channel_id),
+            Some(_) => Replay::ResyncRequired,
+            None => Replay::Complete
+        };
+        if state.peers.insert(peer_id, subscriber).is_none() {
+            if let Some(name) = name {
+                self.join(state, channel_id, name);
+            }
+        }
+        Subscribed { seq: state.seq, epoch, replay }
+    }
+
+    fn join(&self, state: &mut ChannelState, channel_id: &str, name: String) {
+        let count = state.presence.entry(name.clone()).or_default();
+        *count += 1;
+        if *count == 1 {
+            self.publish_presence(channel_id, name, true, !state.hide_presence);
+        }
+    }
+
+    fn leave(&self, state: &mut ChannelState, channel_id: &str, name: &str) {
+        if let Some(count) = state.presence.get_mut(name) {
+            *count -= 1;
+            if *count == 0 {
+                state.presence.remove(name);
+                self.publish_presence(channel_id, name.to_owned(), false, !state.hide_presence);
+            }
+        }
+    }
+
+    fn publish_presence(&self, channel_id: &str, name: String, present: bool, announce: bool) {
+        self.broker.publish(&Event::Presence { node: self.broker.node_id().to_owned(), channel_id: channel_id.to_owned(), name, present, announce });
+    }
+
+    /// updates cluster wide presence, users joining or leaving the whole cluster are announced
+    pub fn apply_presence(&self, node: &str, channel_id: &str, name: String, present: bool, announce: bool) {
+        let changed = {
+            let mut shard = self.shard(channel_id);
+            let presence = shard.presence.entry(channel_id.to_owned()).or_default();
+            presence.announce = announce;
+            let changed = presence.update(node, &name, present);
+            if presence.users.is_empty() {
+                shard.presence.remove(channel_id);
+            }
+            changed && announce
+        };
+        if changed {
+            let message = match present {
+                true => LiveMessage::UserJoined { name },
+                false => LiveMessage::UserLeft { name }
+            };
+            self.fanout(channel_id, &message);
+        }
+    }
+
+    /// users of an instance that left (or starts over) are no longer present there
+    pub fn drop_node(&self, node: &str) {
+        let mut left = Vec::new();
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            shard.presence.retain(|channel_id, presence| {
+                let names: Vec<String> = presence.users.keys().cloned().collect();
+                for name in names {
+                    if presence.update(node, &name, false) && presence.announce {
+                        left.push((channel_id.clone(), name));
+                    }
+                }
+                !presence.users.is_empty()
+            });
+        }
+        for (channel_id, name) in left {
+            self.fanout(&channel_id, &LiveMessage::UserLeft { name });
+        }
+    }
+
+    /// users present through peers of this instance, with `announce` of their channel
+    pub fn local_presence(&self) -> Vec<(String, String, bool)> {
+        self.shards.iter()
+        .flat_map(|shard| shard.lock().unwrap().channels.iter()
+            .flat_map(|(channel_id, state)| state.presence.keys().map(|name| (channel_id.clone(), name.clone(), !state.hide_presence)))
+            .collect::<Vec<(String, String, bool)>>())
+        .collect()
+    }
+
+    /// instance may have missed events, so every peer has to resync and cursors of the old epoch are no longer valid,
+    /// presence is forgotten as well, instances that are still there send theirs again
+    pub fn reset(&self) {
+        *self.epoch.lock().unwrap() = generate_secret();
+        for peer_id in self.peer_ids() {
+            self.close_peer(peer_id, CloseReason::Resync);
+        }
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            shard.channels.clear();
+            shard.presence.clear();
+            shard.evicted.clear();
+        }
+    }
+
+    /// name of the peer's user, if peer may send signals to the channel
+    pub fn signal_sender(&self, peer_id: i64, channel_id: &str) -> Result<String, SignalError> {
+        let name = self.peers.lock().unwrap().get(&peer_id)
+        .filter(|entry| entry.channels.contains(channel_id))
+        .and_then(|entry| entry.name.clone())
+        .ok_or(SignalError::NotSubscribed)?;
+        match self.shard(channel_id).channels.get(channel_id).and_then(|state| state.peers.get(&peer_id)) {
+            Some(subscriber) if subscriber.grants.signal => Ok(name),
+            Some(_) => Err(SignalError::Forbidden),
+            None => Err(SignalError::NotSubscribed)
+        }
+    }
+
+    /// named peers subscribed to the channel
+    pub fn subscribers(&self, channel_id: &str) -> Vec<(i64, String)> {
+        let peer_ids: Vec<i64> = match self.shard(channel_id).channels.get(channel_id) {
+            Some(state) => state.peers.keys().copied().collect(),
+            None => return Vec::new()
+        };
+        let peers = self.peers.lock().unwrap();
+        peer_ids.into_iter()
+        .filter_map(|peer_id| Some((peer_id, peers.get(&peer_id)?.name.clone()?)))
+        .collect()
+    }
+
+    pub fn set_grants(&self, channel_id: &str, peer_id: i64, grants: Grants) {
+        if let Some(subscriber) = self.shard(channel_id).channels.get_mut(channel_id).and_then(|state| state.peers.get_mut(&peer_id)) {
+            subscriber.grants = grants;
+        }
+    }
+
+    pub fn epoch(&self) -> String {
+        self.epoch.lock().unwrap().clone()
+    }
+
+    /// user names of everyone subscribed to the channel on any instance, sorted
+    pub fn presence(&self, channel_id: &str) -> Vec<String> {
+        let mut names: Vec<String> = match self.shard(channel_id).presence.get(channel_id) {
+            Some(presence) => presence.users.keys().cloned().collect(),
+            None => Vec::new()
+        };
+        names.sort();
+        names
+    }
+
+    /// false if peer wasn't subscribed to the channel
+    pub fn unsubscribe(&self, peer_id: i64, channel_id: &str) -> bool {
+        let name = match self.peers.lock().unwrap().get_mut(&peer_id) {
+            Some(entry) => match entry.channels.remove(channel_id) {
+                true => entry.name.clone(),
+                false => return false
+            },
+            None => return false // removed peer is in no channel
+        };
+        self.remove_from_channel(channel_id, peer_id, name.as_deref());
+        true
+    }
+
+    /// unsubscribes peer from the channel and tells it why, after messages of the channel that were already queued
+    pub fn revoke_subscription(&self, peer_id: i64, channel_id: &str, reason: UnsubscribeReason) {
+        let outbox = self.peers.lock().unwrap().get(&peer_id).map(|entry| entry.outbox.clone());
+        if let Some(outbox) = outbox {
+            if self.unsubscribe(peer_id, channel_id) {
+                outbox.unsubscribed(channel_id, reason);
+            }
+        }
+    }
+
+    pub fn channel_ids(&self, peer_id: i64) -> Vec<String> {
+        self.peers.lock().unwrap().get(&peer_id)
+        .map(|entry| entry.channels.iter().cloned().collect())
+        .unwrap_or_default()
+    }
+
+    /// removes peer from every channel, returns its outbox if it was still there
+    pub fn remove_peer(&self, peer_id: i64) -> Option<Arc<Outbox>> {
+        let entry = self.peers.lock().unwrap().remove(&peer_id)?;
+        for channel_id in &entry.channels {
+            self.remove_from_channel(channel_id, peer_id, entry.name.as_deref());
+        }
+        Some(entry.outbox)
+    }
+
+    pub fn peers_with_key(&self, key: &str) -> Vec<i64> {
+        self.peers.lock().unwrap().iter()
+        .filter(|(_, entry)| entry.key.as_deref() == Some(key))
+        .map(|(peer_id, _)| *peer_id)
+        .collect()
+    }
+
+    pub fn peer_ids(&self) -> Vec<i64> {
+        self.peers.lock().unwrap().keys().copied().collect()
+    }
+
+    /// numbers the message and queues it for every subscriber of the channel, doesn't wait for any of them,
+    /// ephemeral messages aren't numbered nor replayed, they carry `seq` of the last event,
+    /// channels nobody subscribed to (or that were dropped as idle) have nobody to resume either
+    pub fn fanout(&self, channel_id: &str, message: &LiveMessage) {
+        let mut shard = self.shard(channel_id);
+        let state = match shard.channels.get_mut(channel_id) {
+            Some(state) => state,
+            None => return
+        };
+        if !message.is_ephemeral() {
+            state.seq += 1;
+        }
+        if self.replay_capacity > 0 && !message.is_ephemeral() {
+            if state.buffer.len() >= self.replay_capacity {
+                state.buffer.pop_front();
+            }
+            state.buffer.push_back((state.seq, message.clone()));
+        }
+        for subscriber in state.peers.values() {
+            subscriber.push(channel_id, state.seq, message);
+        }
+    }
+
+    pub fn close_peer(&self, peer_id: i64, reason: CloseReason) {
+        if let Some(outbox) = self.remove_peer(peer_id) {
+            outbox.close(reason);
+        }
+    }
+
+    fn remove_from_channel(&self, channel_id: &str, peer_id: i64, name: Option<&str>) {
+        let mut shard = self.shard(channel_id);
+        if let Some(state) = shard.channels.get_mut(channel_id) {
+            if state.peers.remove(&peer_id).is_some() {
+                if let Some(name) = name {
+                    self.leave(state, channel_id, name);
+                }
+                if state.peers.is_empty() {
+                    state.idle_since = Some(Instant::now());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::live_channel::{outbox::{OverflowPolicy, Outgoing}, broker::MemoryBroker};
+
+    fn subscriber() -> Subscriber {
+        Subscriber {
+            outbox: Arc::new(Outbox::new(16, OverflowPolicy::DropOldest)),
+            grants: Grants { view_blocks: true, signal: false }
+        }
+    }
+
+    /// events 3..=5 are buffered, 1 and 2 are gone
+    fn channel() -> ChannelState {
+        ChannelState {
+            seq: 5,
+            buffer: (3..=5).map(|seq| (seq, LiveMessage::LabelsChanged)).collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn queued(outbox: &Outbox) -> Vec<u64> {
+        outbox.close(CloseReason::Shutdown);
+        let mut seqs = Vec::new();
+        while let Some(Outgoing::Message(_, seq, _)) = outbox.next().await {
+            seqs.push(seq);
+        }
+        seqs
+    }
+
+    #[actix_rt::test]
+    async fn replay_sends_events_after_last_seq() {
+        let subscriber = subscriber();
+        assert!(matches!(channel().replay(3, &subscriber, "channel"), Replay::Complete));
+        assert_eq!(queued(&subscriber.outbox).await, vec![4, 5]);
+    }
+
+    #[actix_rt::test]
+    async fn replay_of_up_to_date_peer_sends_nothing() {
+        let subscriber = subscriber();
+        assert!(matches!(channel().replay(5, &subscriber, "channel"), Replay::Complete));
+        assert!(queued(&subscriber.outbox).await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn replay_from_the_future_requires_resync() {
+        let subscriber = subscriber();
+        assert!(matches!(channel().replay(6, &subscriber, "channel"), Replay::ResyncRequired));
+        assert!(queued(&subscriber.outbox).await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn replay_past_the_buffer_requires_resync() {
+        let subscriber = subscriber();
+        assert!(matches!(channel().replay(1, &subscriber, "channel"), Replay::ResyncRequired));
+        assert!(queued(&subscriber.outbox).await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn replay_of_empty_buffer() {
+        let state = ChannelState { seq: 2, ..Default::default() };
+        assert!(matches!(state.replay(2, &subscriber(), "channel"), Replay::Complete));
+        assert!(matches!(state.replay(1, &subscriber(), "channel"), Replay::ResyncRequired));
+        assert!(matches!(ChannelState::default().replay(0, &subscriber(), "channel"), Replay::Complete));
+    }
+
+    #[test]
+    fn sweep_drops_channels_idle_past_ttl() {
+        let start = Instant::now();
+        let mut shard = Shard::new(start);
+        shard.channels.insert("idle".to_owned(), ChannelState { idle_since: Some(start), ..Default::default() });
+        shard.channels.insert("watched".to_owned(), ChannelState::default());
+        shard.sweep(start + SWEEP_INTERVAL);
+        assert_eq!(shard.channels.len(), 2);
+        shard.sweep(start + IDLE_TTL + SWEEP_INTERVAL);
+        assert!(!shard.channels.contains_key("idle"));
+        assert!(shard.channels.contains_key("watched"));
+    }
+
+    #[actix_rt::test]
+    async fn cursor_from_before_eviction_requires_resync() {
+        let start = Instant::now();
+        let mut shard = Shard::new(start);
+        shard.channels.insert("channel".to_owned(), ChannelState { idle_since: Some(start), ..channel() });
+        shard.sweep(start + IDLE_TTL + SWEEP_INTERVAL);
+        assert!(shard.channels.is_empty());
+        let subscriber = subscriber();
+        assert!(matches!(shard.channel("channel").replay(5, &subscriber, "channel"), Replay::ResyncRequired));
+        assert!(queued(&subscriber.outbox).await.is_empty());
+        assert!(shard.evicted.is_empty());
+    }
+
+    #[test]
+    fn fanout_keeps_no_state_for_unwatched_channels() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        registry.fanout("channel", &LiveMessage::LabelsChanged);
+        assert!(registry.shard("channel").channels.is_empty());
+    }
+
+    #[test]
+    fn removed_peer_is_not_subscribed() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        let peer_id = registry.add_peer(Arc::new(Outbox::new(16, OverflowPolicy::DropOldest)), None, Some("user".to_owned()));
+        registry.remove_peer(peer_id);
+        registry.subscribe(peer_id, "channel", Some(&Cursor { epoch: registry.epoch(), seq: 0 }), false, Grants { view_blocks: true, signal: false });
+        assert!(registry.shard("channel").channels.is_empty());
+        assert!(registry.presence("channel").is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn revoked_subscription_leaves_peer_connected() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        let grants = Grants { view_blocks: true, signal: false };
+        let outbox = Arc::new(Outbox::new(16, OverflowPolicy::DropOldest));
+        let peer_id = registry.add_peer(outbox.clone(), None, None);
+        registry.subscribe(peer_id, "lost", None, false, grants);
+        registry.subscribe(peer_id, "kept", None, false, grants);
+        registry.fanout("lost", &LiveMessage::LabelsChanged);
+        registry.revoke_subscription(peer_id, "lost", UnsubscribeReason::AccessLost);
+        registry.fanout("lost", &LiveMessage::LabelsChanged);
+        registry.fanout("kept", &LiveMessage::LabelsChanged);
+        assert_eq!(registry.channel_ids(peer_id), vec!["kept".to_owned()]);
+        assert!(matches!(outbox.next().await, Some(Outgoing::Message(channel_id, 1, _)) if channel_id == "lost"));
+        assert!(matches!(outbox.next().await, Some(Outgoing::Unsubscribed(channel_id, UnsubscribeReason::AccessLost)) if channel_id == "lost"));
+        assert!(matches!(outbox.next().await, Some(Outgoing::Message(channel_id, 1, _)) if channel_id == "kept"));
+    }
+
+    #[test]
+    fn cursor_of_another_epoch_requires_resync() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        let grants = Grants { view_blocks: true, signal: false };
+        let first = registry.add_peer(Arc::new(Outbox::new(16, OverflowPolicy::DropOldest)), None, None);
+        registry.subscribe(first, "channel", None, false, grants);
+        let second = registry.add_peer(Arc::new(Outbox::new(16, OverflowPolicy::DropOldest)), None, None);
+        let stale = Cursor { epoch: "other".to_owned(), seq: 0 };
+        assert!(matches!(registry.subscribe(second, "channel", Some(&stale), false, grants).replay, Replay::ResyncRequired));
+        let current = Cursor { epoch: registry.epoch(), seq: 0 };
+        assert!(matches!(registry.subscribe(second, "channel", Some(&current), false, grants).replay, Replay::Complete));
+    }
+
+    #[test]
+    fn reset_changes_epoch_and_forgets_channels() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        let peer_id = registry.add_peer(Arc::new(Outbox::new(16, OverflowPolicy::DropOldest)), None, None);
+        registry.subscribe(peer_id, "channel", None, false, Grants { view_blocks: true, signal: false });
+        registry.apply_presence("node", "channel", "user".to_owned(), true, true);
+        let epoch = registry.epoch();
+        registry.reset();
+        assert_ne!(registry.epoch(), epoch);
+        assert!(registry.peer_ids().is_empty());
+        assert!(registry.shard("channel").channels.is_empty());
+        assert!(registry.presence("channel").is_empty());
+    }
+
+    #[test]
+    fn user_is_present_while_on_any_node() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        registry.apply_presence("first", "channel", "user".to_owned(), true, true);
+        registry.apply_presence("second", "channel", "user".to_owned(), true, true);
+        registry.apply_presence("first", "channel", "user".to_owned(), false, true);
+        assert_eq!(registry.presence("channel"), vec!["user".to_owned()]);
+        registry.drop_node("second");
+        assert!(registry.presence("channel").is_empty());
+    }
+
+    #[test]
+    fn presence_changes_of_the_cluster_are_announced_once() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        let outbox = Arc::new(Outbox::new(16, OverflowPolicy::DropOldest));
+        let peer_id = registry.add_peer(outbox.clone(), None, None);
+        registry.subscribe(peer_id, "channel", None, false, Grants { view_blocks: true, signal: false });
+        registry.apply_presence("first", "channel", "user".to_owned(), true, true);
+        registry.apply_presence("second", "channel", "user".to_owned(), true, true);
+        registry.drop_node("first");
+        registry.drop_node("second");
+        assert_eq!(registry.shard("channel").channels["channel"].seq, 2); // joined and left
+    }
+
+    #[test]
+    fn channel_without_peers_keeps_buffering_until_evicted() {
+        let registry = Registry::new(8, Arc::new(MemoryBroker::default()));
+        let peer_id = registry.add_peer(Arc::new(Outbox::new(16, OverflowPolicy::DropOldest)), None, None);
+        registry.subscribe(peer_id, "channel", None, false, Grants { view_blocks: true, signal: false });
+        registry.unsubscribe(peer_id, "channel");
+        registry.fanout("channel", &LiveMessage::LabelsChanged);
+        let shard = registry.shard("channel");
+        let state = &shard.channels["channel"];
+        assert_eq!(state.seq, 1);
+        assert!(state.idle_since.is_some());
+    }
+}
@@ -1,11 +1,12 @@
 use tokio::io::AsyncWriteExt;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use tokio::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
 use tokio::fs;
 
 pub struct Logger {
     sender: UnboundedSender<String>,
-    receiver: Mutex<UnboundedReceiver<String>>
+    receiver: Mutex<UnboundedReceiver<String>>,
+    shutdown: Notify
 }
 
 impl Logger {
@@ -13,7 +14,8 @@ impl Logger {
         let (sender, receiver) = mpsc::unbounded_channel();
         Self {
             sender,
-            receiver: Mutex::new(receiver)
+            receiver: Mutex::new(receiver),
+            shutdown: Notify::new()
         }
     }
 
@@ -29,18 +31,39 @@ impl Logger {
         }
     }
 
+    /// makes `run` stop accepting new logs, write everything that is already queued and return
+    pub fn shutdown(&self){
+        self.shutdown.notify_one();
+    }
+
+    async fn write(&self, log: String){
+        let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open("logs.txt").await else {
+            println!("[failed to open logs-file]: {log}");
+            return;
+        };
+
+        let Ok(()) = file.write_all(format!("{log}\n").as_bytes()).await else {
+            println!("[failed to write logs-file]: {log}");
+            return;
+        };
+    }
+
     pub async fn run(&self){
         let mut receiver = self.receiver.lock().await;
-        while let Some(log) = receiver.recv().await {
-            let Ok(mut file) = fs::File::open("logs.txt").await else {
-                println!("[failed to open logs-file]: {log}");
-                continue;
-            };
-
-            let Ok(()) = file.write_all(log.as_bytes()).await else {
-                println!("[failed to write logs-file]: {log}");
-                continue;
-            };
+        loop {
+            tokio::select! {
+                log = receiver.recv() => match log {
+                    Some(log) => self.write(log).await,
+                    None => break
+                },
+                _ = self.shutdown.notified() => {
+                    receiver.close();
+                    while let Some(log) = receiver.recv().await {
+                        self.write(log).await;
+                    }
+                    break;
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,51 @@
+use async_trait::async_trait;
+pub use smtp::{SmtpMailer, SmtpConfig};
+pub use outbox::OutboxMailer;
+
+mod smtp;
+mod outbox;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid address: {0}")]
+    Address(lettre::address::AddressError),
+    #[error("failed to build message: {0}")]
+    Message(lettre::error::Error),
+    #[error("smtp: {0}")]
+    Smtp(lettre::transport::smtp::Error),
+    #[error("io: {0}")]
+    Io(std::io::Error)
+}
+impl From<lettre::address::AddressError> for Error {
+    fn from(value: lettre::address::AddressError) -> Self {
+        Self::Address(value)
+    }
+}
+impl From<lettre::error::Error> for Error {
+    fn from(value: lettre::error::Error) -> Self {
+        Self::Message(value)
+    }
+}
+impl From<lettre::transport::smtp::Error> for Error {
+    fn from(value: lettre::transport::smtp::Error) -> Self {
+        Self::Smtp(value)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer {
+    async fn send(&self, mail: &Mail) -> Result<(), Error>;
+}
+
+pub type MailerShared = std::sync::Arc<dyn Mailer + Send + Sync>;
@@ -0,0 +1,26 @@
+use std::path::PathBuf;
+use async_trait::async_trait;
+use tokio::fs;
+use crate::auth_validator::generate_secret;
+use super::{Mailer, Mail, Error};
+
+/// writes every mail into its own file instead of sending it, for running locally without a mail server
+pub struct OutboxMailer {
+    directory: PathBuf
+}
+
+impl OutboxMailer {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {directory: directory.into()}
+    }
+}
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), Error> {
+        fs::create_dir_all(&self.directory).await?;
+        let path = self.directory.join(format!("{}-{}.txt", chrono::Utc::now().timestamp_millis(), generate_secret()));
+        fs::write(path, format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body)).await?;
+        Ok(())
+    }
+}
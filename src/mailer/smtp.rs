@@ -0,0 +1,41 @@
+use async_trait::async_trait;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::Mailbox, transport::smtp::authentication::Credentials};
+use super::{Mailer, Mail, Error};
+
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox
+}
+
+impl SmtpMailer {
+    pub fn new(config: &SmtpConfig) -> Result<Self, Error> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?
+        .credentials(Credentials::new(config.username.clone(), config.password.clone()))
+        .build();
+        Ok(Self {
+            transport,
+            from: config.from.parse()?
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), Error> {
+        let message = Message::builder()
+        .from(self.from.clone())
+        .to(mail.to.parse()?)
+        .subject(mail.subject.clone())
+        .body(mail.body.clone())?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
@@ -1,12 +1,17 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration, collections::HashSet};
 
 use activity_logger::ActivityLogger;
-use auth_validator::AuthValidator;
+use auth_validator::{AuthValidator, KeyRing, KeyRings};
 use db_pool::DbPool;
 use http_server::HttpServer;
+use janitor::Janitor;
 use live_channel::LiveChannel;
 use logger::Logger;
-use session_pool::SessionPool;
+use mailer::{MailerShared, OutboxMailer, SmtpMailer};
+use oidc::OidcClient;
+use password_hasher::PasswordHasher;
+use rate_limiter::RateLimiter;
+use session_pool::{SessionPool, LiveAccess};
 use ts_rs::TS;
 
 mod db_pool;
@@ -17,37 +22,233 @@ mod live_channel;
 mod logger;
 mod auth_validator;
 mod activity_logger;
+mod mailer;
+mod janitor;
+mod oidc;
+mod password_hasher;
+mod rate_limiter;
+
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+const DEFAULT_HTTP_SHUTDOWN_TIMEOUT: u64 = 10;
+const DEFAULT_ACCESS_TOKEN_LIFETIME: i64 = 60 * 15;
+const DEFAULT_KEY_TOKEN_LIFETIME: i64 = 60 * 60 * 24 * 30;
+const DEFAULT_PASSWORD_RESET_LIFETIME: i64 = 60 * 60;
+const DEFAULT_UNVERIFIED_USER_TTL: i64 = 60 * 60 * 24 * 7;
+const DEFAULT_LOGIN_CHALLENGE_LIFETIME: i64 = 60 * 5;
+const DEFAULT_ARGON2_MEMORY_COST: u32 = 19 * 1024; // owasp recommended minimum
+const DEFAULT_ARGON2_TIME_COST: u32 = 2;
+const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+const DEFAULT_PASSWORD_MIN_LENGTH: usize = 7;
+const DEFAULT_PASSWORD_MAX_LENGTH: usize = 128;
+const DEFAULT_LOCKOUT_ACCOUNT_ATTEMPTS: i64 = 5;
+const DEFAULT_LOCKOUT_IP_ATTEMPTS: i64 = 20;
+const DEFAULT_LOCKOUT_BASE_DELAY: i64 = 30;
+const DEFAULT_LOCKOUT_MAX_DELAY: i64 = 60 * 60;
+const DEFAULT_LOGIN_ATTEMPTS_TTL: i64 = 60 * 60 * 24;
+const DEFAULT_LIVE_PING_INTERVAL: u64 = 30;
+const DEFAULT_LIVE_IDLE_TIMEOUT: u64 = 90;
+const DEFAULT_LIVE_QUEUE_CAPACITY: usize = 256;
+const DEFAULT_LIVE_REPLAY_CAPACITY: usize = 128;
+const DEFAULT_LIVE_SIGNAL_RATE: &str = "30/3";
+const DEFAULT_LIVE_SIGNAL_MAX_SIZE: usize = 1024;
+const DEFAULT_LIVE_RELAY_ADDRESS: &str = "127.0.0.1:7700";
+const DEFAULT_RATE_LIMIT: &str = "300/60";
+const DEFAULT_ROUTE_RATE_LIMITS: &str = "/api/auth/login=10/60,/api/auth/join=5/60,/api/auth/password/request-reset=5/60,/api/blocks/create=30/60,/api/blocks/change=60/60";
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok()
+    .and_then(|value| value.parse().ok())
+    .unwrap_or(default)
+}
+
+async fn shutdown_signal(){
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.unwrap();
+}
 
 #[tokio::main]
 async fn main(){
     dotenv::dotenv().ok();
     
+    let key_rings = match std::env::var("KEY_RING_FILE") { // without key ring, tokens are signed with single HS512 secrets
+        Ok(path) => KeyRings::load(&path).unwrap(),
+        Err(_) => KeyRings {
+            access: KeyRing::from_secret(&std::env::var("ACCESS_KEY").unwrap()),
+            key: KeyRing::from_secret(&std::env::var("KEY_KEY").unwrap())
+        }
+    };
     let auth_keys = auth_validator::Keys {
-        access: std::env::var("ACCESS_KEY").unwrap(),
-        key: std::env::var("KEY_KEY").unwrap()
+        access: key_rings.access,
+        key: key_rings.key,
+        verification: std::env::var("VERIFICATION_KEY").unwrap(),
+        challenge: std::env::var("CHALLENGE_KEY").unwrap()
+    };
+    let unverified_user_ttl = chrono::Duration::seconds(env_or("UNVERIFIED_USER_TTL", DEFAULT_UNVERIFIED_USER_TTL));
+    let auth_lifetimes = auth_validator::Lifetimes { // in seconds
+        access: chrono::Duration::seconds(env_or("ACCESS_TOKEN_LIFETIME", DEFAULT_ACCESS_TOKEN_LIFETIME)),
+        key: chrono::Duration::seconds(env_or("KEY_TOKEN_LIFETIME", DEFAULT_KEY_TOKEN_LIFETIME)),
+        verification: unverified_user_ttl, // link is useless after account gets deleted anyway
+        challenge: chrono::Duration::seconds(env_or("LOGIN_CHALLENGE_LIFETIME", DEFAULT_LOGIN_CHALLENGE_LIFETIME))
+    };
+    let shutdown_timeout = env_or("SHUTDOWN_TIMEOUT", DEFAULT_SHUTDOWN_TIMEOUT);
+    let http_shutdown_timeout = env_or("HTTP_SHUTDOWN_TIMEOUT", DEFAULT_HTTP_SHUTDOWN_TIMEOUT).min(shutdown_timeout / 2);
+    let session_config = session_pool::Config {
+        public_url: std::env::var("PUBLIC_URL").unwrap(),
+        password_reset_lifetime: chrono::Duration::seconds(env_or("PASSWORD_RESET_LIFETIME", DEFAULT_PASSWORD_RESET_LIFETIME)),
+        lockout: session_pool::LockoutConfig {
+            account_attempts: env_or("LOCKOUT_ACCOUNT_ATTEMPTS", DEFAULT_LOCKOUT_ACCOUNT_ATTEMPTS),
+            ip_attempts: env_or("LOCKOUT_IP_ATTEMPTS", DEFAULT_LOCKOUT_IP_ATTEMPTS),
+            base_delay: env_or("LOCKOUT_BASE_DELAY", DEFAULT_LOCKOUT_BASE_DELAY),
+            max_delay: env_or("LOCKOUT_MAX_DELAY", DEFAULT_LOCKOUT_MAX_DELAY)
+        },
+        admins: std::env::var("ADMINS").unwrap_or_default() // comma separated user names
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+    };
+    let mailer: MailerShared = match std::env::var("SMTP_HOST") { // without smtp mails are written to outbox directory
+        Ok(host) => Arc::new(SmtpMailer::new(&mailer::SmtpConfig {
+            host,
+            username: std::env::var("SMTP_USERNAME").unwrap(),
+            password: std::env::var("SMTP_PASSWORD").unwrap(),
+            from: std::env::var("MAIL_FROM").unwrap()
+        }).unwrap()),
+        Err(_) => Arc::new(OutboxMailer::new(std::env::var("OUTBOX_DIR").unwrap_or("outbox".to_string())))
+    };
+    let oidc = std::env::var("OIDC_ISSUER").ok().map(|issuer| Arc::new(OidcClient::new(oidc::Config { // oidc login is enabled only with issuer
+        issuer: issuer.trim_end_matches('/').to_string(),
+        client_id: std::env::var("OIDC_CLIENT_ID").unwrap(),
+        client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+        redirect_url: std::env::var("OIDC_REDIRECT_URL").unwrap_or(format!("{}/oidc/callback", session_config.public_url)),
+        scopes: std::env::var("OIDC_SCOPES").unwrap_or("openid profile email".to_string()),
+        username: oidc::UsernameMapping {
+            claim: std::env::var("OIDC_USERNAME_CLAIM").unwrap_or("preferred_username".to_string()),
+            strip_domain: env_or("OIDC_USERNAME_STRIP_DOMAIN", true),
+            lowercase: env_or("OIDC_USERNAME_LOWERCASE", true)
+        }
+    })));
+    let breached_passwords = match std::env::var("BREACHED_PASSWORDS_FILE") {
+        Ok(path) => password_hasher::load_breached_list(&path).unwrap(),
+        Err(_) => HashSet::new()
     };
-    let auth_validator = Arc::new(AuthValidator::new(&auth_keys));
+    let password_hasher = Arc::new(PasswordHasher::new(password_hasher::Config {
+        memory_cost: env_or("ARGON2_MEMORY_COST", DEFAULT_ARGON2_MEMORY_COST),
+        time_cost: env_or("ARGON2_TIME_COST", DEFAULT_ARGON2_TIME_COST),
+        parallelism: env_or("ARGON2_PARALLELISM", DEFAULT_ARGON2_PARALLELISM),
+        min_length: env_or("PASSWORD_MIN_LENGTH", DEFAULT_PASSWORD_MIN_LENGTH),
+        max_length: env_or("PASSWORD_MAX_LENGTH", DEFAULT_PASSWORD_MAX_LENGTH)
+    }, breached_passwords).unwrap());
+    let auth_validator = Arc::new(AuthValidator::new(auth_keys, &auth_lifetimes));
     let logger = Arc::new(Logger::new());
-    let db_pool = Arc::new(DbPool::new(std::env::var("DB_ADDRESS").unwrap().as_str()).await.unwrap());
-    let live_channel = Arc::new(LiveChannel::new(logger.clone()));
+    let db_pool = Arc::new(DbPool::new(std::env::var("DB_ADDRESS").unwrap().as_str()).await.unwrap_or_else(|error| panic!("{error}")));
+    let live_relay_secret = std::env::var("LIVE_RELAY_SECRET").unwrap_or_default(); // shared by hub and instances, hub without it listens only on loopback
+    let live_broker: live_channel::broker::BrokerShared = match std::env::var("LIVE_BROKER").as_deref() { // relay shares events between instances
+        Ok("relay") => Arc::new(live_channel::broker::RelayBroker::new(&std::env::var("LIVE_RELAY_ADDRESS").unwrap_or(DEFAULT_LIVE_RELAY_ADDRESS.to_string()), &live_relay_secret, logger.clone())),
+        _ => Arc::new(live_channel::broker::MemoryBroker::default())
+    };
+    let relay_hub = std::env::var("LIVE_RELAY_LISTEN").ok() // host:port or unix:/path, one instance (or a separate one) hosts the relay
+    .map(|address| Arc::new(live_channel::broker::RelayHub::new(&address, &live_relay_secret, logger.clone())));
+    let live_channel = Arc::new(LiveChannel::new(live_channel::Config {
+        queue_capacity: env_or("LIVE_QUEUE_CAPACITY", DEFAULT_LIVE_QUEUE_CAPACITY),
+        overflow: env_or("LIVE_OVERFLOW_POLICY", live_channel::OverflowPolicy::DropOldest), // drop_oldest or disconnect
+        replay_capacity: env_or("LIVE_REPLAY_CAPACITY", DEFAULT_LIVE_REPLAY_CAPACITY),
+        signal_rate: rate_limiter::Policy::parse(&std::env::var("LIVE_SIGNAL_RATE").unwrap_or(DEFAULT_LIVE_SIGNAL_RATE.to_string())).unwrap(), // capacity/period per user
+        signal_max_size: env_or("LIVE_SIGNAL_MAX_SIZE", DEFAULT_LIVE_SIGNAL_MAX_SIZE)
+    }, live_broker, Arc::new(LiveAccess::new(db_pool.clone())), logger.clone()));
     let activity_logger = Arc::new(ActivityLogger::new(db_pool.clone(), logger.clone()));
-    let session_pool = Arc::new(SessionPool::new(db_pool, auth_validator.clone(), live_channel.clone(), activity_logger.clone(), logger.clone())); // everything.clone()
-    let http_server = Arc::new(HttpServer::new(session_pool, logger.clone()));
-
-    let handle = std::thread::spawn(|| {
-        let system = actix::System::new();
-        system.block_on(async move {
-            http_server.run().await;
-        });
+    let janitor = Arc::new(Janitor::new(db_pool.clone(), janitor::Config {
+        unverified_user_ttl,
+        login_attempts_ttl: chrono::Duration::seconds(env_or("LOGIN_ATTEMPTS_TTL", DEFAULT_LOGIN_ATTEMPTS_TTL))
+    }, logger.clone()));
+    let rate_limit_store: rate_limiter::StoreShared = match std::env::var("RATE_LIMIT_STORE").as_deref() { // db store is shared between instances
+        Ok("db") => Arc::new(rate_limiter::DbStore::new(db_pool.clone())),
+        _ => Arc::new(rate_limiter::MemoryStore::default())
+    };
+    let rate_limiter = Arc::new(RateLimiter::new(
+        rate_limit_store,
+        rate_limiter::Policy::parse(&std::env::var("RATE_LIMIT_DEFAULT").unwrap_or(DEFAULT_RATE_LIMIT.to_string())).unwrap(),
+        rate_limiter::parse_route_policies(&std::env::var("RATE_LIMITS").unwrap_or(DEFAULT_ROUTE_RATE_LIMITS.to_string())), // path=capacity/period, comma separated
+        logger.clone()
+    ));
+    let public_url = session_config.public_url.clone();
+    let session_pool = Arc::new(SessionPool::new(db_pool, auth_validator.clone(), live_channel.clone(), activity_logger.clone(), mailer, oidc, password_hasher, session_config, logger.clone())); // everything.clone()
+    let http_config = http_server::Config {
+        shutdown_timeout: http_shutdown_timeout,
+        live: http_server::LiveConfig { // in seconds
+            ping_interval: env_or("LIVE_PING_INTERVAL", DEFAULT_LIVE_PING_INTERVAL),
+            idle_timeout: env_or("LIVE_IDLE_TIMEOUT", DEFAULT_LIVE_IDLE_TIMEOUT)
+        },
+        allowed_origins: std::env::var("ALLOWED_ORIGINS").unwrap_or(public_url.clone()) // comma separated, public url by default
+        .split(',')
+        .filter_map(|origin| http_server::origin_of(origin.trim()))
+        .collect(),
+        trusted_proxy: env_or("TRUSTED_PROXY", false) // client ip is taken from forwarding headers only when true
+    };
+    let http_server = Arc::new(HttpServer::new(session_pool, auth_validator, rate_limiter, http_config, logger.clone()));
+
+    let handle = std::thread::spawn({
+        let http_server = http_server.clone();
+        || {
+            let system = actix::System::new();
+            system.block_on(async move {
+                http_server.run().await;
+            });
+        }
     });
     println!("run http server");
 
-    tokio_scoped::scope(|scope| {
-        scope.spawn(logger.run());
-        scope.spawn(activity_logger.run());
-        scope.spawn(live_channel.run());
-    });
+    let runners = async {
+        tokio::join!(
+            logger.run(),
+            async {
+                activity_logger.run().await;
+                logger.shutdown(); // activity logger logs its errors, so it must be drained first
+            },
+            live_channel.run(),
+            async {
+                if let Some(relay_hub) = &relay_hub {
+                    relay_hub.run().await;
+                }
+            },
+            janitor.run()
+        )
+    };
+    tokio::pin!(runners);
+
+    tokio::select! {
+        _ = &mut runners => {},
+        _ = shutdown_signal() => {
+            println!("shutting down");
+            let shutdown = async {
+                janitor.shutdown();
+                http_server.pause().await;
+                // live peers hold their connections open, so they are closed before waiting for in-flight requests
+                live_channel.shutdown();
+                if let Some(relay_hub) = &relay_hub {
+                    relay_hub.shutdown();
+                }
+                if tokio::time::timeout(Duration::from_secs(http_shutdown_timeout), http_server.stop()).await.is_err() {
+                    println!("[http server stop timed out after {http_shutdown_timeout}s]");
+                }
+                activity_logger.shutdown(); // after http server, so that activities of finished requests are flushed too
+            };
+            // http server takes at most half of it, the rest is left for flushing logs
+            if tokio::time::timeout(Duration::from_secs(shutdown_timeout), async { tokio::join!(shutdown, &mut runners) }).await.is_err() {
+                println!("[shutdown timed out after {shutdown_timeout}s, exiting anyway]");
+                std::process::exit(1);
+            }
+        }
+    }
 
     handle.join().unwrap();
-    dbg!("run all");
+    println!("shut down"); // logger is already drained here
 }
\ No newline at end of file
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use sha2::{Sha256, Digest};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, jwk::JwkSet};
+use rand::{Rng, distributions::Alphanumeric};
+use tokio::sync::OnceCell;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("request to identity provider failed: {0}")]
+    Http(reqwest::Error),
+    #[error("invalid authorization endpoint: {0}")]
+    Url(String),
+    #[error("identity provider rejected request: {0}")]
+    Provider(String),
+    #[error("invalid id token: {0}")]
+    IdToken(jsonwebtoken::errors::Error),
+    #[error("id token is signed with unknown key")]
+    UnknownKey,
+    #[error("id token is signed with unsupported algorithm {0:?}")]
+    UnsupportedAlgorithm(Algorithm),
+    #[error("id token nonce doesn't match")]
+    InvalidNonce,
+}
+impl From<reqwest::Error> for Error {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Http(value)
+    }
+}
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(value: jsonwebtoken::errors::Error) -> Self {
+        Self::IdToken(value)
+    }
+}
+
+/// how username of newly created users is derived from id token claims
+#[derive(Clone, Debug)]
+pub struct UsernameMapping {
+    /// claim to take the name from, `sub` is used when it is missing
+    pub claim: String,
+    /// cut everything from `@`, for claims that hold an email
+    pub strip_domain: bool,
+    pub lowercase: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// issuer url without trailing slash, `http` is allowed so that a local mock provider can be used
+    pub issuer: String,
+    pub client_id: String,
+    /// public clients rely on pkce only
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+    pub username: UsernameMapping,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IdClaims {
+    pub iss: String,
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+impl IdClaims {
+    /// email only if provider vouches for it, only such email may link to an existing account
+    pub fn verified_email(&self) -> Option<&str> {
+        self.email.as_deref().filter(|_| self.email_verified == Some(true))
+    }
+
+    /// identity may be linked to an existing account with the same email only if both provider and the account verified it,
+    /// otherwise whoever registered the email first (without owning it) would share the account
+    pub fn links_to(&self, account_verified: bool) -> bool {
+        account_verified && self.verified_email().is_some()
+    }
+
+    pub fn claim(&self, name: &str) -> Option<&str> {
+        match name {
+            "sub" => Some(&self.sub),
+            "email" => self.email.as_deref(),
+            _ => self.other.get(name).and_then(|value| value.as_str())
+        }
+    }
+}
+
+/// everything that has to survive the round trip through the identity provider
+pub struct Request {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+}
+
+fn random_string(length: usize) -> String {
+    rand::thread_rng()
+    .sample_iter(&Alphanumeric)
+    .take(length)
+    .map(char::from)
+    .collect()
+}
+
+/// pkce S256 code challenge
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+pub struct OidcClient {
+    config: Config,
+    http: reqwest::Client,
+    discovery: OnceCell<Discovery>,
+}
+
+impl OidcClient {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            discovery: OnceCell::new()
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// provider metadata is fetched once, on first login
+    async fn discovery(&self) -> Result<&Discovery, Error> {
+        self.discovery.get_or_try_init(|| async {
+            let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+            Ok(self.http.get(url).send().await?.error_for_status()?.json::<Discovery>().await?)
+        }).await
+    }
+
+    pub async fn authorization_request(&self) -> Result<Request, Error> {
+        let discovery = self.discovery().await?;
+        let state = random_string(32);
+        let nonce = random_string(32);
+        let verifier = random_string(64);
+        let url = reqwest::Url::parse_with_params(&discovery.authorization_endpoint, &[
+            ("response_type", "code"),
+            ("client_id", &self.config.client_id),
+            ("redirect_uri", &self.config.redirect_url),
+            ("scope", &self.config.scopes),
+            ("state", &state),
+            ("nonce", &nonce),
+            ("code_challenge", &code_challenge(&verifier)),
+            ("code_challenge_method", "S256"),
+        ]).map_err(|error| Error::Url(error.to_string()))?;
+        Ok(Request { url: url.to_string(), state, nonce, verifier })
+    }
+
+    /// exchanges authorization code for id token and returns its verified claims
+    pub async fn exchange_code(&self, code: &str, verifier: &str, nonce: &str) -> Result<IdClaims, Error> {
+        let discovery = self.discovery().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_url),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let response = self.http.post(&discovery.token_endpoint).form(&form).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Provider(response.text().await.unwrap_or_default()));
+        }
+        let id_token = response.json::<TokenResponse>().await?.id_token;
+        let claims = self.verify_id_token(&id_token, &discovery.jwks_uri).await?;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(Error::InvalidNonce);
+        }
+        Ok(claims)
+    }
+
+    async fn verify_id_token(&self, id_token: &str, jwks_uri: &str) -> Result<IdClaims, Error> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(Error::UnsupportedAlgorithm(header.alg));
+        }
+        // keys are fetched every time, so that rotation on provider side just works
+        let jwks = self.http.get(jwks_uri).send().await?.error_for_status()?.json::<JwkSet>().await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None
+        }.ok_or(Error::UnknownKey)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        Ok(jsonwebtoken::decode::<IdClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+    use actix_web::{App, HttpServer, HttpResponse, web};
+    use jsonwebtoken::{EncodingKey, Header};
+    use ring::{rand::SystemRandom, signature::{Ed25519KeyPair, KeyPair}};
+    use serde_json::json;
+    use crate::auth_validator::{AuthValidator, Keys, Lifetimes, KeyRing};
+    use super::*;
+
+    const CLIENT_ID: &str = "chane";
+
+    /// provider that checks pkce like a real one and signs id tokens with a fresh ed25519 key
+    struct MockProvider {
+        issuer: String,
+        key: Vec<u8>,
+        public: String,
+        /// code -> (code challenge, nonce)
+        codes: Mutex<HashMap<String, (String, String)>>,
+        email_verified: Mutex<Option<bool>>,
+    }
+
+    impl MockProvider {
+        /// what provider remembers when user approves login at `url`, returns the code
+        fn approve(&self, url: &str) -> String {
+            let url = reqwest::Url::parse(url).unwrap();
+            let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+            assert_eq!(params["code_challenge_method"], "S256");
+            let code = random_string(16);
+            self.codes.lock().unwrap().insert(code.clone(), (params["code_challenge"].clone(), params["nonce"].clone()));
+            code
+        }
+    }
+
+    async fn discovery(provider: web::Data<MockProvider>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "authorization_endpoint": format!("{}/authorize", provider.issuer),
+            "token_endpoint": format!("{}/token", provider.issuer),
+            "jwks_uri": format!("{}/jwks", provider.issuer)
+        }))
+    }
+
+    async fn jwks(provider: web::Data<MockProvider>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({"keys": [{
+            "kty": "OKP", "crv": "Ed25519", "kid": "mock", "alg": "EdDSA", "use": "sig", "x": provider.public
+        }]}))
+    }
+
+    async fn token(provider: web::Data<MockProvider>, form: web::Form<HashMap<String, String>>) -> HttpResponse {
+        let Some((challenge, nonce)) = provider.codes.lock().unwrap().remove(&form["code"]) else {
+            return HttpResponse::BadRequest().body("invalid_grant");
+        };
+        if form.get("code_verifier").map(|verifier| code_challenge(verifier)) != Some(challenge) {
+            return HttpResponse::BadRequest().body("invalid_grant: pkce");
+        }
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some("mock".to_string());
+        let claims = json!({
+            "iss": provider.issuer, "aud": CLIENT_ID, "sub": "123",
+            "exp": chrono::Utc::now().timestamp() + 60, "nonce": nonce,
+            "email": "user@example.com", "email_verified": *provider.email_verified.lock().unwrap(),
+            "preferred_username": "user"
+        });
+        let id_token = jsonwebtoken::encode(&header, &claims, &EncodingKey::from_ed_der(&provider.key)).unwrap();
+        HttpResponse::Ok().json(json!({"id_token": id_token}))
+    }
+
+    async fn start_provider() -> (web::Data<MockProvider>, OidcClient) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap().as_ref().to_vec();
+        let public = URL_SAFE_NO_PAD.encode(Ed25519KeyPair::from_pkcs8(&key).unwrap().public_key().as_ref());
+        let provider = web::Data::new(MockProvider {
+            issuer: issuer.clone(),
+            key,
+            public,
+            codes: Mutex::new(HashMap::new()),
+            email_verified: Mutex::new(Some(true))
+        });
+        let data = provider.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+            .app_data(data.clone())
+            .route("/.well-known/openid-configuration", web::get().to(discovery))
+            .route("/jwks", web::get().to(jwks))
+            .route("/token", web::post().to(token))
+        })
+        .workers(1)
+        .listen(listener).unwrap()
+        .run();
+        actix_rt::spawn(server);
+
+        let client = OidcClient::new(Config {
+            issuer,
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost/oidc/callback".to_string(),
+            scopes: "openid email".to_string(),
+            username: UsernameMapping { claim: "preferred_username".to_string(), strip_domain: false, lowercase: true }
+        });
+        (provider, client)
+    }
+
+    fn auth_validator() -> AuthValidator {
+        let lifetime = chrono::Duration::seconds(60);
+        AuthValidator::new(Keys {
+            access: KeyRing::from_secret("access"),
+            key: KeyRing::from_secret("key"),
+            verification: "verification".to_string(),
+            challenge: "challenge".to_string()
+        }, &Lifetimes { access: lifetime, key: lifetime, verification: lifetime, challenge: lifetime })
+    }
+
+    #[actix_rt::test]
+    async fn authorization_request_carries_fresh_state_nonce_and_pkce_challenge() {
+        let (_provider, client) = start_provider().await;
+        let request = client.authorization_request().await.unwrap();
+        let url = reqwest::Url::parse(&request.url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["state"], request.state);
+        assert_eq!(params["nonce"], request.nonce);
+        assert_eq!(params["code_challenge"], code_challenge(&request.verifier));
+        assert!(!url.as_str().contains(&request.verifier));
+
+        let other = client.authorization_request().await.unwrap();
+        assert_ne!(other.state, request.state);
+        assert_ne!(other.nonce, request.nonce);
+        assert_ne!(other.verifier, request.verifier);
+    }
+
+    #[actix_rt::test]
+    async fn state_token_only_accepts_its_own_state() {
+        let (_provider, client) = start_provider().await;
+        let auth_validator = auth_validator();
+        let request = client.authorization_request().await.unwrap();
+        let state_token = auth_validator.oidc_state_token(&request.state, &request.nonce, &request.verifier).unwrap();
+
+        assert!(auth_validator.decode_oidc_state_token(&state_token, "forged").is_err());
+        let claims = auth_validator.decode_oidc_state_token(&state_token, &request.state).unwrap();
+        assert_eq!(claims.nonce, request.nonce);
+        assert_eq!(claims.verifier, request.verifier);
+    }
+
+    #[actix_rt::test]
+    async fn code_exchange_requires_matching_pkce_verifier() {
+        let (provider, client) = start_provider().await;
+        let request = client.authorization_request().await.unwrap();
+        let code = provider.approve(&request.url);
+        assert!(matches!(client.exchange_code(&code, "wrong verifier", &request.nonce).await, Err(Error::Provider(_))));
+
+        let code = provider.approve(&request.url);
+        let claims = client.exchange_code(&code, &request.verifier, &request.nonce).await.unwrap();
+        assert_eq!(claims.sub, "123");
+        assert_eq!(claims.claim("preferred_username"), Some("user"));
+        // codes are single use on provider side too
+        assert!(client.exchange_code(&code, &request.verifier, &request.nonce).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn id_token_with_other_nonce_is_rejected() {
+        let (provider, client) = start_provider().await;
+        let request = client.authorization_request().await.unwrap();
+        let other = client.authorization_request().await.unwrap();
+        let code = provider.approve(&other.url);
+        assert!(matches!(client.exchange_code(&code, &other.verifier, &request.nonce).await, Err(Error::InvalidNonce)));
+    }
+
+    #[actix_rt::test]
+    async fn only_verified_email_links_to_existing_account() {
+        let (provider, client) = start_provider().await;
+        for (email_verified, linkable) in [(Some(true), true), (Some(false), false), (None, false)] {
+            *provider.email_verified.lock().unwrap() = email_verified;
+            let request = client.authorization_request().await.unwrap();
+            let code = provider.approve(&request.url);
+            let claims = client.exchange_code(&code, &request.verifier, &request.nonce).await.unwrap();
+            assert_eq!(claims.email.as_deref(), Some("user@example.com"));
+            assert_eq!(claims.verified_email().is_some(), linkable);
+            assert_eq!(claims.links_to(true), linkable);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn verified_email_does_not_link_to_unverified_account() {
+        let (provider, client) = start_provider().await;
+        let request = client.authorization_request().await.unwrap();
+        let code = provider.approve(&request.url);
+        let claims = client.exchange_code(&code, &request.verifier, &request.nonce).await.unwrap();
+        assert!(claims.verified_email().is_some());
+        assert!(!claims.links_to(false)); // someone else may have registered the email
+    }
+}
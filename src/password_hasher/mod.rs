@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use argon2::{Argon2, Algorithm, Version, Params, PasswordHash, PasswordHasher as _, PasswordVerifier, password_hash::SaltString};
+use pwhash::bcrypt;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid argon2 parameters: {0}")]
+    Params(argon2::Error),
+    #[error("failed to hash password: {0}")]
+    Hashing(argon2::password_hash::Error),
+    #[error("failed to read breached passwords: {0}")]
+    Io(std::io::Error),
+    #[error("hashing task failed: {0}")]
+    Task(tokio::task::JoinError),
+}
+impl From<argon2::Error> for Error {
+    fn from(value: argon2::Error) -> Self {
+        Self::Params(value)
+    }
+}
+impl From<argon2::password_hash::Error> for Error {
+    fn from(value: argon2::password_hash::Error) -> Self {
+        Self::Hashing(value)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<tokio::task::JoinError> for Error {
+    fn from(value: tokio::task::JoinError) -> Self {
+        Self::Task(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum PolicyViolation {
+    TooShort,
+    TooLong,
+    Breached,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// in KiB
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+    pub min_length: usize,
+    pub max_length: usize,
+}
+
+#[derive(PartialEq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// valid, but hashed with bcrypt or with other argon2 parameters than the current ones
+    Outdated,
+}
+
+pub struct PasswordHasher {
+    argon2: Argon2<'static>,
+    config: Config,
+    /// lowercase, compared case insensitively
+    breached: HashSet<String>,
+}
+
+/// one password per line, empty lines are ignored
+pub fn load_breached_list(path: &str) -> Result<HashSet<String>, Error> {
+    Ok(std::fs::read_to_string(path)?
+    .lines()
+    .map(|line| line.trim().to_lowercase())
+    .filter(|line| !line.is_empty())
+    .collect())
+}
+
+impl PasswordHasher {
+    pub fn new(config: Config, breached: HashSet<String>) -> Result<Self, Error> {
+        let params = Params::new(config.memory_cost, config.time_cost, config.parallelism, None)?;
+        Ok(Self {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+            config,
+            breached
+        })
+    }
+
+    pub fn check_policy(&self, password: &str) -> Result<(), PolicyViolation> {
+        let length = password.chars().count();
+        if length < self.config.min_length {
+            return Err(PolicyViolation::TooShort);
+        }
+        if length > self.config.max_length {
+            return Err(PolicyViolation::TooLong);
+        }
+        if self.breached.contains(&password.to_lowercase()) {
+            return Err(PolicyViolation::Breached);
+        }
+        Ok(())
+    }
+
+    /// runs on blocking thread pool, hashing takes long enough to stall other requests
+    pub async fn hash(&self, password: &str) -> Result<String, Error> {
+        let argon2 = self.argon2.clone();
+        let password = password.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut rand::rngs::OsRng);
+            Ok(argon2.hash_password(password.as_bytes(), &salt)?.to_string())
+        }).await?
+    }
+
+    /// accepts argon2 and legacy bcrypt hashes, anything unparsable (e.g. users without password) is invalid,
+    /// runs on blocking thread pool like `hash`
+    pub async fn verify(&self, password: &str, hash: &str) -> Verification {
+        let argon2 = self.argon2.clone();
+        let config = self.config.clone();
+        let (password, hash) = (password.to_owned(), hash.to_owned());
+        tokio::task::spawn_blocking(move || verify(&argon2, &config, &password, &hash)).await
+        .unwrap_or(Verification::Invalid)
+    }
+}
+
+fn verify(hasher: &Argon2, config: &Config, password: &str, hash: &str) -> Verification {
+    if hash.starts_with("$2") {
+        return match bcrypt::verify(password, hash) {
+            true => Verification::Outdated,
+            false => Verification::Invalid
+        };
+    }
+    let parsed = match PasswordHash::new(hash) {
+        Ok(parsed) => parsed,
+        Err(_) => return Verification::Invalid
+    };
+    if hasher.verify_password(password.as_bytes(), &parsed).is_err() {
+        return Verification::Invalid;
+    }
+    let current = match Params::try_from(&parsed) {
+        Ok(params) => parsed.algorithm == argon2::ARGON2ID_IDENT
+            && params.m_cost() == config.memory_cost
+            && params.t_cost() == config.time_cost
+            && params.p_cost() == config.parallelism,
+        Err(_) => false
+    };
+    match current {
+        true => Verification::Valid,
+        false => Verification::Outdated
+    }
+}
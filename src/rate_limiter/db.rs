@@ -0,0 +1,23 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use crate::db_pool::DbPool;
+use super::{Store, Policy, Bucket, Error};
+
+/// buckets stored in db, so that all instances share the limits
+pub struct DbStore {
+    db_pool: Arc<DbPool>,
+}
+
+impl DbStore {
+    pub fn new(db_pool: Arc<DbPool>) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[async_trait]
+impl Store for DbStore {
+    async fn take(&self, key: &str, policy: &Policy) -> Result<Bucket, Error> {
+        let (allowed, tokens) = self.db_pool.take_rate_limit_token(key, policy.capacity, policy.rate()).await?;
+        Ok(Bucket { allowed, tokens })
+    }
+}
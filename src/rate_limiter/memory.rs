@@ -0,0 +1,92 @@
+use std::{collections::HashMap, sync::Mutex, time::{Instant, Duration}};
+use async_trait::async_trait;
+use super::{Store, Policy, Bucket, Error};
+
+/// how often idle buckets get dropped, so a sweep is paid for once per interval and not on every request
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// buckets are full again long before this for any sane policy, so dropping them changes nothing
+const IDLE_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct State {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    states: HashMap<String, State>,
+    swept: Instant,
+}
+
+/// per-instance buckets, limits are not shared between instances
+pub struct MemoryStore {
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            buckets: Mutex::new(Buckets { states: HashMap::new(), swept: Instant::now() })
+        }
+    }
+}
+
+fn refill(state: &mut State, policy: &Policy, now: Instant) {
+    let elapsed = now.duration_since(state.updated).as_secs_f64();
+    state.tokens = (state.tokens + elapsed * policy.rate()).min(policy.capacity as f64);
+    state.updated = now;
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn take(&self, key: &str, policy: &Policy) -> Result<Bucket, Error> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.duration_since(buckets.swept) >= SWEEP_INTERVAL {
+            buckets.states.retain(|_, state| now.duration_since(state.updated) < IDLE_TTL);
+            buckets.swept = now;
+        }
+        let state = buckets.states.entry(key.to_string()).or_insert(State { tokens: policy.capacity as f64, updated: now });
+        refill(state, policy, now);
+        let allowed = state.tokens >= 1.0;
+        if allowed {
+            state.tokens -= 1.0;
+        }
+        Ok(Bucket { allowed, tokens: state.tokens })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: Policy = Policy { capacity: 10, period: 20 };
+
+    #[test]
+    fn refill_adds_tokens_at_policy_rate() {
+        let start = Instant::now();
+        let mut state = State { tokens: 2.0, updated: start };
+        refill(&mut state, &POLICY, start + Duration::from_secs(4));
+        assert_eq!(state.tokens, 4.0);
+        assert_eq!(state.updated, start + Duration::from_secs(4));
+    }
+
+    #[test]
+    fn refill_stops_at_capacity() {
+        let start = Instant::now();
+        let mut state = State { tokens: 9.0, updated: start };
+        refill(&mut state, &POLICY, start + Duration::from_secs(60));
+        assert_eq!(state.tokens, 10.0);
+    }
+
+    #[tokio::test]
+    async fn take_empties_bucket_then_denies() {
+        let store = MemoryStore::default();
+        for remaining in (0..10).rev() {
+            let bucket = store.take("ip:1", &POLICY).await.unwrap();
+            assert!(bucket.allowed);
+            assert_eq!(bucket.tokens.floor(), remaining as f64);
+        }
+        assert!(!store.take("ip:1", &POLICY).await.unwrap().allowed);
+        assert!(store.take("ip:2", &POLICY).await.unwrap().allowed);
+    }
+}
@@ -0,0 +1,170 @@
+use std::{collections::HashMap, sync::Arc};
+use async_trait::async_trait;
+use crate::{db_pool, logger::Logger};
+pub use memory::MemoryStore;
+pub use db::DbStore;
+
+mod memory;
+mod db;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("db: {0}")]
+    Db(db_pool::Error)
+}
+impl From<db_pool::Error> for Error {
+    fn from(value: db_pool::Error) -> Self {
+        Self::Db(value)
+    }
+}
+
+/// token bucket that holds `capacity` tokens and refills completely in `period` seconds
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    pub capacity: u32,
+    pub period: u32,
+}
+
+impl Policy {
+    /// `capacity/period`, e.g. `30/60`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (capacity, period) = value.trim().split_once('/')?;
+        let policy = Self { capacity: capacity.trim().parse().ok()?, period: period.trim().parse().ok()? };
+        if policy.capacity == 0 || policy.period == 0 {
+            return None;
+        }
+        Some(policy)
+    }
+
+    /// tokens per second
+    pub fn rate(&self) -> f64 {
+        self.capacity as f64 / self.period as f64
+    }
+}
+
+/// `path=capacity/period` pairs separated by commas
+pub fn parse_route_policies(value: &str) -> HashMap<String, Policy> {
+    value.split(',')
+    .filter_map(|rule| {
+        let (path, policy) = rule.split_once('=')?;
+        Some((path.trim().to_string(), Policy::parse(policy)?))
+    })
+    .collect()
+}
+
+/// state of the bucket after taking a token (or failing to)
+pub struct Bucket {
+    pub allowed: bool,
+    pub tokens: f64,
+}
+
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// seconds until bucket is full again
+    pub reset: u64,
+    /// seconds until next token, 0 when allowed
+    pub retry_after: u64,
+}
+
+impl Decision {
+    fn new(policy: &Policy, bucket: &Bucket) -> Self {
+        let rate = policy.rate();
+        Self {
+            allowed: bucket.allowed,
+            limit: policy.capacity,
+            remaining: bucket.tokens.floor().max(0.0) as u32,
+            reset: ((policy.capacity as f64 - bucket.tokens) / rate).ceil().max(0.0) as u64,
+            retry_after: if bucket.allowed { 0 } else { ((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64 }
+        }
+    }
+}
+
+#[async_trait]
+pub trait Store {
+    /// refills the bucket under `key` and takes one token from it if possible
+    async fn take(&self, key: &str, policy: &Policy) -> Result<Bucket, Error>;
+}
+
+pub type StoreShared = Arc<dyn Store + Send + Sync>;
+
+pub struct RateLimiter {
+    store: StoreShared,
+    default: Policy,
+    routes: HashMap<String, Policy>,
+    logger: Arc<Logger>,
+}
+
+impl RateLimiter {
+    pub fn new(store: StoreShared, default: Policy, routes: HashMap<String, Policy>, logger: Arc<Logger>) -> Self {
+        Self { store, default, routes, logger }
+    }
+
+    /// routes with own policy get own bucket, all other routes share one, `None` if store failed (request is let through)
+    pub async fn check(&self, path: &str, identity: &str) -> Option<Decision> {
+        let (key, policy) = match self.routes.get(path) {
+            Some(policy) => (format!("{identity} {path}"), policy),
+            None => (format!("{identity} *"), &self.default)
+        };
+        match self.store.take(&key, policy).await {
+            Ok(bucket) => Some(Decision::new(policy, &bucket)),
+            Err(error) => {
+                self.logger.log(error.to_string());
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_parses_capacity_over_period() {
+        let policy = Policy::parse(" 30 / 60 ").unwrap();
+        assert_eq!((policy.capacity, policy.period), (30, 60));
+        assert_eq!(policy.rate(), 0.5);
+    }
+
+    #[test]
+    fn policy_rejects_zero_and_garbage() {
+        for value in ["0/60", "30/0", "30", "30/", "/60", "a/60", "30/b", "-1/60", ""] {
+            assert!(Policy::parse(value).is_none(), "{value}");
+        }
+    }
+
+    #[test]
+    fn route_policies_skip_invalid_rules() {
+        let routes = parse_route_policies("/api/auth/login=10/60, /api/blocks/create = 30/60,/broken=0/60,nonsense,");
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes["/api/auth/login"].capacity, 10);
+        assert_eq!(routes["/api/blocks/create"].period, 60);
+    }
+
+    #[test]
+    fn decision_of_allowed_request() {
+        let policy = Policy { capacity: 10, period: 20 };
+        let decision = Decision::new(&policy, &Bucket { allowed: true, tokens: 7.5 });
+        assert!(decision.allowed);
+        assert_eq!(decision.limit, 10);
+        assert_eq!(decision.remaining, 7);
+        assert_eq!(decision.reset, 5);
+        assert_eq!(decision.retry_after, 0);
+    }
+
+    #[test]
+    fn decision_of_denied_request() {
+        let policy = Policy { capacity: 10, period: 20 };
+        let decision = Decision::new(&policy, &Bucket { allowed: false, tokens: 0.25 });
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.reset, 20);
+        assert_eq!(decision.retry_after, 2);
+
+        // never tells client to retry right away
+        let decision = Decision::new(&policy, &Bucket { allowed: false, tokens: 0.99 });
+        assert_eq!(decision.retry_after, 1);
+    }
+}
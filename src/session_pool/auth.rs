@@ -1,11 +1,13 @@
 use crate::{db_pool, auth_validator::{self, Tokens, AuthInfo, InfoAsTokensError}};
-use crate::activity_logger::Activity;
-use super::{Session, Error as GeneralError};
-use pwhash::bcrypt;
+use crate::{activity_logger::Activity, live_channel::CloseReason, password_hasher::{self, PolicyViolation, Verification}};
+use super::{Session, Error as GeneralError, two_factor::TwoFactorError};
+use db_pool::KeyClient;
 use serde::Serialize;
 use ts_rs::TS;
 
-const NAME_CHARS: &str = "QAZWSXEDCRFVTGBYHNUJMIKOLPqazwsxedcrfvtgbyhnujmikolp1234567890_";
+pub(super) const NAME_CHARS: &str = "QAZWSXEDCRFVTGBYHNUJMIKOLPqazwsxedcrfvtgbyhnujmikolp1234567890_";
+pub(super) const MIN_NAME_LENGTH: usize = 3;
+pub(super) const MAX_NAME_LENGTH: usize = 20;
 
 #[derive(thiserror::Error, Debug)]
 pub enum RegisterError {
@@ -17,6 +19,8 @@ pub enum RegisterError {
     TooShortPassword,
     #[error("too long password")]
     TooLongPassword,
+    #[error("password is in breached passwords list")]
+    BreachedPassword,
     #[error("general error: {0}")]
     General(GeneralError),
     #[error("username already taken")]
@@ -26,7 +30,7 @@ pub enum RegisterError {
     #[error("failed to convert info to tokens: {0}")]
     InfoAsTokens(InfoAsTokensError),
     #[error("failed to hash password: {0}")]
-    Hashing(pwhash::error::Error)
+    Hashing(password_hasher::Error)
 }
 impl From<db_pool::Error> for RegisterError {
     fn from(value: db_pool::Error) -> Self {
@@ -38,11 +42,20 @@ impl From<InfoAsTokensError> for RegisterError {
         Self::InfoAsTokens(value)
     }
 }
-impl From<pwhash::error::Error> for RegisterError {
-    fn from(value: pwhash::error::Error) -> Self {
+impl From<password_hasher::Error> for RegisterError {
+    fn from(value: password_hasher::Error) -> Self {
         Self::Hashing(value)
     }
 }
+impl From<PolicyViolation> for RegisterError {
+    fn from(value: PolicyViolation) -> Self {
+        match value {
+            PolicyViolation::TooShort => Self::TooShortPassword,
+            PolicyViolation::TooLong => Self::TooLongPassword,
+            PolicyViolation::Breached => Self::BreachedPassword
+        }
+    }
+}
 
 #[derive(thiserror::Error, Debug)]
 pub enum LoginError {
@@ -51,7 +64,15 @@ pub enum LoginError {
     #[error("general error: {0}")]
     General(GeneralError),
     #[error("invalid credentials")]
-    InvalidCredentials
+    InvalidCredentials,
+    #[error("login challenge is invalid or expired")]
+    InvalidChallenge,
+    #[error("invalid second factor code")]
+    InvalidCode,
+    #[error("failed to check second factor: {0}")]
+    SecondFactor(TwoFactorError),
+    #[error("too many failed attempts, retry after {retry_after}s")]
+    Locked { retry_after: i64 }
 }
 impl From<db_pool::Error> for LoginError {
     fn from(value: db_pool::Error) -> Self {
@@ -64,6 +85,35 @@ impl From<InfoAsTokensError> for LoginError {
     }
 }
 
+/// login either finishes right away or, if user has 2fa enabled, needs a second step with the challenge
+pub enum LoginOutcome {
+    Tokens(Tokens),
+    Challenge(String)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RefreshError {
+    #[error("failed to convert info to tokens: {0}")]
+    InfoAsTokens(InfoAsTokensError),
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("invalid key token")]
+    InvalidToken,
+    #[error("key was revoked")]
+    Revoked,
+    #[error("key token was already used, key got revoked")]
+    Reused
+}
+impl From<db_pool::Error> for RefreshError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+impl From<InfoAsTokensError> for RefreshError {
+    fn from(value: InfoAsTokensError) -> Self {
+        Self::InfoAsTokens(value)
+    }
+}
 
 #[derive(Serialize, TS)]
 #[ts(export)]
@@ -84,39 +134,203 @@ impl From<auth_validator::Auth> for AuthMe {
 }
 
 
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct AuthSession {
+    pub id: String,
+    pub device: String,
+    pub ip: String,
+    pub created: i64,
+    pub last_seen: i64,
+    pub current: bool,
+}
+impl AuthSession {
+    fn new(key: db_pool::Key, current_key: &str) -> Self {
+        Self {
+            id: key.id.unwrap().to_string(),
+            current: key.key == current_key,
+            device: key.device,
+            ip: key.ip,
+            created: key.created,
+            last_seen: key.last_seen
+        }
+    }
+}
+
 impl Session {
     pub async fn me(&self) -> AuthMe {
         self.auth.clone().into()
     }
 
-    pub async fn login(&self, name: &str, password: &str) -> Result<Tokens, LoginError> {
-        let user = self.db_pool.get_user(name).await?;
-        if !bcrypt::verify(password, user.password_hash.as_str()) {
-            return Err(LoginError::InvalidCredentials);
-        }
-
+    /// creates key record for a new login and issues tokens for it
+    pub(super) async fn issue_tokens<E: From<InfoAsTokensError> + From<db_pool::Error>>(&self, name: &str) -> Result<Tokens, E> {
+        let key = auth_validator::generate_secret();
+        let refresh = auth_validator::generate_secret();
         let tokens = self.auth_validator.info_as_tokens(&AuthInfo {
-            name: name.to_string()
-        })?;
+            name: name.to_string(),
+            key: key.clone()
+        }, &refresh)?;
 
+        self.db_pool.create_key(&key, name, &refresh, self.key_expiration(), &self.key_client()).await?;
         Ok(tokens)
     }
 
+    fn key_expiration(&self) -> i64 {
+        (chrono::Utc::now() + self.auth_validator.key_lifetime()).timestamp()
+    }
+
+    fn key_client(&self) -> KeyClient<'_> {
+        KeyClient {
+            device: &self.client.device,
+            ip: &self.client.ip
+        }
+    }
+
+    /// revokes key and kicks all live peers that were connected with it
+    pub(super) async fn revoke_key(&self, key: &str) -> Result<(), db_pool::Error> {
+        self.db_pool.revoke_key(key).await?;
+        self.live_channel.disconnect_key(key, CloseReason::Revoked);
+        Ok(())
+    }
+
+    /// revokes every key of the user except `except`
+    pub(super) async fn revoke_user_keys(&self, name: &str, except: Option<&str>) -> Result<(), db_pool::Error> {
+        let (keys, errors) = self.db_pool.get_user_keys(name).await?;
+        for error in errors {
+            self.logger.log(error.to_string());
+        }
+        for key in keys {
+            if Some(key.key.as_str()) != except {
+                self.revoke_key(&key.key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn refresh(&self, key_token: &str) -> Result<Tokens, RefreshError> {
+        let claims = self.auth_validator.decode_key_token(key_token).map_err(|_| RefreshError::InvalidToken)?;
+        let key = match self.db_pool.get_key(&claims.key).await {
+            Ok(key) => key,
+            Err(db_pool::Error::NotFound) => return Err(RefreshError::InvalidToken),
+            Err(error) => return Err(error.into())
+        };
+        if key.revoked {
+            return Err(RefreshError::Revoked);
+        }
+
+        let refresh = auth_validator::generate_secret();
+        match self.db_pool.rotate_key(&key.key, &claims.refresh, &refresh, self.key_expiration(), &self.key_client()).await {
+            Ok(()) => {},
+            Err(db_pool::Error::NotFound) => { // someone already used this key token, whole login is considered stolen
+                self.revoke_key(&key.key).await?;
+                return Err(RefreshError::Reused);
+            },
+            Err(error) => return Err(error.into())
+        }
+
+        Ok(self.auth_validator.info_as_tokens(&AuthInfo {
+            name: key.owner,
+            key: key.key
+        }, &refresh)?)
+    }
+
+    /// revokes key of the login key token belongs to, works even if access token has already expired,
+    /// best effort so client can always drop its tokens
+    pub async fn logout(&self, key_token: &str) {
+        let Ok(claims) = self.auth_validator.decode_key_token(key_token) else {
+            return;
+        };
+        if let Err(error) = self.revoke_key(&claims.key).await {
+            self.logger.log(error.to_string());
+        }
+    }
+
+    pub async fn get_sessions(&self) -> Result<Vec<AuthSession>, GeneralError> {
+        let auth = self.auth()?;
+        let (keys, errors) = self.db_pool.get_user_keys(&auth.name).await?;
+        for error in errors {
+            self.logger.log(error.to_string());
+        }
+        Ok(keys.into_iter().map(|key| AuthSession::new(key, &auth.key)).collect())
+    }
+
+    pub async fn revoke_session(&self, id: &str) -> Result<(), GeneralError> {
+        let auth = self.auth()?;
+        let key = self.db_pool.get_user_key_by_id(&auth.name, id).await?;
+        self.revoke_key(&key.key).await?;
+        Ok(())
+    }
+
+    /// revokes every session of the user except the current one
+    pub async fn revoke_other_sessions(&self) -> Result<(), GeneralError> {
+        let auth = self.auth()?;
+        self.revoke_user_keys(&auth.name, Some(&auth.key)).await?;
+        Ok(())
+    }
+
+    /// failure is only logged, old hash keeps working
+    async fn rehash_password(&self, name: &str, password: &str) {
+        let result = match self.password_hasher.hash(password).await {
+            Ok(password_hash) => self.db_pool.change_user_password(name, &password_hash).await.map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string())
+        };
+        if let Err(error) = result {
+            self.logger.log(error);
+        }
+    }
+
+    pub async fn login(&self, name: &str, password: &str) -> Result<LoginOutcome, LoginError> {
+        self.begin_login_attempt(name).await?;
+        let user = match self.db_pool.get_user(name).await {
+            Ok(user) => user,
+            Err(db_pool::Error::NotFound) => return Err(LoginError::InvalidCredentials),
+            Err(error) => return Err(error.into())
+        };
+        match self.password_hasher.verify(password, &user.password_hash).await {
+            Verification::Invalid => return Err(LoginError::InvalidCredentials),
+            Verification::Valid => {},
+            Verification::Outdated => self.rehash_password(name, password).await // password is known only now
+        }
+
+        if user.totp.as_ref().is_some_and(|totp| totp.enabled) { // failures are cleared only after second factor too
+            return Ok(LoginOutcome::Challenge(self.auth_validator.challenge_token(name)?));
+        }
+        self.clear_login_failures(name).await?;
+        Ok(LoginOutcome::Tokens(self.issue_tokens::<LoginError>(name).await?))
+    }
+
+    /// second step of the login, `code` is either a totp code or one of the recovery codes
+    pub async fn login_second_factor(&self, challenge: &str, code: &str) -> Result<Tokens, LoginError> {
+        let claims = self.auth_validator.decode_challenge_token(challenge).map_err(|_| LoginError::InvalidChallenge)?;
+        self.begin_login_attempt(&claims.name).await?;
+        match self.db_pool.use_challenge(&claims.id, claims.exp as i64).await {
+            Ok(()) => {},
+            Err(db_pool::Error::NotFound) => return Err(LoginError::InvalidChallenge),
+            Err(error) => return Err(error.into())
+        }
+        let user = self.db_pool.get_user(&claims.name).await?;
+        match self.check_second_factor(&user, code).await {
+            Ok(true) => {
+                self.clear_login_failures(&user.name).await?;
+                self.issue_tokens(&user.name).await
+            },
+            Ok(false) => Err(LoginError::InvalidCode),
+            Err(TwoFactorError::NotEnrolled) => Err(LoginError::InvalidChallenge), // 2fa was disabled meanwhile
+            Err(TwoFactorError::General(error)) => Err(LoginError::General(error)),
+            Err(error) => Err(LoginError::SecondFactor(error))
+        }
+    }
+
     pub async fn register(&self, name: &str, email: &str, password: &str) -> Result<Tokens, RegisterError> {
         for char in name.chars(){
             if !NAME_CHARS.contains(char) {
                 return Err(RegisterError::InvaildNameChars);
             }
         }
-        if name.len() < 3 || name.len() > 20 {
+        if name.len() < MIN_NAME_LENGTH || name.len() > MAX_NAME_LENGTH {
             return Err(RegisterError::BadNameLength);
         }
-        if password.len() < 7 {
-            return Err(RegisterError::TooShortPassword);
-        }
-        if password.len() > 50 {
-            return Err(RegisterError::TooLongPassword);
-        }
+        self.password_hasher.check_policy(password)?;
 
         let uniqueness = self.db_pool.check_if_unique_credentials(name, email).await?;
         if !uniqueness.email {
@@ -126,15 +340,19 @@ impl Session {
             return Err(RegisterError::NameTaken);
         }
 
-        let password_hash = bcrypt::hash(password)?;
-        let tokens = self.auth_validator.info_as_tokens(&AuthInfo {
-            name: name.to_string(),
-        })?;
+        let password_hash = self.password_hasher.hash(password).await?;
 
         let activity_table_id = self.db_pool.create_activity_table().await?;
-        self.db_pool.create_user(name, email, password_hash.as_str(), &activity_table_id).await?;
+        match self.db_pool.create_user(name, email, password_hash.as_str(), &activity_table_id).await {
+            Ok(()) => {},
+            Err(db_pool::Error::Duplicate) => return Err(RegisterError::NameTaken), // registered meanwhile
+            Err(error) => return Err(error.into())
+        }
         self.activity_logger.log(Activity::Joined { by: name.to_string() });
+        if let Err(error) = self.send_verification(name, email).await { // user can ask to resend it later
+            self.logger.log(error.to_string());
+        }
 
-        Ok(tokens)
+        self.issue_tokens(name).await
     }
 }
\ No newline at end of file
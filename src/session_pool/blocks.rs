@@ -1,6 +1,6 @@
 use serde::{Serialize, Deserialize};
 use ts_rs::TS;
-use crate::{db_pool, live_channel::LiveMessage, activity_logger::Activity};
+use crate::{db_pool::{self, TokenScope}, live_channel::LiveMessage, activity_logger::Activity};
 
 use super::{Session, Error as GeneralError};
 
@@ -24,7 +24,7 @@ impl From<db_pool::Block> for Block {
 
 impl Session {
     pub async fn create_block(&self, content: &str) -> Result<String, GeneralError> {
-        let auth = self.auth()?;
+        let auth = self.scoped_auth(TokenScope::WriteBlocks)?;
         let id = self.db_pool.create_block(content, auth.name.as_str(), &Vec::new()).await?;
 
         self.activity_logger.log(Activity::BlockCreated { id: id.clone(), by: auth.name.clone() });
@@ -36,7 +36,7 @@ impl Session {
     }
 
     pub async fn change_block(&self, id: &str, content: &str) -> Result<(), GeneralError> {
-        let auth = self.auth()?;
+        let auth = self.scoped_auth(TokenScope::WriteBlocks)?;
         let block = self.db_pool.get_block(id).await?;
         if block.owner != auth.name {
             return Err(GeneralError::Unauthorized);
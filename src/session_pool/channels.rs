@@ -1,6 +1,6 @@
 use serde::{Serialize, Deserialize};
 use ts_rs::TS;
-use crate::{db_pool::{self, ChannelType}, session_pool::{roles::RolePermissionValidator, Block}, live_channel::LiveMessage, activity_logger::Activity};
+use crate::{db_pool::{self, ChannelType, TokenScope}, session_pool::{roles::RolePermissionValidator, Block}, live_channel::LiveMessage, activity_logger::Activity};
 use super::{Session, Error as GeneralError, roles::{resolve_user_role, RoleWrappedError}};
 
 #[derive(Serialize, Deserialize, TS)]
@@ -11,7 +11,9 @@ pub struct Channel {
     pub _type: ChannelType,
     pub roles: Vec<(String, String)>,
     pub default_role: String,
-    pub labels: Vec<String>
+    pub labels: Vec<String>,
+    pub require_verified: bool,
+    pub hide_presence: bool
 }
 
 impl From<db_pool::Channel> for Channel {
@@ -21,17 +23,21 @@ impl From<db_pool::Channel> for Channel {
             _type: model._type,
             roles: model.roles,
             default_role: model.default_role,
-            labels: model.labels
+            labels: model.labels,
+            require_verified: model.require_verified,
+            hide_presence: model.hide_presence
         }
     }
 }
 
 impl Session {
-    pub async fn create_channel(&self, _type: &ChannelType, title: &str, description: &str, default_role: &str, labels: &[String]) -> Result<String, GeneralError> {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_channel(&self, _type: &ChannelType, title: &str, description: &str, default_role: &str, labels: &[String], require_verified: bool, hide_presence: bool) -> Result<String, GeneralError> {
         let auth = self.auth()?;
+        let hide_presence = hide_presence && matches!(_type, ChannelType::Ghosted);
 
         let activity_table_id = self.db_pool.create_activity_table().await?;
-        let id = self.db_pool.create_channel(_type, title, description, &Vec::new(), default_role, labels, &activity_table_id).await?;
+        let id = self.db_pool.create_channel(_type, title, description, &Vec::new(), default_role, labels, &activity_table_id, require_verified, hide_presence).await?;
         Ok(id)
     }
 
@@ -40,16 +46,7 @@ impl Session {
     }
 
     pub async fn connect_block_to_channel(&self, id: &str, block_id: &str) -> Result<(), RoleWrappedError> {
-        // TO BE REMOVED ---------------------------------------
-        let auth = self.auth()?;
-        self.db_pool.connect_block_to_channel(block_id, id).await?;
-        self.live_channel.receive_message(id, &LiveMessage::BlockConnected { id: block_id.to_string() });
-        self.activity_logger.log(Activity::BlockConnectedToChannel { block_id: block_id.to_string(), id: id.to_string(), by: auth.name.clone() });
-        return Ok(());
-        // TO BE REMOVED ---------------------------------------
-
-        let auth = self.auth()?;
-        
+        let auth = self.scoped_auth(TokenScope::WriteBlocks)?;
         let (role, channel) = resolve_user_role(self.db_pool.clone(), id, &auth.name).await?;
         let validator = RolePermissionValidator::new(&role.permissions, &channel.labels);
 
@@ -65,7 +62,7 @@ impl Session {
     }
 
     pub async fn disconnect_block_from_channel(&self, id: &str, block_id: &str) -> Result<(), RoleWrappedError> {
-        let auth = self.auth()?;
+        let auth = self.scoped_auth(TokenScope::WriteBlocks)?;
         
         let (role, channel) = resolve_user_role(self.db_pool.clone(), id, &auth.name).await?;
         let validator = RolePermissionValidator::new(&role.permissions, &channel.labels);
@@ -82,7 +79,7 @@ impl Session {
     }
 
     pub async fn pin_channel_block(&self, id: &str, block_id: &Option<String>) -> Result<(), RoleWrappedError> {
-        let auth = self.auth()?;
+        let auth = self.scoped_auth(TokenScope::WriteBlocks)?;
         let (role, channel) = resolve_user_role(self.db_pool.clone(), id, &auth.name).await?;
         let validator = RolePermissionValidator::new(&role.permissions, &channel.labels);
 
@@ -127,14 +124,7 @@ impl Session {
     }
 
     pub async fn get_channel_blocks(&self, id: &str, limit: &Option<i64>, offset: &Option<u64>) -> Result<(Vec<Block>, Vec<mongodb::error::Error>), RoleWrappedError> {
-        let (db_blocks, blocks_errors) = self.db_pool.get_channel_blocks(id, limit, offset).await?;
-        let mut blocks = Vec::new();
-        for block in db_blocks {
-            blocks.push(Block::from(block));
-        }
-        return Ok((blocks, blocks_errors)); // WARNING ABOVE CODE TO BE REMOVED
-        
-        let auth = self.auth()?;
+        let auth = self.scoped_auth(TokenScope::ReadBlocks)?;
         let (role, channel) = resolve_user_role(self.db_pool.clone(), id, &auth.name).await?;
         let validator = RolePermissionValidator::new(&role.permissions, &channel.labels);
 
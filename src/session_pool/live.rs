@@ -1,55 +1,165 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use crate::{live_channel::{self, LiveChannel}, logger::Logger};
+use async_trait::async_trait;
+use crate::{live_channel::{self, LiveChannel}, db_pool::{self, DbPool, TokenScope}};
 use super::{Session, roles::{resolve_user_role, RolePermissionValidator}, Error as GeneralError, RoleWrappedError};
 
-pub struct Handle {
+#[derive(thiserror::Error, Debug)]
+pub enum SignalError {
+    #[error("general: {0}")]
+    General(GeneralError),
+    #[error("peer is not subscribed to the channel")]
+    NotSubscribed,
+    #[error("signal is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("too many signals")]
+    RateLimited,
+}
+impl From<GeneralError> for SignalError {
+    fn from(value: GeneralError) -> Self {
+        Self::General(value)
+    }
+}
+impl From<live_channel::SignalError> for SignalError {
+    fn from(value: live_channel::SignalError) -> Self {
+        match value {
+            live_channel::SignalError::NotSubscribed => Self::NotSubscribed,
+            live_channel::SignalError::Forbidden => Self::General(GeneralError::Unauthorized),
+            live_channel::SignalError::TooLarge(max) => Self::TooLarge(max),
+            live_channel::SignalError::RateLimited => Self::RateLimited
+        }
+    }
+}
+
+/// channels one peer is subscribed to
+pub struct Subscriptions {
     live_channel: Arc<LiveChannel>,
-    channel_id: String,
-    handle: Mutex<Option<live_channel::Handle>>,
-    logger: Arc<Logger>
+    handle: live_channel::PeerHandle,
+    /// user that opened the peer, only they can change its subscriptions
+    name: Option<String>,
 }
 
-impl Handle {
-    pub async fn connect(&self, peer: Arc<dyn live_channel::Peer + Send + Sync>){
-        let handle = self.live_channel.connect(peer, &self.channel_id).await;
-        *self.handle.lock().await = Some(handle);
+impl Subscriptions {
+    /// subscribing again with `cursor` replays events after it once more
+    async fn add(&self, channel_id: &str, cursor: Option<&live_channel::Cursor>, hide_presence: bool, grants: live_channel::Grants) -> live_channel::Subscribed {
+        self.live_channel.subscribe(self.handle, channel_id, cursor, hide_presence, grants)
+    }
+
+    /// false if peer wasn't subscribed to the channel
+    pub async fn unsubscribe(&self, channel_id: &str) -> bool {
+        self.live_channel.unsubscribe(self.handle, channel_id)
+    }
+
+    /// epoch of every `seq` the peer gets
+    pub fn epoch(&self) -> String {
+        self.live_channel.epoch()
     }
 
-    pub async fn disconnect(self){
-        if let Err(error) = match &*self.handle.lock().await {
-            Some(handle) => self.live_channel.disconnect(handle.clone()).await,
-            None => panic!("[live channel session failed to find handle, called disconnected without connecting]")
-        } { self.logger.log(error.to_string()) }
+    pub async fn channel_ids(&self) -> Vec<String> {
+        self.live_channel.channel_ids(self.handle)
+    }
+
+    /// unsubscribes from everything and unregisters the peer
+    pub async fn close(&self) {
+        self.live_channel.unregister(self.handle);
     }
 }
 
 impl Session {
-    pub async fn live(&self, channel_id: &str) -> Result<Handle, RoleWrappedError> {
-        // TO BE REMOVED -------------------
-        let handle = Handle {
-            live_channel: self.live_channel.clone(),
-            logger: self.logger.clone(),
-            channel_id: channel_id.to_owned(),
-            handle: Mutex::new(None)
+    /// subscriptions start empty, channels are added with `subscribe`
+    pub fn live(&self, peer: live_channel::PeerShared) -> Subscriptions {
+        let (key, name) = match self.scoped_auth(TokenScope::Live) {
+            Ok(auth) => (Some(auth.key.clone()), Some(auth.name.clone())),
+            Err(_) => (None, None)
         };
-        return Ok(handle);
-        // TO BE REMOVED -------------------
+        Subscriptions {
+            handle: self.live_channel.register(peer, key, name.clone()),
+            name,
+            live_channel: self.live_channel.clone()
+        }
+    }
+
+    /// checks `live` permission on the channel before subscribing to it,
+    /// `cursor` is the last event client got before reconnecting
+    pub async fn subscribe(&self, subscriptions: &Subscriptions, channel_id: &str, cursor: Option<&live_channel::Cursor>) -> Result<live_channel::Subscribed, RoleWrappedError> {
+        self.check_owner(subscriptions)?;
+        let (role, channel) = self.check_live(channel_id).await?;
+        let grants = grants(&RolePermissionValidator::new(&role.permissions, &channel.labels));
+        Ok(subscriptions.add(channel_id, cursor, channel.hide_presence, grants).await)
+    }
+
+    /// sends ephemeral signal to a subscribed channel, needs `signal` permission
+    pub async fn signal(&self, subscriptions: &Subscriptions, channel_id: &str, data: String) -> Result<(), SignalError> {
+        Ok(self.live_channel.signal(subscriptions.handle, channel_id, data).await?)
+    }
+
+    /// for peers that are driven by separate requests (e.g. event streams), false if peer wasn't subscribed
+    pub async fn unsubscribe(&self, subscriptions: &Subscriptions, channel_id: &str) -> Result<bool, GeneralError> {
+        self.check_owner(subscriptions)?;
+        Ok(subscriptions.unsubscribe(channel_id).await)
+    }
 
-        let auth = self.auth()?;
+    fn check_owner(&self, subscriptions: &Subscriptions) -> Result<(), GeneralError> {
+        let auth = self.scoped_auth(TokenScope::Live)?;
+        if subscriptions.name.as_deref() != Some(auth.name.as_str()) {
+            return Err(GeneralError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /// users watching the channel live, several peers of one user are listed once
+    pub async fn get_channel_presence(&self, channel_id: &str) -> Result<Vec<String>, RoleWrappedError> {
+        let (_, channel) = self.check_live(channel_id).await?;
+        if channel.hide_presence {
+            return Err(GeneralError::Forbidden.into());
+        }
+        Ok(self.live_channel.presence(channel_id))
+    }
+
+    async fn check_live(&self, channel_id: &str) -> Result<(db_pool::Role, db_pool::Channel), RoleWrappedError> {
+        let auth = self.scoped_auth(TokenScope::Live)?;
         let (role, channel) = resolve_user_role(self.db_pool.clone(), channel_id, &auth.name).await?;
         let validator = RolePermissionValidator::new(&role.permissions, &channel.labels);
 
         if !validator.can_live() {
             return Err(GeneralError::Unauthorized.into());
         }
-        
-        let handle = Handle {
-            live_channel: self.live_channel.clone(),
-            logger: self.logger.clone(),
-            channel_id: channel_id.to_owned(),
-            handle: Mutex::new(None)
-        };
-        Ok(handle)
+        Ok((role, channel))
+    }
+}
+
+fn grants(validator: &RolePermissionValidator) -> live_channel::Grants {
+    live_channel::Grants {
+        view_blocks: validator.can_view_blocks(),
+        signal: validator.can_signal()
     }
-}
\ No newline at end of file
+}
+
+/// resolves user's role in the channel the same way `subscribe` does
+pub struct LiveAccess {
+    db_pool: Arc<DbPool>,
+}
+
+impl LiveAccess {
+    pub fn new(db_pool: Arc<DbPool>) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[async_trait]
+impl live_channel::AccessChecker for LiveAccess {
+    async fn check(&self, channel_id: &str, name: &str) -> Option<live_channel::Access> {
+        match resolve_user_role(self.db_pool.clone(), channel_id, name).await {
+            Ok((role, channel)) => {
+                let validator = RolePermissionValidator::new(&role.permissions, &channel.labels);
+                if validator.can_live() {
+                    Some(live_channel::Access::Live(grants(&validator)))
+                } else {
+                    Some(live_channel::Access::Denied)
+                }
+            },
+            // channel or role is gone, or channel started requiring verified users
+            Err(RoleWrappedError::General(GeneralError::Db(db_pool::Error::NotFound) | GeneralError::Unverified)) => Some(live_channel::Access::Denied),
+            Err(_) => None
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{db_pool, auth_validator, activity_logger::Activity};
+use super::{Session, Error as GeneralError, auth::LoginError};
+
+#[derive(Clone, Debug)]
+pub struct LockoutConfig {
+    /// failures allowed before account gets locked
+    pub account_attempts: i64,
+    /// failures allowed before ip gets locked, higher since many users can share one ip
+    pub ip_attempts: i64,
+    /// first lock, every next counted attempt doubles it (in seconds)
+    pub base_delay: i64,
+    pub max_delay: i64,
+}
+
+fn account_key(name: &str) -> String {
+    format!("user:{name}")
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{ip}")
+}
+
+impl Session {
+    /// keys this login attempt is tracked under, with number of failures allowed for each
+    fn attempt_keys(&self, name: &str) -> Vec<(String, i64, bool)> {
+        let mut keys = vec![(account_key(name), self.config.lockout.account_attempts, false)];
+        if !self.client.ip.is_empty() {
+            keys.push((ip_key(&self.client.ip), self.config.lockout.ip_attempts, true));
+        }
+        keys
+    }
+
+    /// counts the attempt before credentials are checked, so parallel attempts can't get past the limit,
+    /// every attempt over the limit locks with exponential backoff
+    pub(super) async fn begin_login_attempt(&self, name: &str) -> Result<(), LoginError> {
+        let lockout = &self.config.lockout;
+        let attempt = auth_validator::generate_secret();
+        let now = chrono::Utc::now().timestamp();
+        for (key, allowed, is_ip) in self.attempt_keys(name) {
+            let backoff = db_pool::Backoff { allowed, base_delay: lockout.base_delay, max_delay: lockout.max_delay };
+            let attempts = self.db_pool.count_login_attempt(&key, &attempt, &backoff, now).await?;
+            if attempts.attempt != attempt {
+                return Err(LoginError::Locked { retry_after: attempts.locked_until - now });
+            }
+            if attempts.locked_until > now {
+                self.activity_logger.log(Activity::LoginLocked {
+                    name: name.to_string(),
+                    until: attempts.locked_until,
+                    ip: if is_ip { Some(self.client.ip.clone()) } else { None }
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// account starts over, ip only gets back this attempt and one earlier failure so that
+    /// logging into own account doesn't reset ip limit for guessing others
+    pub(super) async fn clear_login_failures(&self, name: &str) -> Result<(), db_pool::Error> {
+        self.db_pool.clear_login_attempts(&account_key(name)).await?;
+        if !self.client.ip.is_empty() {
+            self.db_pool.forgive_login_failures(&ip_key(&self.client.ip), 2).await?;
+        }
+        Ok(())
+    }
+
+    /// admin only, `name` unlocks account and `ip` unlocks address
+    pub async fn unlock_login(&self, name: Option<&str>, ip: Option<&str>) -> Result<(), GeneralError> {
+        let auth = self.admin_auth()?;
+        if let Some(name) = name {
+            self.db_pool.clear_login_attempts(&account_key(name)).await?;
+            self.activity_logger.log(Activity::LoginUnlocked { name: name.to_string(), by: auth.name.clone() });
+        }
+        if let Some(ip) = ip {
+            self.db_pool.clear_login_attempts(&ip_key(ip)).await?;
+            self.activity_logger.log(Activity::LoginIpUnlocked { ip: ip.to_string(), by: auth.name.clone() });
+        }
+        Ok(())
+    }
+}
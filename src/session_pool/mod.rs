@@ -1,11 +1,18 @@
-use crate::{db_pool::{self, DbPool}, auth_validator::{AuthValidator, Tokens, Auth, AuthInfo}, live_channel::LiveChannel, activity_logger::ActivityLogger, logger::Logger};
+use crate::{db_pool::{self, DbPool, TokenScope}, auth_validator::{self, AuthValidator, Tokens, Auth, AuthInfo, InvalidAuthData}, live_channel::LiveChannel, activity_logger::ActivityLogger, logger::Logger, mailer::MailerShared, oidc::OidcClient, password_hasher::PasswordHasher};
 use std::sync::Arc;
 pub use roles::{RoleWrappedError, CreateRoleError, Role, RoleError};
 pub use blocks::Block;
-pub use auth::{RegisterError, LoginError, AuthMe};
+pub use live::{Subscriptions, SignalError, LiveAccess};
+pub use auth::{RegisterError, LoginError, LoginOutcome, RefreshError, AuthMe, AuthSession};
 pub use channels::Channel;
-pub use users::User;
+pub use users::{User, GetUserError, ChangeNameError, ProfileError, MAX_AVATAR_SIZE};
 pub use activity_table::ActivityTable;
+pub use password::{ChangePasswordError, ResetPasswordError};
+pub use verification::VerifyEmailError;
+pub use two_factor::{TwoFactorError, TwoFactorEnrollment};
+pub use tokens::{AccessToken, CreatedAccessToken, CreateAccessTokenError};
+pub use oidc::OidcError;
+pub use lockout::LockoutConfig;
 
 mod auth;
 mod users;
@@ -15,6 +22,15 @@ mod groups;
 mod roles;
 mod live;
 mod activity_table;
+mod password;
+mod verification;
+mod two_factor;
+mod tokens;
+mod oidc;
+mod lockout;
+
+/// seconds between `last_seen` updates of a key that is in use
+const LAST_SEEN_INTERVAL: i64 = 60;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -22,6 +38,10 @@ pub enum Error {
     Db(db_pool::Error),
     #[error("unauthorized")]
     Unauthorized,
+    #[error("email is not verified")]
+    Unverified,
+    #[error("forbidden")]
+    Forbidden,
 }
 
 impl From<db_pool::Error> for Error {
@@ -30,29 +50,109 @@ impl From<db_pool::Error> for Error {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// where frontend is hosted, used to build links that are sent by mail
+    pub public_url: String,
+    pub password_reset_lifetime: chrono::Duration,
+    pub lockout: LockoutConfig,
+    /// names of users that can use admin endpoints
+    pub admins: Vec<String>,
+}
+
+/// what the request was authenticated with
+pub enum Credentials {
+    /// access and key tokens from cookies
+    Tokens(Tokens),
+    /// personal access token from `Authorization: Bearer` header
+    Bearer(String),
+}
+
+/// information about the client a session was spawned for
+#[derive(Clone, Default)]
+pub struct Client {
+    pub device: String,
+    pub ip: String,
+}
+
 pub struct Session {
     db_pool: Arc<DbPool>,
     live_channel: Arc<LiveChannel>,
     auth_validator: Arc<AuthValidator>,
     activity_logger: Arc<ActivityLogger>,
+    mailer: MailerShared,
+    oidc: Option<Arc<OidcClient>>,
+    password_hasher: Arc<PasswordHasher>,
+    config: Arc<Config>,
     auth: Auth,
+    /// `None` for login sessions, personal access tokens are limited to their scopes
+    scopes: Option<Vec<TokenScope>>,
+    client: Client,
     logger: Arc<Logger>
 }
 
 impl Session {
-    pub fn new(tokens: &Tokens, db_pool: Arc<DbPool>, auth_validator: Arc<AuthValidator>, live_channel: Arc<LiveChannel>, activity_logger: Arc<ActivityLogger>, logger: Arc<Logger>) -> Self {
-        let auth = auth_validator.tokens_as_auth(tokens);
+    pub async fn new(credentials: &Credentials, client: Client, pool: &SessionPool) -> Self {
+        let (auth, scopes) = match credentials {
+            Credentials::Tokens(tokens) => (pool.check_key(pool.auth_validator.tokens_as_auth(tokens)).await, None),
+            Credentials::Bearer(token) => match pool.db_pool.get_access_token_by_hash(&auth_validator::hash_secret(token)).await {
+                Ok(access_token) => (Auth::Valid { info: AuthInfo {
+                    key: tokens::access_token_key(&access_token.id.unwrap().to_string()),
+                    name: access_token.owner
+                }}, Some(access_token.scopes)),
+                Err(error) => {
+                    if !matches!(error, db_pool::Error::NotFound) {
+                        pool.logger.log(error.to_string());
+                    }
+                    (Auth::Invalid(InvalidAuthData::PersonalToken), Some(Vec::new()))
+                }
+            }
+        };
         Self {
-            db_pool,
-            auth_validator,
+            db_pool: pool.db_pool.clone(),
+            auth_validator: pool.auth_validator.clone(),
             auth,
-            live_channel,
-            activity_logger,
-            logger
+            scopes,
+            client,
+            live_channel: pool.live_channel.clone(),
+            activity_logger: pool.activity_logger.clone(),
+            mailer: pool.mailer.clone(),
+            oidc: pool.oidc.clone(),
+            password_hasher: pool.password_hasher.clone(),
+            config: pool.config.clone(),
+            logger: pool.logger.clone()
         }
     }
 
+    /// for errors clients only see as internal ones
+    fn logged<E: std::fmt::Display>(&self, error: E) -> E {
+        self.logger.log(error.to_string());
+        error
+    }
+
+    /// only login sessions, personal access tokens are accepted only by `scoped_auth`
     fn auth(&self) -> Result<&AuthInfo, Error> {
+        if self.scopes.is_some() {
+            return Err(Error::Unauthorized);
+        }
+        self.auth.as_result().map_err(|_| Error::Unauthorized)
+    }
+
+    fn admin_auth(&self) -> Result<&AuthInfo, Error> {
+        let auth = self.auth()?;
+        if !self.config.admins.contains(&auth.name) {
+            return Err(Error::Forbidden);
+        }
+        Ok(auth)
+    }
+
+    /// login sessions or personal access tokens that have `scope`
+    fn scoped_auth(&self, scope: TokenScope) -> Result<&AuthInfo, Error> {
+        if let Some(scopes) = &self.scopes {
+            if !scopes.contains(&scope) {
+                return Err(Error::Unauthorized);
+            }
+        }
         self.auth.as_result().map_err(|_| Error::Unauthorized)
     }
 }
@@ -62,15 +162,64 @@ pub struct SessionPool {
     auth_validator: Arc<AuthValidator>,
     live_channel: Arc<LiveChannel>,
     activity_logger: Arc<ActivityLogger>,
+    mailer: MailerShared,
+    oidc: Option<Arc<OidcClient>>,
+    password_hasher: Arc<PasswordHasher>,
+    config: Arc<Config>,
     logger: Arc<Logger>
 }
 
 impl SessionPool {
-    pub fn new(db_pool: Arc<DbPool>, auth_validator: Arc<AuthValidator>, live_channel: Arc<LiveChannel>, activity_logger: Arc<ActivityLogger>, logger: Arc<Logger>) -> Self {
-        Self {db_pool, auth_validator, live_channel, activity_logger, logger}
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(db_pool: Arc<DbPool>, auth_validator: Arc<AuthValidator>, live_channel: Arc<LiveChannel>, activity_logger: Arc<ActivityLogger>, mailer: MailerShared, oidc: Option<Arc<OidcClient>>, password_hasher: Arc<PasswordHasher>, config: Config, logger: Arc<Logger>) -> Self {
+        Self {db_pool, auth_validator, live_channel, activity_logger, mailer, oidc, password_hasher, config: Arc::new(config), logger}
+    }
+
+    /// who the request is from without checking key token, good enough to tell clients apart,
+    /// `None` for anything that isn't signed or stored by us, so made up credentials don't get own limits
+    pub async fn identify(&self, credentials: &Credentials) -> Option<String> {
+        match credentials {
+            Credentials::Tokens(tokens) => self.auth_validator.decode_access_token(&tokens.access).ok()
+            .map(|claims| format!("user:{}", claims.name)),
+            Credentials::Bearer(token) => match self.db_pool.get_access_token_by_hash(&auth_validator::hash_secret(token)).await {
+                Ok(access_token) => access_token.id.map(|id| format!("token:{id}")),
+                Err(error) => {
+                    if !matches!(error, db_pool::Error::NotFound) {
+                        self.logger.log(error.to_string());
+                    }
+                    None
+                }
+            }
+        }
+    }
+
+    /// access tokens of revoked, expired or deleted keys stop working right away, not after their lifetime
+    async fn check_key(&self, auth: Auth) -> Auth {
+        let info = match &auth {
+            Auth::Valid { info } => info,
+            Auth::Invalid(_) => return auth
+        };
+        let now = chrono::Utc::now().timestamp();
+        match self.db_pool.get_key(&info.key).await {
+            Ok(key) if !key.revoked && key.owner == info.name && key.expires > now => {
+                if key.last_seen + LAST_SEEN_INTERVAL <= now {
+                    if let Err(error) = self.db_pool.touch_key(&key.key, now).await {
+                        self.logger.log(error.to_string());
+                    }
+                }
+                auth
+            },
+            Ok(_) => Auth::Invalid(InvalidAuthData::RevokedKey),
+            Err(error) => {
+                if !matches!(error, db_pool::Error::NotFound) {
+                    self.logger.log(error.to_string());
+                }
+                Auth::Invalid(InvalidAuthData::RevokedKey)
+            }
+        }
     }
 
-    pub fn spawn_session(&self, tokens: &Tokens) -> Session {
-        Session::new(tokens, self.db_pool.clone(), self.auth_validator.clone(), self.live_channel.clone(), self.activity_logger.clone(), self.logger.clone())
+    pub async fn spawn_session(&self, credentials: &Credentials, client: Client) -> Session {
+        Session::new(credentials, client, self).await
     }
 }
\ No newline at end of file
@@ -0,0 +1,141 @@
+use crate::{db_pool::{self, Identity}, auth_validator::InfoAsTokensError, oidc::{self, IdClaims, UsernameMapping}, activity_logger::Activity};
+use super::{Session, Error as GeneralError, auth::{LoginOutcome, NAME_CHARS, MIN_NAME_LENGTH, MAX_NAME_LENGTH}};
+
+/// how many numbered variants of mapped username are tried before giving up
+const MAX_NAME_ATTEMPTS: usize = 20;
+
+#[derive(thiserror::Error, Debug)]
+pub enum OidcError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("oidc login is not configured")]
+    Disabled,
+    #[error("login state is invalid or expired")]
+    InvalidState,
+    #[error("identity provider error: {0}")]
+    Provider(oidc::Error),
+    #[error("identity provider didn't provide email")]
+    MissingEmail,
+    #[error("email is already used by another account")]
+    EmailTaken,
+    #[error("couldn't find free username")]
+    NameUnavailable,
+    #[error("failed to convert info to tokens: {0}")]
+    InfoAsTokens(InfoAsTokensError),
+}
+impl From<db_pool::Error> for OidcError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+impl From<InfoAsTokensError> for OidcError {
+    fn from(value: InfoAsTokensError) -> Self {
+        Self::InfoAsTokens(value)
+    }
+}
+impl From<oidc::Error> for OidcError {
+    fn from(value: oidc::Error) -> Self {
+        Self::Provider(value)
+    }
+}
+
+/// where to send the user and the state token that has to come back with the callback
+pub struct OidcStart {
+    pub url: String,
+    pub state_token: String,
+}
+
+/// applies mapping rules, result may still be taken
+fn map_username(claims: &IdClaims, mapping: &UsernameMapping) -> String {
+    let mut name = claims.claim(&mapping.claim).unwrap_or(&claims.sub).to_string();
+    if mapping.strip_domain {
+        if let Some(at) = name.find('@') {
+            name.truncate(at);
+        }
+    }
+    if mapping.lowercase {
+        name = name.to_lowercase();
+    }
+    let mut name: String = name.chars()
+    .map(|char| if NAME_CHARS.contains(char) { char } else { '_' })
+    .take(MAX_NAME_LENGTH)
+    .collect();
+    while name.len() < MIN_NAME_LENGTH {
+        name.push('_');
+    }
+    name
+}
+
+impl Session {
+    pub async fn start_oidc_login(&self) -> Result<OidcStart, OidcError> {
+        let client = self.oidc.as_ref().ok_or(OidcError::Disabled)?;
+        let request = client.authorization_request().await.map_err(|error| self.logged(error))?;
+        Ok(OidcStart {
+            state_token: self.auth_validator.oidc_state_token(&request.state, &request.nonce, &request.verifier)?,
+            url: request.url
+        })
+    }
+
+    /// links identity to existing verified user by verified email or creates a new one, 2fa of linked users is still required
+    pub async fn finish_oidc_login(&self, code: &str, state: &str, state_token: &str) -> Result<LoginOutcome, OidcError> {
+        let client = self.oidc.as_ref().ok_or(OidcError::Disabled)?;
+        let claims = self.auth_validator.decode_oidc_state_token(state_token, state).map_err(|_| OidcError::InvalidState)?;
+        let id_claims = client.exchange_code(code, &claims.verifier, &claims.nonce).await.map_err(|error| self.logged(error))?;
+        let identity = Identity { issuer: id_claims.iss.clone(), subject: id_claims.sub.clone() };
+
+        let user = match self.db_pool.get_user_by_identity(&identity).await {
+            Ok(user) => user,
+            Err(db_pool::Error::NotFound) => {
+                let email = id_claims.email.as_deref().ok_or(OidcError::MissingEmail)?;
+                let email_verified = id_claims.verified_email().is_some();
+                match self.db_pool.get_user_by_email(email).await {
+                    Ok(user) if id_claims.links_to(user.verified) => {
+                        self.db_pool.link_user_identity(&user.name, &identity).await?;
+                        user
+                    },
+                    Ok(_) => return Err(OidcError::EmailTaken),
+                    Err(db_pool::Error::NotFound) => {
+                        let activity_table_id = self.db_pool.create_activity_table().await?;
+                        let name = self.create_linked_user(&map_username(&id_claims, &client.config().username), email, email_verified, &identity, &activity_table_id).await?;
+                        self.activity_logger.log(Activity::Joined { by: name.clone() });
+                        if !email_verified {
+                            if let Err(error) = self.send_verification(&name, email).await {
+                                self.logger.log(error.to_string());
+                            }
+                        }
+                        self.db_pool.get_user(&name).await?
+                    },
+                    Err(error) => return Err(error.into())
+                }
+            },
+            Err(error) => return Err(error.into())
+        };
+
+        if user.totp.as_ref().is_some_and(|totp| totp.enabled) {
+            return Ok(LoginOutcome::Challenge(self.auth_validator.challenge_token(&user.name)?));
+        }
+        Ok(LoginOutcome::Tokens(self.issue_tokens::<OidcError>(&user.name).await?))
+    }
+
+    /// named `name`, or `name` with numeric suffix if it is taken or reserved, returns the name
+    async fn create_linked_user(&self, name: &str, email: &str, verified: bool, identity: &Identity, activity_table_id: &str) -> Result<String, OidcError> {
+        for attempt in 1..=MAX_NAME_ATTEMPTS {
+            let candidate = if attempt == 1 {
+                name.to_string()
+            } else {
+                let suffix = attempt.to_string();
+                let base: String = name.chars().take(MAX_NAME_LENGTH - suffix.len()).collect();
+                format!("{base}{suffix}")
+            };
+            if self.db_pool.is_name_taken(&candidate, None).await? {
+                continue;
+            }
+            match self.db_pool.create_linked_user(&candidate, email, verified, identity, activity_table_id).await {
+                Ok(()) => return Ok(candidate),
+                Err(db_pool::Error::Duplicate) => continue, // taken meanwhile
+                Err(error) => return Err(error.into())
+            }
+        }
+        Err(OidcError::NameUnavailable)
+    }
+}
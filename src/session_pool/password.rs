@@ -0,0 +1,136 @@
+use crate::{db_pool, auth_validator, mailer::Mail, password_hasher::{self, PolicyViolation, Verification}};
+use super::{Session, Error as GeneralError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChangePasswordError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("invalid credentials")]
+    InvalidCredentials,
+    #[error("password is too short")]
+    TooShortPassword,
+    #[error("too long password")]
+    TooLongPassword,
+    #[error("password is in breached passwords list")]
+    BreachedPassword,
+    #[error("failed to hash password: {0}")]
+    Hashing(password_hasher::Error)
+}
+impl From<GeneralError> for ChangePasswordError {
+    fn from(value: GeneralError) -> Self {
+        Self::General(value)
+    }
+}
+impl From<db_pool::Error> for ChangePasswordError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+impl From<password_hasher::Error> for ChangePasswordError {
+    fn from(value: password_hasher::Error) -> Self {
+        Self::Hashing(value)
+    }
+}
+impl From<PolicyViolation> for ChangePasswordError {
+    fn from(value: PolicyViolation) -> Self {
+        match value {
+            PolicyViolation::TooShort => Self::TooShortPassword,
+            PolicyViolation::TooLong => Self::TooLongPassword,
+            PolicyViolation::Breached => Self::BreachedPassword
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResetPasswordError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("reset token is invalid, expired or already used")]
+    InvalidToken,
+    #[error("password is too short")]
+    TooShortPassword,
+    #[error("too long password")]
+    TooLongPassword,
+    #[error("password is in breached passwords list")]
+    BreachedPassword,
+    #[error("failed to hash password: {0}")]
+    Hashing(password_hasher::Error)
+}
+impl From<db_pool::Error> for ResetPasswordError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+impl From<password_hasher::Error> for ResetPasswordError {
+    fn from(value: password_hasher::Error) -> Self {
+        Self::Hashing(value)
+    }
+}
+impl From<PolicyViolation> for ResetPasswordError {
+    fn from(value: PolicyViolation) -> Self {
+        match value {
+            PolicyViolation::TooShort => Self::TooShortPassword,
+            PolicyViolation::TooLong => Self::TooLongPassword,
+            PolicyViolation::Breached => Self::BreachedPassword
+        }
+    }
+}
+
+impl Session {
+    /// other sessions get logged out, current one stays
+    pub async fn change_password(&self, old_password: &str, new_password: &str) -> Result<(), ChangePasswordError> {
+        let auth = self.auth()?;
+        let user = self.db_pool.get_user(&auth.name).await?;
+        if self.password_hasher.verify(old_password, &user.password_hash).await == Verification::Invalid {
+            return Err(ChangePasswordError::InvalidCredentials);
+        }
+        self.password_hasher.check_policy(new_password)?;
+
+        let password_hash = self.password_hasher.hash(new_password).await?;
+        self.db_pool.change_user_password(&auth.name, &password_hash).await?;
+        self.revoke_user_keys(&auth.name, Some(&auth.key)).await?;
+        Ok(())
+    }
+
+    /// succeeds even if there is no user with such email or the mail can't be sent, so that emails can't be probed
+    pub async fn request_password_reset(&self, email: &str) -> Result<(), ResetPasswordError> {
+        let user = match self.db_pool.get_user_by_email(email).await {
+            Ok(user) => user,
+            Err(db_pool::Error::NotFound) => return Ok(()),
+            Err(error) => return Err(error.into())
+        };
+
+        let token = auth_validator::generate_secret();
+        let expires = (chrono::Utc::now() + self.config.password_reset_lifetime).timestamp();
+        self.db_pool.create_password_reset(&auth_validator::hash_secret(&token), &user.name, expires).await?;
+
+        let mail = Mail {
+            to: user.email,
+            subject: "Password reset".to_string(),
+            body: format!(
+                "Hi {}, use the following link to set a new password:\n{}/reset-password?token={}\nIf you didn't request this, just ignore this email.",
+                user.name, self.config.public_url, token
+            )
+        };
+        if let Err(error) = self.mailer.send(&mail).await {
+            self.logger.log(error.to_string());
+        }
+        Ok(())
+    }
+
+    /// all sessions of the user get logged out
+    pub async fn reset_password(&self, token: &str, new_password: &str) -> Result<(), ResetPasswordError> {
+        self.password_hasher.check_policy(new_password)?;
+
+        let reset = match self.db_pool.use_password_reset(&auth_validator::hash_secret(token)).await {
+            Ok(reset) => reset,
+            Err(db_pool::Error::NotFound) => return Err(ResetPasswordError::InvalidToken),
+            Err(error) => return Err(error.into())
+        };
+
+        let password_hash = self.password_hasher.hash(new_password).await?;
+        self.db_pool.change_user_password(&reset.owner, &password_hash).await?;
+        self.revoke_user_keys(&reset.owner, None).await?;
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use ts_rs::TS;
-use crate::db_pool::{self, RolePermissions, Channel, DbPool};
+use crate::db_pool::{self, RolePermissions, Channel, DbPool, TokenScope};
 use super::{Error as GeneralError, Session};
 
 #[derive(Serialize, Deserialize, TS)]
@@ -52,7 +52,7 @@ impl Session {
     }
 
     pub async fn create_role(&self, name: &str, extends: &[String], editors: &[String], permissions: &RolePermissions) -> Result<String, CreateRoleError> {
-        let auth = self.auth()?;
+        let auth = self.scoped_auth(TokenScope::ManageRoles)?;
 
         for role_id in extends { // validates that all extending roles exist to avoid stuff like recursion to itself
             match self.db_pool.get_role(role_id).await {
@@ -65,7 +65,7 @@ impl Session {
     }
 
     pub async fn change_role(&self, id: &str, name: &str, extends: &[String], editors: &[String], permissions: RolePermissions) -> Result<(), GeneralError> {
-        let auth = self.auth()?;
+        let auth = self.scoped_auth(TokenScope::ManageRoles)?;
         let role = self.db_pool.get_role(id).await?;
 
         let editors = if role.owner == auth.name {
@@ -76,7 +76,25 @@ impl Session {
             return Err(GeneralError::Unauthorized)
         };
         
-        Ok(self.db_pool.change_role(id, name, extends, &editors, permissions).await?)
+        self.db_pool.change_role(id, name, extends, &editors, permissions).await?;
+        match self.role_channel_ids(id).await {
+            Ok(channel_ids) => self.live_channel.recheck(channel_ids),
+            Err(error) => self.logger.log(error.to_string()) // role is changed, subscribers just keep their grants
+        }
+        Ok(())
+    }
+
+    /// channels where the role applies, directly or through roles that extend it
+    async fn role_channel_ids(&self, id: &str) -> Result<Vec<String>, db_pool::Error> {
+        let mut role_ids = vec![id.to_owned()];
+        let mut added = role_ids.clone();
+        while !added.is_empty() {
+            added = self.db_pool.get_extending_role_ids(&added).await?.into_iter()
+            .filter(|id| !role_ids.contains(id))
+            .collect();
+            role_ids.extend(added.iter().cloned());
+        }
+        self.db_pool.get_channel_ids_with_roles(&role_ids).await
     }
 }
 
@@ -113,6 +131,9 @@ pub async fn resolve_user_role(db_pool: Arc<DbPool>, channel_id: &str, user_name
 -> Result<(db_pool::Role, Channel), RoleWrappedError>
 {
     let channel = db_pool.get_channel(channel_id).await?;
+    if channel.require_verified && !db_pool.get_user(user_name).await?.verified {
+        return Err(GeneralError::Unverified.into());
+    }
 
     let mut role_id = &channel.default_role;
     for role in &channel.roles {
@@ -204,6 +225,9 @@ impl<'a> RolePermissionValidator<'a> {
     pub fn can_live(&self) -> bool {
         catch_vec_intersection(self.labels, &self.permissions.live)
     }
+    pub fn can_signal(&self) -> bool {
+        catch_vec_intersection(self.labels, &self.permissions.signal)
+    }
     pub fn can_set_labels(&self) -> bool {
         self.permissions.set_labels
     }
@@ -0,0 +1,89 @@
+use serde::Serialize;
+use ts_rs::TS;
+use crate::{db_pool::{self, TokenScope}, auth_validator, live_channel::CloseReason};
+use super::{Session, Error as GeneralError};
+
+const TOKEN_PREFIX: &str = "chane_";
+
+/// auth key of sessions authenticated with personal access token, so that its live peers can be kicked on revocation
+pub(super) fn access_token_key(id: &str) -> String {
+    format!("access-token:{id}")
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct AccessToken {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires: Option<i64>,
+    pub created: i64,
+}
+impl From<db_pool::AccessToken> for AccessToken {
+    fn from(value: db_pool::AccessToken) -> Self {
+        Self {
+            id: value.id.unwrap().to_string(),
+            name: value.name,
+            scopes: value.scopes,
+            expires: value.expires,
+            created: value.created
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CreateAccessTokenError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("lifetime must be positive and not too long")]
+    InvalidLifetime,
+}
+impl From<GeneralError> for CreateAccessTokenError {
+    fn from(value: GeneralError) -> Self {
+        Self::General(value)
+    }
+}
+impl From<db_pool::Error> for CreateAccessTokenError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+
+/// returned only once, when the token is created
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct CreatedAccessToken {
+    pub id: String,
+    pub token: String,
+}
+
+impl Session {
+    pub async fn get_access_tokens(&self) -> Result<Vec<AccessToken>, GeneralError> {
+        let auth = self.auth()?;
+        let (tokens, errors) = self.db_pool.get_user_access_tokens(&auth.name).await?;
+        for error in errors {
+            self.logger.log(error.to_string());
+        }
+        Ok(tokens.into_iter().map(AccessToken::from).collect())
+    }
+
+    /// `lifetime` is in seconds, token never expires without it
+    pub async fn create_access_token(&self, name: &str, scopes: &[TokenScope], lifetime: Option<i64>) -> Result<CreatedAccessToken, CreateAccessTokenError> {
+        let auth = self.auth()?;
+        let expires = match lifetime {
+            Some(lifetime) if lifetime <= 0 => return Err(CreateAccessTokenError::InvalidLifetime),
+            Some(lifetime) => Some(chrono::Utc::now().timestamp().checked_add(lifetime).ok_or(CreateAccessTokenError::InvalidLifetime)?),
+            None => None
+        };
+        let token = format!("{TOKEN_PREFIX}{}{}", auth_validator::generate_secret(), auth_validator::generate_secret());
+        let id = self.db_pool.create_access_token(name, &auth.name, &auth_validator::hash_secret(&token), scopes, expires).await?;
+        Ok(CreatedAccessToken {id, token})
+    }
+
+    pub async fn revoke_access_token(&self, id: &str) -> Result<(), GeneralError> {
+        let auth = self.auth()?;
+        self.db_pool.delete_access_token(&auth.name, id).await?;
+        self.live_channel.disconnect_key(&access_token_key(id), CloseReason::Revoked);
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use rand::RngCore;
+use serde::Serialize;
+use totp_rs::{TOTP, Algorithm, Secret};
+use ts_rs::TS;
+use crate::{db_pool, auth_validator, password_hasher::Verification};
+use super::{Session, Error as GeneralError};
+
+const ISSUER: &str = "chane";
+const SECRET_LENGTH: usize = 20;
+const RECOVERY_CODES_COUNT: usize = 10;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TwoFactorError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("two factor authentication is already enabled")]
+    AlreadyEnabled,
+    #[error("two factor authentication is not enrolled")]
+    NotEnrolled,
+    #[error("invalid code")]
+    InvalidCode,
+    #[error("invalid credentials")]
+    InvalidCredentials,
+    #[error("totp: {0}")]
+    Totp(String)
+}
+impl From<GeneralError> for TwoFactorError {
+    fn from(value: GeneralError) -> Self {
+        Self::General(value)
+    }
+}
+impl From<db_pool::Error> for TwoFactorError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct TwoFactorEnrollment {
+    /// otpauth:// uri, usually shown as a QR code
+    pub provisioning_uri: String,
+    /// base32 secret for manual entry
+    pub secret: String,
+}
+
+impl Session {
+    fn build_totp(&self, secret: &str, name: &str) -> Result<TOTP, TwoFactorError> {
+        Secret::Encoded(secret.to_string()).to_bytes().map_err(|error| error.to_string())
+        .and_then(|secret| TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, Some(ISSUER.to_string()), name.to_string()).map_err(|error| error.to_string()))
+        .map_err(|error| TwoFactorError::Totp(self.logged(error)))
+    }
+
+    /// time step `code` belongs to, current one or one within skew
+    fn code_step(&self, secret: &str, name: &str, code: &str) -> Result<Option<u64>, TwoFactorError> {
+        let mut totp = self.build_totp(secret, name)?;
+        let skew = totp.skew as u64;
+        totp.skew = 0;
+        let current = chrono::Utc::now().timestamp() as u64 / totp.step;
+        Ok((current.saturating_sub(skew)..=current + skew).find(|step| totp.check(code, step * totp.step)))
+    }
+
+    /// checks totp code (each one works once), then falls back to recovery codes (which get used up)
+    pub(super) async fn check_second_factor(&self, user: &db_pool::User, code: &str) -> Result<bool, TwoFactorError> {
+        let Some(totp) = user.totp.as_ref().filter(|totp| totp.enabled) else {
+            return Err(TwoFactorError::NotEnrolled);
+        };
+        if let Some(step) = self.code_step(&totp.secret, &user.name, code)? {
+            return match self.db_pool.use_user_totp_step(&user.name, step).await {
+                Ok(()) => Ok(true),
+                Err(db_pool::Error::NotFound) => Ok(false), // replayed
+                Err(error) => Err(error.into())
+            };
+        }
+        match self.db_pool.use_user_recovery_code(&user.name, &auth_validator::hash_secret(code)).await {
+            Ok(()) => Ok(true),
+            Err(db_pool::Error::NotFound) => Ok(false),
+            Err(error) => Err(error.into())
+        }
+    }
+
+    /// generates a new secret, 2fa isn't enabled until `confirm_two_factor` is called with a valid code
+    pub async fn enroll_two_factor(&self) -> Result<TwoFactorEnrollment, TwoFactorError> {
+        let auth = self.auth()?;
+        let user = self.db_pool.get_user(&auth.name).await?;
+        if user.totp.as_ref().is_some_and(|totp| totp.enabled) {
+            return Err(TwoFactorError::AlreadyEnabled);
+        }
+
+        let mut secret = vec![0u8; SECRET_LENGTH];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let secret = Secret::Raw(secret).to_encoded().to_string();
+        let totp = self.build_totp(&secret, &user.name)?;
+
+        self.db_pool.set_user_totp(&user.name, &Some(db_pool::Totp {
+            secret: secret.clone(),
+            enabled: false,
+            recovery_codes: Vec::new(),
+            last_step: 0
+        })).await?;
+
+        Ok(TwoFactorEnrollment {
+            provisioning_uri: totp.get_url(),
+            secret
+        })
+    }
+
+    /// enables 2fa and returns recovery codes, they are only stored hashed so this is the only time they're visible
+    pub async fn confirm_two_factor(&self, code: &str) -> Result<Vec<String>, TwoFactorError> {
+        let auth = self.auth()?;
+        let user = self.db_pool.get_user(&auth.name).await?;
+        let Some(mut totp) = user.totp else {
+            return Err(TwoFactorError::NotEnrolled);
+        };
+        if totp.enabled {
+            return Err(TwoFactorError::AlreadyEnabled);
+        }
+        let Some(step) = self.code_step(&totp.secret, &user.name, code)? else {
+            return Err(TwoFactorError::InvalidCode);
+        };
+
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODES_COUNT).map(|_| auth_validator::generate_secret()).collect();
+        totp.enabled = true;
+        totp.last_step = step; // confirming code can't be used to log in
+        totp.recovery_codes = recovery_codes.iter().map(|code| auth_validator::hash_secret(code)).collect();
+        self.db_pool.set_user_totp(&user.name, &Some(totp)).await?;
+        Ok(recovery_codes)
+    }
+
+    pub async fn disable_two_factor(&self, password: &str, code: &str) -> Result<(), TwoFactorError> {
+        let auth = self.auth()?;
+        let user = self.db_pool.get_user(&auth.name).await?;
+        if self.password_hasher.verify(password, &user.password_hash).await == Verification::Invalid {
+            return Err(TwoFactorError::InvalidCredentials);
+        }
+        if !self.check_second_factor(&user, code).await? {
+            return Err(TwoFactorError::InvalidCode);
+        }
+        self.db_pool.set_user_totp(&user.name, &None).await?;
+        Ok(())
+    }
+}
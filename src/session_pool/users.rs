@@ -1,24 +1,292 @@
 use serde::{Serialize, Deserialize};
 use ts_rs::TS;
-use crate::db_pool;
-use super::{Session, Error as GeneralError};
+use crate::{db_pool::{self, Profile, ProfileAvatar, ProfileVisibility, Visibility}, auth_validator::{Tokens, InfoAsTokensError}, activity_logger::Activity, live_channel::CloseReason};
+use super::{Session, Error as GeneralError, auth::{NAME_CHARS, MIN_NAME_LENGTH, MAX_NAME_LENGTH}, tokens::access_token_key};
 
+const MAX_DISPLAY_NAME_LENGTH: usize = 40;
+const MAX_BIO_LENGTH: usize = 500;
+const MAX_LINKS: usize = 5;
+const MAX_LINK_LENGTH: usize = 200;
+/// in bytes, same as default payload limit of the upload endpoint
+pub const MAX_AVATAR_SIZE: usize = 256 * 1024;
+
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UserCounts {
+    blocks: u64,
+    channels: u64,
+    roles: u64,
+}
+
+/// profile fields the viewer isn't allowed to see are left out
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct User {
     name: String,
+    display_name: Option<String>,
+    bio: Option<String>,
+    avatar: Option<ProfileAvatar>,
+    links: Option<Vec<String>>,
+    pinned_block: Option<String>,
+    /// only for the user themself
+    visibility: Option<ProfileVisibility>,
+    counts: UserCounts,
 }
 
-impl From<db_pool::User> for User {
-    fn from(user: db_pool::User) -> Self {
-        Self {
-            name: user.name
-        }
+#[derive(thiserror::Error, Debug)]
+pub enum ProfileError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("display name is empty or too long")]
+    BadDisplayNameLength,
+    #[error("bio is too long")]
+    BioTooLong,
+    #[error("too many links")]
+    TooManyLinks,
+    #[error("link is not a valid http url: {0}")]
+    InvalidLink(String),
+    #[error("block doesn't exist or isn't owned by the user: {0}")]
+    InvalidBlock(String),
+    #[error("avatar image has to be uploaded first")]
+    InvalidAvatarImage,
+    #[error("image is not png, jpeg, gif or webp")]
+    UnsupportedImage,
+    #[error("image is too large")]
+    ImageTooLarge,
+}
+impl From<GeneralError> for ProfileError {
+    fn from(value: GeneralError) -> Self {
+        Self::General(value)
+    }
+}
+impl From<db_pool::Error> for ProfileError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+
+/// mime type by magic bytes, content type sent by client isn't trusted
+fn image_mime(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn is_valid_link(link: &str) -> bool {
+    link.len() <= MAX_LINK_LENGTH && match reqwest::Url::parse(link) {
+        Ok(url) => matches!(url.scheme(), "http" | "https") && url.host().is_some(),
+        Err(_) => false
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetUserError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("user was renamed to {0}")]
+    Renamed(String),
+}
+impl From<db_pool::Error> for GetUserError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChangeNameError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("invalid username characters")]
+    InvaildNameChars,
+    #[error("username is too long or too short")]
+    BadNameLength,
+    #[error("username already taken")]
+    NameTaken,
+    #[error("failed to convert info to tokens: {0}")]
+    InfoAsTokens(InfoAsTokensError),
+}
+impl From<GeneralError> for ChangeNameError {
+    fn from(value: GeneralError) -> Self {
+        Self::General(value)
+    }
+}
+impl From<db_pool::Error> for ChangeNameError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+impl From<InfoAsTokensError> for ChangeNameError {
+    fn from(value: InfoAsTokensError) -> Self {
+        Self::InfoAsTokens(value)
     }
 }
 
 impl Session {
-    pub async fn get_user(&self, name: &str) -> Result<User, GeneralError> {
-        Ok(User::from(self.db_pool.get_user(name).await?))
+    fn can_see(&self, owner: &str, visibility: Visibility) -> bool {
+        match (visibility, self.auth.as_result()) {
+            (Visibility::Public, _) => true,
+            (Visibility::Users, auth) => auth.is_ok(),
+            (Visibility::Private, auth) => auth.is_ok_and(|auth| auth.name == owner)
+        }
+    }
+
+    /// `Renamed` with the current name when `name` is a former name of someone
+    pub async fn get_user(&self, name: &str) -> Result<User, GetUserError> {
+        let user = match self.db_pool.get_user(name).await {
+            Ok(user) => user,
+            Err(db_pool::Error::NotFound) => match self.db_pool.get_user_by_former_name(name).await {
+                Ok(user) => return Err(GetUserError::Renamed(user.name)),
+                Err(error) => return Err(error.into())
+            },
+            Err(error) => return Err(error.into())
+        };
+        let counts = UserCounts {
+            blocks: self.db_pool.count_user_blocks(&user.name).await?,
+            channels: self.db_pool.count_user_channels(&user.name).await?,
+            roles: self.db_pool.count_user_roles(&user.name).await?
+        };
+        let profile = user.profile;
+        let visibility = &profile.visibility;
+        let is_self = self.auth.as_result().is_ok_and(|auth| auth.name == user.name);
+        Ok(User {
+            display_name: profile.display_name.filter(|_| self.can_see(&user.name, visibility.display_name)),
+            bio: Some(profile.bio).filter(|_| self.can_see(&user.name, visibility.bio)),
+            avatar: profile.avatar.filter(|_| self.can_see(&user.name, visibility.avatar)),
+            links: Some(profile.links).filter(|_| self.can_see(&user.name, visibility.links)),
+            pinned_block: profile.pinned_block.filter(|_| self.can_see(&user.name, visibility.pinned_block)),
+            visibility: Some(profile.visibility.clone()).filter(|_| is_self),
+            name: user.name,
+            counts
+        })
+    }
+
+    async fn check_owned_block(&self, id: &str, owner: &str) -> Result<(), ProfileError> {
+        match self.db_pool.get_block(id).await {
+            Ok(block) if block.owner == owner => Ok(()),
+            Ok(_) | Err(db_pool::Error::NotFound) | Err(db_pool::Error::InvalidObjectId(_)) => Err(ProfileError::InvalidBlock(id.to_string())),
+            Err(error) => Err(error.into())
+        }
     }
-}
\ No newline at end of file
+
+    /// replaces whole profile, image avatar can only be the one that is already set by `upload_avatar`
+    pub async fn update_profile(&self, mut profile: Profile) -> Result<(), ProfileError> {
+        let auth = self.auth()?;
+        if let Some(display_name) = &profile.display_name {
+            let length = display_name.trim().chars().count();
+            if length == 0 || length > MAX_DISPLAY_NAME_LENGTH {
+                return Err(ProfileError::BadDisplayNameLength);
+            }
+        }
+        profile.display_name = profile.display_name.map(|display_name| display_name.trim().to_string());
+        if profile.bio.chars().count() > MAX_BIO_LENGTH {
+            return Err(ProfileError::BioTooLong);
+        }
+        if profile.links.len() > MAX_LINKS {
+            return Err(ProfileError::TooManyLinks);
+        }
+        if let Some(link) = profile.links.iter().find(|link| !is_valid_link(link)) {
+            return Err(ProfileError::InvalidLink(link.clone()));
+        }
+        if let Some(block_id) = &profile.pinned_block {
+            self.check_owned_block(block_id, &auth.name).await?;
+        }
+
+        let current = self.db_pool.get_user(&auth.name).await?.profile;
+        let current_image = match current.avatar {
+            Some(ProfileAvatar::Image(id)) => Some(id),
+            _ => None
+        };
+        match &profile.avatar {
+            Some(ProfileAvatar::Image(id)) if current_image.as_ref() != Some(id) => return Err(ProfileError::InvalidAvatarImage),
+            Some(ProfileAvatar::Block(id)) => self.check_owned_block(id, &auth.name).await?,
+            _ => {}
+        }
+
+        self.db_pool.set_user_profile(&auth.name, &profile).await?;
+        if let Some(id) = current_image {
+            if !matches!(&profile.avatar, Some(ProfileAvatar::Image(new_id)) if new_id == &id) {
+                self.db_pool.delete_avatar(&id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// stores image and makes it the avatar, previously uploaded image is deleted
+    pub async fn upload_avatar(&self, data: Vec<u8>) -> Result<String, ProfileError> {
+        let auth = self.auth()?;
+        if data.len() > MAX_AVATAR_SIZE {
+            return Err(ProfileError::ImageTooLarge);
+        }
+        let mime = image_mime(&data).ok_or(ProfileError::UnsupportedImage)?;
+
+        let mut profile = self.db_pool.get_user(&auth.name).await?.profile;
+        let id = self.db_pool.create_avatar(mime, data).await?;
+        let previous = profile.avatar.replace(ProfileAvatar::Image(id.clone()));
+        self.db_pool.set_user_profile(&auth.name, &profile).await?;
+        if let Some(ProfileAvatar::Image(previous)) = previous {
+            self.db_pool.delete_avatar(&previous).await?;
+        }
+        Ok(id)
+    }
+
+    /// mime type and image data, only while it's the avatar of someone the viewer can see it on
+    pub async fn get_avatar(&self, id: &str) -> Result<(String, Vec<u8>), GeneralError> {
+        let owner = self.db_pool.get_user_by_avatar_image(id).await?;
+        if !self.can_see(&owner.name, owner.profile.visibility.avatar) {
+            return Err(GeneralError::Db(db_pool::Error::NotFound));
+        }
+        let avatar = self.db_pool.get_avatar(id).await?;
+        Ok((avatar.mime, avatar.data.bytes))
+    }
+
+    /// renames user and rewrites every reference to the old name, returns tokens with the new name for the current login,
+    /// other logins get the new name on their next refresh
+    pub async fn change_name(&self, new_name: &str) -> Result<Tokens, ChangeNameError> {
+        let auth = self.auth()?;
+        if new_name.chars().any(|char| !NAME_CHARS.contains(char)) {
+            return Err(ChangeNameError::InvaildNameChars);
+        }
+        if new_name.len() < MIN_NAME_LENGTH || new_name.len() > MAX_NAME_LENGTH {
+            return Err(ChangeNameError::BadNameLength);
+        }
+        if new_name == auth.name || self.db_pool.is_name_taken(new_name, Some(&auth.name)).await? {
+            return Err(ChangeNameError::NameTaken);
+        }
+
+        let name = auth.name.as_str();
+        match self.db_pool.rename_user_everywhere(name, new_name).await {
+            Ok(()) => {},
+            Err(db_pool::Error::Duplicate) => return Err(ChangeNameError::NameTaken), // taken after the check above
+            Err(error) => return Err(error.into())
+        }
+        self.activity_logger.log(Activity::Renamed { name: new_name.to_string(), from: name.to_string() });
+
+        let tokens = self.issue_tokens::<ChangeNameError>(new_name).await?;
+        self.revoke_key(&auth.key).await?; // access token of this login still carries the old name
+        self.disconnect_user_peers(new_name).await?;
+        Ok(tokens)
+    }
+
+    /// live peers know the user by name, so they have to reconnect after rename
+    async fn disconnect_user_peers(&self, name: &str) -> Result<(), db_pool::Error> {
+        let (keys, key_errors) = self.db_pool.get_user_keys(name).await?;
+        let (access_tokens, token_errors) = self.db_pool.get_user_access_tokens(name).await?;
+        for error in key_errors.into_iter().chain(token_errors) {
+            self.logger.log(error.to_string());
+        }
+        let auth_keys = keys.into_iter().map(|key| key.key)
+        .chain(access_tokens.into_iter().filter_map(|token| token.id).map(|id| access_token_key(&id.to_string())));
+        for key in auth_keys {
+            self.live_channel.disconnect_key(&key, CloseReason::Renamed);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{db_pool, auth_validator::InfoAsTokensError, mailer::{self, Mail}};
+use super::{Session, Error as GeneralError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum VerifyEmailError {
+    #[error("general error: {0}")]
+    General(GeneralError),
+    #[error("verification token is invalid or expired")]
+    InvalidToken,
+    #[error("email is already verified")]
+    AlreadyVerified,
+    #[error("failed to create verification token: {0}")]
+    InfoAsTokens(InfoAsTokensError),
+    #[error("failed to send mail: {0}")]
+    Mail(mailer::Error)
+}
+impl From<GeneralError> for VerifyEmailError {
+    fn from(value: GeneralError) -> Self {
+        Self::General(value)
+    }
+}
+impl From<db_pool::Error> for VerifyEmailError {
+    fn from(value: db_pool::Error) -> Self {
+        Self::General(GeneralError::Db(value))
+    }
+}
+impl From<InfoAsTokensError> for VerifyEmailError {
+    fn from(value: InfoAsTokensError) -> Self {
+        Self::InfoAsTokens(value)
+    }
+}
+impl From<mailer::Error> for VerifyEmailError {
+    fn from(value: mailer::Error) -> Self {
+        Self::Mail(value)
+    }
+}
+
+impl Session {
+    pub(super) async fn send_verification(&self, name: &str, email: &str) -> Result<(), VerifyEmailError> {
+        let token = self.auth_validator.verification_token(name, email)?;
+        self.mailer.send(&Mail {
+            to: email.to_string(),
+            subject: "Confirm your email".to_string(),
+            body: format!(
+                "Hi {}, use the following link to confirm your email:\n{}/verify-email?token={}",
+                name, self.config.public_url, token
+            )
+        }).await.map_err(|error| self.logged(error))?;
+        Ok(())
+    }
+
+    pub async fn resend_verification(&self) -> Result<(), VerifyEmailError> {
+        let auth = self.auth()?;
+        let user = self.db_pool.get_user(&auth.name).await?;
+        if user.verified {
+            return Err(VerifyEmailError::AlreadyVerified);
+        }
+        self.send_verification(&user.name, &user.email).await
+    }
+
+    pub async fn verify_email(&self, token: &str) -> Result<(), VerifyEmailError> {
+        let claims = self.auth_validator.decode_verification_token(token).map_err(|_| VerifyEmailError::InvalidToken)?;
+        match self.db_pool.verify_user_email(&claims.name, &claims.email).await {
+            Ok(()) => Ok(()),
+            Err(db_pool::Error::NotFound) => Err(VerifyEmailError::InvalidToken), // email was changed or user was cleaned up
+            Err(error) => Err(error.into())
+        }
+    }
+}